@@ -5,6 +5,7 @@
 mod sega;
 
 use argh::FromArgs;
+use std::io::Write;
 
 #[cfg(target_os = "emscripten")]
 pub mod emscripten;
@@ -36,9 +37,227 @@ struct RustSegaArgs {
     #[argh(switch, short = 'l')]
     list_drivers: bool,
 
+    /// master audio volume, as a percentage (0-100)
+    #[argh(option, default = "100")]
+    volume: u8,
+
+    /// mute a sound channel (0-2 tone, 3 noise), may be repeated
+    #[argh(option)]
+    mute: Vec<usize>,
+
+    /// solo a sound channel (0-2 tone, 3 noise), may be repeated
+    #[argh(option)]
+    solo: Vec<usize>,
+
+    /// record the audio output to a wav file (also used by the record hotkey)
+    #[argh(option)]
+    record_audio: Option<String>,
+
+    /// record the display and audio to a y4m video file, with a wav file
+    /// alongside it (also used by the record video hotkey)
+    #[argh(option)]
+    record_video: Option<String>,
+
+    /// record the sound chip writes to a vgm file
+    #[argh(option)]
+    record_vgm: Option<String>,
+
+    /// play a vgm file (instead of running a cartridge)
+    #[argh(option)]
+    play_vgm: Option<String>,
+
+    /// loop the vgm file played with '--play-vgm' (if it has a loop point)
+    #[argh(switch)]
+    loop_vgm: bool,
+
+    /// config file to use (instead of the one in the user config directory)
+    #[argh(option)]
+    config: Option<String>,
+
+    /// how opposing directions (up+down, left+right) are handled: allow, last
+    /// (pressed) or neutral
+    #[argh(option)]
+    opposing_directions: Option<String>,
+
+    /// speed multiplier used while the fast forward key is held (default 4)
+    #[argh(option)]
+    fast_forward_speed: Option<f64>,
+
+    /// number of frames the turbo (autofire) buttons are pressed, then
+    /// released, for (default 2)
+    #[argh(option)]
+    turbo_rate: Option<u64>,
+
+    /// number of frames between rewind snapshots (default 4)
+    #[argh(option)]
+    rewind_interval: Option<u64>,
+
+    /// memory used for the rewind snapshots, in MiB (default 32, 0 disables
+    /// rewind)
+    #[argh(option)]
+    rewind_buffer: Option<usize>,
+
+    /// save a screenshot once the given frame is reached
+    #[argh(option)]
+    screenshot_at_frame: Option<u64>,
+
+    /// also save a copy of each screenshot scaled by this factor
+    #[argh(option)]
+    screenshot_scale: Option<u32>,
+
+    /// display filter: nearest, nearest2x, nearest3x, nearest4x, scale2x,
+    /// scale3x or hq2x
+    #[argh(option)]
+    filter: Option<String>,
+
+    /// darken alternate lines by this percentage (0-100, 0 for none)
+    #[argh(option)]
+    scanlines: Option<u8>,
+
+    /// ntsc video signal filter: rgb (none), svideo, composite or rf
+    #[argh(option)]
+    ntsc: Option<String>,
+
+    /// pixel aspect ratio: square, 4:3 (whole display) or 8:7
+    #[argh(option)]
+    aspect_ratio: Option<String>,
+
+    /// show the overscan border (in the border colour) around the display
+    #[argh(switch)]
+    overscan: bool,
+
+    /// only scale the display by whole numbers
+    #[argh(switch)]
+    integer_scale: bool,
+
+    /// initial window size, as a multiple of the display size (default 3)
+    #[argh(option)]
+    window_scale: Option<u16>,
+
+    /// record the joystick input to a movie file (from power on)
+    #[argh(option)]
+    record_movie: Option<String>,
+
+    /// play back the joystick input from a movie file
+    #[argh(option)]
+    play_movie: Option<String>,
+
+    /// peripheral on controller port A: joypad, paddle or sportspad
+    #[argh(option)]
+    port_a: Option<String>,
+
+    /// peripheral on controller port B: joypad, paddle or sportspad
+    #[argh(option)]
+    port_b: Option<String>,
+
+    /// log each instruction to a trace file
+    #[argh(option)]
+    trace: Option<String>,
+
+    /// trace fields, comma separated: cycles, pc, disasm, regs, flags
+    /// (default all)
+    #[argh(option)]
+    trace_fields: Option<String>,
+
+    /// start tracing once this address (hex) is reached
+    #[argh(option)]
+    trace_start_pc: Option<String>,
+
+    /// stop tracing once this address (hex) is reached
+    #[argh(option)]
+    trace_stop_pc: Option<String>,
+
+    /// only trace these frames (first-last)
+    #[argh(option)]
+    trace_frames: Option<String>,
+
+    /// only keep the last N traced instructions, written out on a panic or an
+    /// unimplemented opcode
+    #[argh(option)]
+    trace_ring: Option<usize>,
+
+    /// stop in the debugger (on the terminal) at this address (hex), may be
+    /// repeated
+    #[argh(option)]
+    break_at: Vec<String>,
+
+    /// what to do on an unimplemented opcode: ask, debug, nop (skip it) or
+    /// stop (default ask)
+    #[argh(option)]
+    on_cpu_error: Option<String>,
+
     /// name of cartridge to run
     #[argh(positional, default = "default_cart()")]
     cartridge_name: String,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Disasm(DisasmArgs),
+    Zex(ZexArgs),
+}
+
+#[derive(FromArgs)]
+/// Disassemble a 16K bank of a cartridge ROM.
+#[argh(subcommand, name = "disasm")]
+struct DisasmArgs {
+    /// rom file to disassemble
+    #[argh(positional)]
+    rom: String,
+
+    /// bank to disassemble (default 0), listed at the address it's normally
+    /// paged in at
+    #[argh(option, default = "0")]
+    bank: usize,
+}
+
+fn disassemble_rom(args: &DisasmArgs) {
+    match std::fs::read(&args.rom) {
+        Ok(rom) => match sega::cpu::disassembler::Disassembler::bank_listing(&rom, args.bank) {
+            Ok(listing) => print!("{}", listing),
+            Err(e) => println!("{}", e),
+        },
+        Err(e) => println!("Error reading {}: {}", args.rom, e),
+    }
+}
+
+#[derive(FromArgs)]
+/// Run a CP/M Z80 instruction exerciser (zexdoc.com/zexall.com).
+#[argh(subcommand, name = "zex")]
+struct ZexArgs {
+    /// exerciser to run
+    #[argh(positional)]
+    program: String,
+}
+
+fn run_zex(args: &ZexArgs) {
+    let program = match std::fs::read(&args.program) {
+        Ok(program) => program,
+        Err(e) => {
+            println!("Error reading {}: {}", args.program, e);
+            return;
+        }
+    };
+    let mut runner = sega::cpu::zex::ZexRunner::new(&program);
+    let result = runner.run(|text| {
+        print!("{}", text);
+        std::io::stdout().flush().ok();
+    });
+    if let Err(e) = result {
+        println!("\n{}", e);
+    }
+    println!(
+        "\n{} groups passed, {} failed",
+        runner.passed(),
+        runner.failed()
+    );
+    for group in runner.groups.iter().filter(|group| !group.passed) {
+        println!("    Failed: {}", group.name);
+    }
 }
 
 fn full_description_string() -> String {
@@ -62,6 +281,15 @@ fn full_description_string() -> String {
 fn main() {
     let args: RustSegaArgs = argh::from_env();
 
+    if let Some(Command::Disasm(disasm_args)) = &args.command {
+        disassemble_rom(disasm_args);
+        return;
+    }
+    if let Some(Command::Zex(zex_args)) = &args.command {
+        run_zex(zex_args);
+        return;
+    }
+
     if args.list_drivers {
         println!("{}", full_description_string());
     }
@@ -72,6 +300,118 @@ fn main() {
         &args.cartridge_name,
         args.fullscreen,
     );
+    sega_machine.load_config(args.config.as_deref());
+    if let Some(opposing_directions) = &args.opposing_directions {
+        sega_machine.set_opposing_directions(opposing_directions);
+    }
+    if let Some(fast_forward_speed) = args.fast_forward_speed {
+        sega_machine.set_fast_forward_speed(fast_forward_speed);
+    }
+    if let Some(turbo_rate) = args.turbo_rate {
+        sega_machine.set_turbo_rate(turbo_rate);
+    }
+    if args.rewind_interval.is_some() || args.rewind_buffer.is_some() {
+        let (interval, buffer_mb) = sega_machine.get_rewind();
+        sega_machine.set_rewind(
+            args.rewind_interval.unwrap_or(interval),
+            args.rewind_buffer.unwrap_or(buffer_mb),
+        );
+    }
+    if let Some(scale) = args.screenshot_scale {
+        sega_machine.set_screenshot_scale(scale);
+    }
+    if let Some(frame) = args.screenshot_at_frame {
+        sega_machine.set_screenshot_at_frame(frame);
+    }
+    if let Some(filter) = &args.filter {
+        sega_machine.set_filter(filter);
+    }
+    if let Some(scanlines) = args.scanlines {
+        sega_machine.set_scanlines(scanlines);
+    }
+    if let Some(ntsc) = &args.ntsc {
+        sega_machine.set_ntsc_mode(ntsc);
+    }
+    if let Some(aspect_ratio) = &args.aspect_ratio {
+        sega_machine.set_aspect_ratio(aspect_ratio);
+    }
+    if args.overscan {
+        sega_machine.set_overscan(true);
+    }
+    if args.integer_scale {
+        sega_machine.set_integer_scale(true);
+    }
+    if let Some(window_scale) = args.window_scale {
+        sega_machine.set_window_scale(window_scale);
+    }
+    if let Some(port_a) = &args.port_a {
+        sega_machine.set_peripheral(0, port_a);
+    }
+    if let Some(port_b) = &args.port_b {
+        sega_machine.set_peripheral(1, port_b);
+    }
+    for address in &args.break_at {
+        match sega::debugger::Debugger::parse_value(address) {
+            Some(address) => sega_machine.add_breakpoint(address),
+            None => println!("Invalid breakpoint address: {}", address),
+        }
+    }
+    if let Some(name) = &args.on_cpu_error {
+        sega_machine.set_on_cpu_error(name);
+    }
+    if let Some(trace) = &args.trace {
+        sega_machine.start_trace(trace, args.trace_ring);
+        if let Some(fields) = &args.trace_fields {
+            sega_machine.set_trace_fields(fields);
+        }
+        if let Some(frames) = &args.trace_frames {
+            sega_machine.set_trace_frames(frames);
+        }
+        let parse_pc = |pc: &String| {
+            let address = sega::debugger::Debugger::parse_value(pc);
+            if address.is_none() {
+                println!("Invalid trace address: {}", pc);
+            }
+            address
+        };
+        if let Some(pc) = args.trace_start_pc.as_ref().and_then(parse_pc) {
+            sega_machine.set_trace_start_pc(pc);
+        }
+        if let Some(pc) = args.trace_stop_pc.as_ref().and_then(parse_pc) {
+            sega_machine.set_trace_stop_pc(pc);
+        }
+    }
+    sega_machine.set_master_volume(args.volume as f32 / 100.0);
+    for channel in &args.mute {
+        sega_machine.set_channel_muted(*channel, true);
+    }
+    for channel in &args.solo {
+        sega_machine.set_channel_solo(*channel, true);
+    }
+    if let Some(record_audio) = &args.record_audio {
+        sega_machine.set_record_audio_filename(record_audio);
+        sega_machine.start_audio_recording(record_audio);
+    }
+    if let Some(record_vgm) = &args.record_vgm {
+        sega_machine.start_vgm_recording(record_vgm);
+    }
+    if let Some(record_video) = &args.record_video {
+        sega_machine.set_record_video_filename(record_video);
+        sega_machine.start_video_recording(record_video);
+    }
+
+    if let Some(record_movie) = &args.record_movie {
+        sega_machine.start_movie_recording(record_movie);
+    } else if let Some(play_movie) = &args.play_movie {
+        sega_machine.start_movie_playback(play_movie);
+    }
+
+    if let Some(play_vgm) = &args.play_vgm {
+        sega_machine.play_vgm(play_vgm, args.loop_vgm);
+        sega_machine.stop_audio_recording();
+        println!("Finished.");
+        return;
+    }
 
     #[cfg(target_os = "emscripten")]
     {
@@ -107,6 +447,11 @@ fn main() {
                 break;
             }
         }
+        sega_machine.stop_audio_recording();
+        sega_machine.stop_video_recording();
+        sega_machine.stop_vgm_recording();
+        sega_machine.stop_movie();
+        sega_machine.stop_trace();
     }
 
     println!("Finished.");
@@ -1,2 +1,4 @@
 pub mod sound;
 pub mod soundchannel;
+pub mod vgm;
+pub mod wav;
@@ -1,4 +1,7 @@
+use super::super::clocks;
+use super::super::snapshot;
 use super::soundchannel;
+use super::vgm;
 use sdl2::audio;
 
 pub type SoundQueueType = audio::AudioQueue<soundchannel::PlaybackType>;
@@ -11,7 +14,7 @@ impl SDLUtility {
     const AUDIO_SAMPLE_SIZE: u16 = 1024; // 'Desired' sample size, too small and SDL buffer doesn't stay filled (pops/crackles).
     const FRACTION_FILL: f32 = 0.05; // TODO: FUDGE FACTOR.  Don't completely fill, samples a removed 1 at a time, don't fill them immediately.
 
-    const MONO_STERO_FLAG: u8 = 2; // TODO: Make this configurable 1 - mono, 2 - stereo
+    pub const MONO_STERO_FLAG: u8 = 2; // TODO: Make this configurable 1 - mono, 2 - stereo
 
     pub fn get_audio_queue(sdl_context: &mut sdl2::Sdl) -> Option<Box<SoundQueueType>> {
         let audio_subsystem = sdl_context.audio().unwrap();
@@ -43,13 +46,29 @@ impl SDLUtility {
     where
         F: FnMut(u32) -> Vec<soundchannel::PlaybackType>,
     {
-        assert!(audio_queue.size() <= SDLUtility::TARGET_QUEUE_LENGTH);
-        let fill_size = ((SDLUtility::TARGET_QUEUE_LENGTH - audio_queue.size()) as f32
+        // The queue size is reported in bytes, the target length is in samples.
+        let queued_samples =
+            audio_queue.size() / std::mem::size_of::<soundchannel::PlaybackType>() as u32;
+        assert!(queued_samples <= SDLUtility::TARGET_QUEUE_LENGTH);
+        let fill_size = ((SDLUtility::TARGET_QUEUE_LENGTH - queued_samples) as f32
             * SDLUtility::FRACTION_FILL) as u32;
         // If 'stereo' the buffer is twice as large, so just as for half as much.
         let sound_buffer = get_additional_buffer(fill_size / (SDLUtility::MONO_STERO_FLAG as u32));
         audio_queue.queue_audio(&sound_buffer).unwrap();
     }
+
+    // Queue already generated samples (eg, while recording video), any that
+    // don't fit in the target queue length are dropped.
+    pub fn queue_audio_samples(
+        audio_queue: &mut SoundQueueType,
+        samples: &[soundchannel::PlaybackType],
+    ) {
+        let queued_samples =
+            audio_queue.size() / std::mem::size_of::<soundchannel::PlaybackType>() as u32;
+        let space = SDLUtility::TARGET_QUEUE_LENGTH.saturating_sub(queued_samples) as usize;
+        let length = samples.len().min(space);
+        audio_queue.queue_audio(&samples[..length]).unwrap();
+    }
 }
 
 pub enum ChannelTypeEnum {
@@ -111,50 +130,186 @@ impl LatchSoundReg {
     }
 }
 
+// Single pole high-pass filter, removes the DC offset from the (unsigned)
+// channel outputs so the mixed signal is centred around zero.
+#[derive(Default)]
+pub struct DcFilter {
+    previous_input: soundchannel::WaveType,
+    previous_output: soundchannel::WaveType,
+}
+
+impl DcFilter {
+    const POLE: soundchannel::WaveType = 0.995; // ~35Hz cut-off at 44.1kHz
+
+    pub fn filter(&mut self, input: soundchannel::WaveType) -> soundchannel::WaveType {
+        let output = input - self.previous_input + DcFilter::POLE * self.previous_output;
+        self.previous_input = input;
+        self.previous_output = output;
+        output
+    }
+}
+
 pub struct Sound {
     channels: Vec<Box<dyn soundchannel::SoundGenerator>>,
 
     latched_reg: LatchSoundReg,
+
+    // Per channel (tone 0-2, noise 3) mute/solo state, indexed as 'channels'.
+    muted: Vec<bool>,
+    soloed: Vec<bool>,
+
+    master_volume: soundchannel::WaveType,
+    dc_filter: DcFilter,
+
+    vgm_recorder: Option<vgm::VgmFileWriter>,
 }
 
 impl Sound {
     //    const SAMPLERATE:u32 = 32050;
-    const SAMPLERATE: u32 = 44100;
+    pub const SAMPLERATE: u32 = 44100;
     const CHANNELS: u8 = 4;
-    const BITS: u8 = 8;
+    const BITS: u8 = 16;
+
+    // Scale the mixed channels so all channels at full volume reach the limit of the playback type.
+    const MIXER_SCALE: soundchannel::WaveType = soundchannel::PlaybackType::MAX
+        as soundchannel::WaveType
+        / Sound::CHANNELS as soundchannel::WaveType;
+    pub const DEFAULT_MASTER_VOLUME: soundchannel::WaveType = 1.0;
+
     pub fn new() -> Self {
+        let channels: Vec<Box<dyn soundchannel::SoundGenerator>> = vec![
+            Box::new(soundchannel::ToneSoundChannel::new()),
+            Box::new(soundchannel::ToneSoundChannel::new()),
+            Box::new(soundchannel::ToneSoundChannel::new()),
+            Box::new(soundchannel::NoiseSoundChannel::new()),
+        ];
+        let num_channels = channels.len();
         Self {
-            channels: vec![
-                Box::new(soundchannel::ToneSoundChannel::new()),
-                Box::new(soundchannel::ToneSoundChannel::new()),
-                Box::new(soundchannel::ToneSoundChannel::new()),
-                Box::new(soundchannel::NoiseSoundChannel::new()),
-            ],
+            channels,
             latched_reg: LatchSoundReg::default(),
+            muted: vec![false; num_channels],
+            soloed: vec![false; num_channels],
+            master_volume: Sound::DEFAULT_MASTER_VOLUME,
+            dc_filter: DcFilter::default(),
+            vgm_recorder: None,
+        }
+    }
+
+    // Master volume, 0.0 (silent) to 1.0 (full volume).
+    pub fn set_master_volume(&mut self, volume: soundchannel::WaveType) {
+        self.master_volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn get_master_volume(&self) -> soundchannel::WaveType {
+        self.master_volume
+    }
+
+    pub fn num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    // Mute/solo calls with an invalid channel are reported and ignored.
+    fn valid_channel(&self, channel: usize) -> bool {
+        if channel < self.channels.len() {
+            true
+        } else {
+            println!(
+                "Invalid sound channel {}, expected 0-{}.",
+                channel,
+                self.channels.len() - 1
+            );
+            false
+        }
+    }
+
+    pub fn set_channel_muted(&mut self, channel: usize, muted: bool) {
+        if self.valid_channel(channel) {
+            self.muted[channel] = muted;
+        }
+    }
+
+    pub fn toggle_channel_muted(&mut self, channel: usize) {
+        if self.valid_channel(channel) {
+            self.muted[channel] = !self.muted[channel];
+        }
+    }
+
+    pub fn is_channel_muted(&self, channel: usize) -> bool {
+        self.muted.get(channel).copied().unwrap_or(false)
+    }
+
+    // When any channel is soloed, only the soloed (and not muted) channels are heard.
+    pub fn set_channel_solo(&mut self, channel: usize, solo: bool) {
+        if self.valid_channel(channel) {
+            self.soloed[channel] = solo;
+        }
+    }
+
+    pub fn toggle_channel_solo(&mut self, channel: usize) {
+        if self.valid_channel(channel) {
+            self.soloed[channel] = !self.soloed[channel];
         }
     }
 
+    pub fn is_channel_soloed(&self, channel: usize) -> bool {
+        self.soloed.get(channel).copied().unwrap_or(false)
+    }
+
+    pub fn is_channel_audible(&self, channel: usize) -> bool {
+        let any_soloed = self.soloed.iter().any(|soloed| *soloed);
+        channel < self.channels.len()
+            && !self.muted[channel]
+            && (!any_soloed || self.soloed[channel])
+    }
+
+    pub fn print_channel_status(&self) {
+        println!(
+            "Sound channels: {}",
+            (0..self.num_channels())
+                .map(|channel| format!(
+                    "{}:{}",
+                    channel,
+                    if self.is_channel_audible(channel) {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                ))
+                .collect::<Vec<String>>()
+                .join(" ")
+        );
+    }
+
     pub fn get_next_audio_chunk(&mut self, length: u32) -> Vec<soundchannel::PlaybackType> {
-        let mut stream = Vec::with_capacity((2 * length) as usize);
+        let mut stream =
+            Vec::with_capacity((length * (SDLUtility::MONO_STERO_FLAG as u32)) as usize);
         if length > 0 {
-            for i in 0..(length * (SDLUtility::MONO_STERO_FLAG as u32)) {
-                stream.push(0x0); // Neutral volume
+            let mut mix = vec![0.0; length as usize];
+
+            let audible: Vec<bool> = (0..self.channels.len())
+                .map(|channel| self.is_channel_audible(channel))
+                .collect();
+            for (channel, audible) in self.channels.iter_mut().zip(audible) {
+                // Always generate the wave, so channels stay in phase when unmuted.
+                let channel_wave = channel.get_wave(length, Sound::SAMPLERATE);
+                if !audible {
+                    continue;
+                }
+                for (mixed, sample) in mix.iter_mut().zip(channel_wave.iter()) {
+                    *mixed += sample;
+                }
             }
 
-            for c in 0..Sound::CHANNELS {
-                let channel_wave = self.channels[c as usize].get_wave(length, Sound::SAMPLERATE);
+            for mixed in mix {
+                let sample =
+                    (self.dc_filter.filter(mixed) * self.master_volume * Sound::MIXER_SCALE).clamp(
+                        soundchannel::PlaybackType::MIN as soundchannel::WaveType,
+                        soundchannel::PlaybackType::MAX as soundchannel::WaveType,
+                    ) as soundchannel::PlaybackType;
 
-                if c % SDLUtility::MONO_STERO_FLAG == 0 {
-                    for i in 0..length {
-                        stream[(i * (SDLUtility::MONO_STERO_FLAG as u32)) as usize] +=
-                            channel_wave[i as usize];
-                    }
-                } else {
-                    // This will only be called if 'MONO_STEREO_FLAG' is set to '2'
-                    for i in 0..length {
-                        stream[(i * (SDLUtility::MONO_STERO_FLAG as u32) + 1) as usize] +=
-                            channel_wave[i as usize];
-                    }
+                // The master system is mono, so the same sample goes to all outputs.
+                for _ in 0..SDLUtility::MONO_STERO_FLAG {
+                    stream.push(sample);
                 }
             }
         }
@@ -162,7 +317,50 @@ impl Sound {
         stream
     }
 
-    pub fn write_port(&mut self, data: u8) {
+    // Log all writes made (via 'write_port') to a VGM file, from 'clock' onwards.
+    pub fn start_vgm_recording(
+        &mut self,
+        filename: &str,
+        gd3_tag: vgm::Gd3Tag,
+        cpu_clock_hz: u32,
+        clock: &clocks::Clock,
+    ) {
+        self.stop_vgm_recording(clock);
+        match vgm::VgmWriter::create(filename, gd3_tag, cpu_clock_hz, clock.cycles) {
+            Ok(vgm_recorder) => {
+                println!("Recording VGM to: {}", filename);
+                self.vgm_recorder = Some(vgm_recorder);
+            }
+            Err(e) => {
+                println!("Error while creating VGM recording {}. {}", filename, e);
+            }
+        }
+    }
+
+    pub fn stop_vgm_recording(&mut self, clock: &clocks::Clock) {
+        if let Some(mut vgm_recorder) = self.vgm_recorder.take() {
+            match vgm_recorder.finalise_at(clock.cycles) {
+                Ok(_) => println!("VGM recording stopped."),
+                Err(e) => println!("Error while finalising VGM recording. {}", e),
+            }
+        }
+    }
+
+    pub fn is_vgm_recording(&self) -> bool {
+        self.vgm_recorder.is_some()
+    }
+
+    pub fn write_port(&mut self, clock: &clocks::Clock, data: u8) {
+        if let Some(vgm_recorder) = self.vgm_recorder.as_mut() {
+            if let Err(e) = vgm_recorder.write_psg(clock.cycles, data) {
+                println!("Error while recording VGM, recording stopped. {}", e);
+                self.vgm_recorder = None;
+            }
+        }
+        self.write_data(data);
+    }
+
+    pub fn write_data(&mut self, data: u8) {
         // Dispatch the data to perform the specified audio function (frequency,
         // channel frequency, volume).
 
@@ -186,3 +384,108 @@ impl Sound {
         }
     }
 }
+
+// Only the sound chip state is saved, mute/solo and volume are user settings.
+impl snapshot::Snapshot for Sound {
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        writer.write_u8(self.latched_reg.data);
+        for channel in &self.channels {
+            channel.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        self.latched_reg.set_reg_value(reader.read_u8()?);
+        for channel in self.channels.iter_mut() {
+            channel.load_state(reader)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::audio::sound;
+    use crate::sega::audio::soundchannel;
+
+    #[test]
+    fn test_volume_table_steps() {
+        // Each volume step is a 2dB attenuation, 0xF is silent.
+        for volume_reg in 0..0xE {
+            let ratio = soundchannel::SoundChannel::get_volume(volume_reg + 1)
+                / soundchannel::SoundChannel::get_volume(volume_reg);
+            assert!((20.0 * ratio.log10() + 2.0).abs() < 0.01);
+        }
+        assert_eq!(soundchannel::SoundChannel::get_volume(0xF), 0.0);
+    }
+
+    #[test]
+    fn test_dc_filter_removes_offset() {
+        let mut dc_filter = sound::DcFilter::default();
+        let mut output = 0.0;
+        for _ in 0..5000 {
+            output = dc_filter.filter(1.0);
+        }
+        assert!(output.abs() < 0.001);
+    }
+
+    #[test]
+    fn test_master_volume() {
+        let mut sound = sound::Sound::new();
+
+        // Channel 0, full volume, then a tone.
+        sound.write_data(0x90);
+        sound.write_data(0x80 | 0x0F);
+        sound.write_data(0x01);
+
+        sound.set_master_volume(0.0);
+        assert!(sound.get_next_audio_chunk(100).iter().all(|x| *x == 0));
+
+        sound.set_master_volume(2.0);
+        assert_eq!(sound.get_master_volume(), 1.0);
+        assert!(sound.get_next_audio_chunk(100).iter().any(|x| *x != 0));
+    }
+
+    #[test]
+    fn test_mute_and_solo() {
+        let mut sound = sound::Sound::new();
+        assert!((0..4).all(|channel| sound.is_channel_audible(channel)));
+
+        sound.toggle_channel_muted(3);
+        assert!(!sound.is_channel_audible(3));
+        assert!(sound.is_channel_audible(0));
+
+        sound.set_channel_solo(1, true);
+        assert!(sound.is_channel_audible(1));
+        assert!(!sound.is_channel_audible(0));
+        assert!(!sound.is_channel_audible(2));
+
+        // Muting wins over solo.
+        sound.set_channel_solo(3, true);
+        assert!(!sound.is_channel_audible(3));
+
+        sound.set_channel_solo(1, false);
+        sound.set_channel_solo(3, false);
+        sound.toggle_channel_muted(3);
+        assert!((0..4).all(|channel| sound.is_channel_audible(channel)));
+
+        // Out of range channels are ignored.
+        sound.set_channel_muted(7, true);
+        assert!(!sound.is_channel_audible(7));
+    }
+
+    #[test]
+    fn test_muted_channel_is_silent() {
+        let mut sound = sound::Sound::new();
+
+        // Channel 0, full volume, then a tone.
+        sound.write_data(0x90);
+        sound.write_data(0x80 | 0x0F);
+        sound.write_data(0x01);
+
+        sound.set_channel_muted(0, true);
+        assert!(sound.get_next_audio_chunk(100).iter().all(|x| *x == 0));
+        sound.set_channel_muted(0, false);
+        assert!(sound.get_next_audio_chunk(100).iter().any(|x| *x != 0));
+    }
+}
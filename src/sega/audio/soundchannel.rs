@@ -1,31 +1,39 @@
-pub trait SoundGenerator {
+use super::super::snapshot;
+
+pub trait SoundGenerator: snapshot::Snapshot {
     // Data may be from latched or data
     fn set_volume(&mut self, data: u8);
     // Data is from both 'latched' and 'data', may represent noise or tone (depending on channel).
     // It's only updated on a 'data' write.
     fn set_tone(&mut self, latched_data: u8, data: u8);
-    fn get_wave(&mut self, length: u32, sample_rate: u32) -> Vec<PlaybackType>;
+    fn get_wave(&mut self, length: u32, sample_rate: u32) -> Vec<WaveType>;
 }
 
 pub struct SoundChannel {}
 
-pub type PlaybackType = u8; // Only 8-bit playback is currently supported.
+pub type PlaybackType = i16; // Signed 16-bit samples are sent to the audio device.
+pub type WaveType = f32; // Channels generate (unsigned) samples in the range [0.0, 1.0], mixed as floats.
+
 impl SoundChannel {
     const FREQMULTIPLIER: u32 = 125000;
 
     pub const MAX_VOLUME_MASK: u8 = 0xF;
-    pub const MAX_VOLUME: PlaybackType = 0xFF;
 
-    const NUM_CHANNELS: u8 = 4;
+    // SN76489 attenuation, each step of the volume register is 2dB
+    // (10^(-2n/20)), with 0xF being 'off'.
+    const VOLUME_TABLE: [WaveType; 16] = [
+        1.0, 0.794_328, 0.630_957, 0.501_187, 0.398_107, 0.316_228, 0.251_189, 0.199_526,
+        0.158_489, 0.125_893, 0.1, 0.079_433, 0.063_096, 0.050_119, 0.039_811, 0.0,
+    ];
 
     pub fn get_hertz(frequency: u16) -> u32 {
         SoundChannel::FREQMULTIPLIER / (frequency as u32 + 1)
     }
 
-    pub fn get_volume(volume_reg: u8) -> PlaybackType {
-        // Min volume when volume_reg = 0xF
+    pub fn get_volume(volume_reg: u8) -> WaveType {
         // Max volume when volume_reg = 0x0
-        ((SoundChannel::MAX_VOLUME_MASK ^ volume_reg) << 4) / SoundChannel::NUM_CHANNELS
+        // Min volume (silent) when volume_reg = 0xF
+        SoundChannel::VOLUME_TABLE[(volume_reg & SoundChannel::MAX_VOLUME_MASK) as usize]
     }
 }
 
@@ -57,7 +65,7 @@ impl ToneSoundChannel {
             frequency_counter: 0, // counter remaining before toggle.
         }
     }
-    pub fn get_wave(&mut self, length: u32, sample_rate: u32) -> Vec<PlaybackType> {
+    pub fn get_wave(&mut self, length: u32, sample_rate: u32) -> Vec<WaveType> {
         // Generate the 'wave' output buffer.
         // First copy what's left of the current 'play buffer', update to the
         // new buffer, if it's changed and copy that until the wave buffer has
@@ -76,7 +84,7 @@ impl ToneSoundChannel {
             let volume = if self.current_level {
                 SoundChannel::get_volume(self.volume_reg)
             } else {
-                0x0
+                0.0
             };
             wave.push(volume);
         }
@@ -123,7 +131,7 @@ impl NoiseSoundChannel {
     }
 
     // Outputs  '1' or '0' on each 'clock'
-    pub fn get_shiff_register_output(&mut self, noise: bool, sample_rate: u32) -> PlaybackType {
+    pub fn get_shiff_register_output(&mut self, noise: bool, sample_rate: u32) -> WaveType {
         let output = self.noise_shift_register & 0x1;
 
         if 0 == self.noise_shift_register {
@@ -145,13 +153,49 @@ impl NoiseSoundChannel {
         }
 
         if output == 0x1 {
-            0
+            0.0
         } else {
             SoundChannel::get_volume(self.volume_reg)
         }
     }
 }
 
+impl snapshot::Snapshot for ToneSoundChannel {
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        writer.write_u16(self.freq_reg);
+        writer.write_u8(self.volume_reg);
+        writer.write_bool(self.current_level);
+        writer.write_u32(self.frequency_counter);
+    }
+
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        self.freq_reg = reader.read_u16()?;
+        self.volume_reg = reader.read_u8()?;
+        self.current_level = reader.read_bool()?;
+        self.frequency_counter = reader.read_u32()?;
+        Ok(())
+    }
+}
+
+impl snapshot::Snapshot for NoiseSoundChannel {
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        writer.write_bool(self.noise_period_select);
+        writer.write_u16(self.noise_shift_register);
+        writer.write_u16(self.freq_reg);
+        writer.write_u8(self.volume_reg);
+        writer.write_u32(self.frequency_counter);
+    }
+
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        self.noise_period_select = reader.read_bool()?;
+        self.noise_shift_register = reader.read_u16()?;
+        self.freq_reg = reader.read_u16()?;
+        self.volume_reg = reader.read_u8()?;
+        self.frequency_counter = reader.read_u32()?;
+        Ok(())
+    }
+}
+
 impl SoundGenerator for ToneSoundChannel {
     // Data may be from latched or data
     fn set_volume(&mut self, data: u8) {
@@ -168,7 +212,7 @@ impl SoundGenerator for ToneSoundChannel {
         }
     }
 
-    fn get_wave(&mut self, length: u32, sample_rate: u32) -> Vec<PlaybackType> {
+    fn get_wave(&mut self, length: u32, sample_rate: u32) -> Vec<WaveType> {
         self.get_wave(length, sample_rate)
     }
 }
@@ -184,8 +228,8 @@ impl SoundGenerator for NoiseSoundChannel {
         self.set_data(data);
     }
 
-    fn get_wave(&mut self, length: u32, sample_rate: u32) -> Vec<PlaybackType> {
-        let mut channel_wave = Vec::<PlaybackType>::new();
+    fn get_wave(&mut self, length: u32, sample_rate: u32) -> Vec<WaveType> {
+        let mut channel_wave = Vec::<WaveType>::new();
         for i in 0..length {
             channel_wave
                 .push(self.get_shiff_register_output(self.noise_period_select, sample_rate));
@@ -0,0 +1,542 @@
+use super::sound;
+use super::soundchannel;
+use std::fs;
+use std::io;
+use std::io::{Seek, SeekFrom, Write};
+
+pub struct Constants {}
+
+impl Constants {
+    pub const VGM_SAMPLE_RATE: u32 = 44100; // VGM waits are always in 44.1kHz samples.
+    pub const SN76489_CLOCK_HZ: u32 = 3579545;
+    pub const SN76489_FEEDBACK: u16 = 0x0009; // Sega VDP PSG noise feedback pattern.
+    pub const SN76489_SHIFT_WIDTH: u8 = 16;
+    pub const VERSION: u32 = 0x150;
+    pub const HEADER_SIZE: u32 = 0x40;
+
+    // Header offsets.
+    pub const EOF_OFFSET: usize = 0x04;
+    pub const VERSION_OFFSET: usize = 0x08;
+    pub const SN76489_CLOCK_OFFSET: usize = 0x0C;
+    pub const GD3_OFFSET: usize = 0x14;
+    pub const TOTAL_SAMPLES_OFFSET: usize = 0x18;
+    pub const LOOP_OFFSET: usize = 0x1C;
+    pub const RATE_OFFSET: usize = 0x24;
+    pub const SN76489_FEEDBACK_OFFSET: usize = 0x28;
+    pub const SN76489_SHIFT_WIDTH_OFFSET: usize = 0x2A;
+    pub const DATA_OFFSET: usize = 0x34;
+
+    // Commands.
+    pub const CMD_GG_STEREO: u8 = 0x4F;
+    pub const CMD_PSG_WRITE: u8 = 0x50;
+    pub const CMD_WAIT: u8 = 0x61;
+    pub const CMD_WAIT_NTSC_FRAME: u8 = 0x62;
+    pub const CMD_WAIT_PAL_FRAME: u8 = 0x63;
+    pub const CMD_END: u8 = 0x66;
+    pub const CMD_DATA_BLOCK: u8 = 0x67;
+    pub const CMD_WAIT_SHORT: u8 = 0x70; // 0x7n, wait n+1 samples
+
+    pub const NTSC_FRAME_SAMPLES: u32 = 735;
+    pub const PAL_FRAME_SAMPLES: u32 = 882;
+}
+
+// Values used to fill in the GD3 (track information) tag.
+#[derive(Default)]
+pub struct Gd3Tag {
+    pub track_name: String,
+    pub game_name: String,
+    pub system_name: String,
+    pub author: String,
+    pub release_date: String,
+    pub ripper: String,
+    pub notes: String,
+}
+
+impl Gd3Tag {
+    const VERSION: u32 = 0x100;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // English and Japanese names, the Japanese names are left empty.
+        let strings = [
+            &self.track_name,
+            "",
+            &self.game_name,
+            "",
+            &self.system_name,
+            "",
+            &self.author,
+            "",
+            &self.release_date,
+            &self.ripper,
+            &self.notes,
+        ];
+        let mut data = Vec::new();
+        for string in strings {
+            for unit in string.encode_utf16().chain(std::iter::once(0)) {
+                data.extend_from_slice(&unit.to_le_bytes());
+            }
+        }
+
+        let mut tag = Vec::new();
+        tag.extend_from_slice(b"Gd3 ");
+        tag.extend_from_slice(&Gd3Tag::VERSION.to_le_bytes());
+        tag.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        tag.extend_from_slice(&data);
+        tag
+    }
+}
+
+pub type VgmFileWriter = VgmWriter<io::BufWriter<fs::File>>;
+
+// Logs PSG writes, with the waits between them, as a VGM stream.
+// Waits are calculated from the CPU clock, so they are cycle accurate (to
+// the nearest VGM sample).  The header is completed in 'finalise'.
+pub struct VgmWriter<W: Write + Seek> {
+    writer: Option<W>,
+    gd3_tag: Gd3Tag,
+    cpu_clock_hz: u32,
+    start_cycles: u64,
+    written_samples: u64, // Samples already covered by wait commands.
+    pending_cycles: u64,  // Clock cycles of the last write.
+    data_bytes: u32,
+}
+
+impl VgmFileWriter {
+    pub fn create(
+        filename: &str,
+        gd3_tag: Gd3Tag,
+        cpu_clock_hz: u32,
+        start_cycles: u64,
+    ) -> io::Result<Self> {
+        let file = fs::File::create(filename)?;
+        VgmWriter::new(
+            io::BufWriter::new(file),
+            gd3_tag,
+            cpu_clock_hz,
+            start_cycles,
+        )
+    }
+}
+
+impl<W: Write + Seek> VgmWriter<W> {
+    pub fn new(
+        mut writer: W,
+        gd3_tag: Gd3Tag,
+        cpu_clock_hz: u32,
+        start_cycles: u64,
+    ) -> io::Result<Self> {
+        // Write a header with placeholders (completed in 'finalise').
+        writer.write_all(&VgmWriter::<W>::header(0, 0, 0))?;
+        Ok(Self {
+            writer: Some(writer),
+            gd3_tag,
+            cpu_clock_hz,
+            start_cycles,
+            written_samples: 0,
+            pending_cycles: start_cycles,
+            data_bytes: 0,
+        })
+    }
+
+    fn header(data_bytes: u32, gd3_length: u32, total_samples: u32) -> Vec<u8> {
+        let mut header = vec![0; Constants::HEADER_SIZE as usize];
+        let mut set_u32 = |offset: usize, value: u32| {
+            header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        // Offsets are relative to the position of the offset field.
+        let gd3_position = Constants::HEADER_SIZE + data_bytes;
+        set_u32(
+            Constants::EOF_OFFSET,
+            gd3_position + gd3_length - Constants::EOF_OFFSET as u32,
+        );
+        set_u32(Constants::VERSION_OFFSET, Constants::VERSION);
+        set_u32(Constants::SN76489_CLOCK_OFFSET, Constants::SN76489_CLOCK_HZ);
+        if gd3_length > 0 {
+            set_u32(
+                Constants::GD3_OFFSET,
+                gd3_position - Constants::GD3_OFFSET as u32,
+            );
+        }
+        set_u32(Constants::TOTAL_SAMPLES_OFFSET, total_samples);
+        set_u32(
+            Constants::DATA_OFFSET,
+            Constants::HEADER_SIZE - Constants::DATA_OFFSET as u32,
+        );
+        header[0..4].copy_from_slice(b"Vgm ");
+        header[Constants::RATE_OFFSET..Constants::RATE_OFFSET + 4]
+            .copy_from_slice(&60u32.to_le_bytes());
+        header[Constants::SN76489_FEEDBACK_OFFSET..Constants::SN76489_FEEDBACK_OFFSET + 2]
+            .copy_from_slice(&Constants::SN76489_FEEDBACK.to_le_bytes());
+        header[Constants::SN76489_SHIFT_WIDTH_OFFSET] = Constants::SN76489_SHIFT_WIDTH;
+        header
+    }
+
+    fn cycles_to_samples(&self, cycles: u64) -> u64 {
+        cycles.saturating_sub(self.start_cycles) * Constants::VGM_SAMPLE_RATE as u64
+            / self.cpu_clock_hz as u64
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write_all(bytes)?;
+            self.data_bytes = self.data_bytes.saturating_add(bytes.len() as u32);
+        }
+        Ok(())
+    }
+
+    // Write the wait commands needed to bring the stream up to 'cycles'.
+    fn write_wait(&mut self, cycles: u64) -> io::Result<()> {
+        let target_samples = self.cycles_to_samples(cycles);
+        while self.written_samples < target_samples {
+            let remaining = target_samples - self.written_samples;
+            let (command, samples): (Vec<u8>, u64) =
+                if remaining == Constants::NTSC_FRAME_SAMPLES as u64 {
+                    (vec![Constants::CMD_WAIT_NTSC_FRAME], remaining)
+                } else if remaining == Constants::PAL_FRAME_SAMPLES as u64 {
+                    (vec![Constants::CMD_WAIT_PAL_FRAME], remaining)
+                } else if remaining <= 16 {
+                    (
+                        vec![Constants::CMD_WAIT_SHORT | (remaining - 1) as u8],
+                        remaining,
+                    )
+                } else {
+                    let samples = remaining.min(u16::MAX as u64);
+                    let mut command = vec![Constants::CMD_WAIT];
+                    command.extend_from_slice(&(samples as u16).to_le_bytes());
+                    (command, samples)
+                };
+            self.write_bytes(&command)?;
+            self.written_samples += samples;
+        }
+        self.pending_cycles = cycles;
+        Ok(())
+    }
+
+    pub fn write_psg(&mut self, cycles: u64, data: u8) -> io::Result<()> {
+        self.write_wait(cycles)?;
+        self.write_bytes(&[Constants::CMD_PSG_WRITE, data])
+    }
+
+    // End the stream at 'cycles', write the GD3 tag, and complete the header.
+    pub fn finalise_at(&mut self, cycles: u64) -> io::Result<Option<W>> {
+        if self.writer.is_none() {
+            return Ok(None);
+        }
+        self.write_wait(cycles)?;
+        self.write_bytes(&[Constants::CMD_END])?;
+
+        let gd3 = self.gd3_tag.to_bytes();
+        let header = VgmWriter::<W>::header(
+            self.data_bytes,
+            gd3.len() as u32,
+            self.written_samples.min(u32::MAX as u64) as u32,
+        );
+        let mut writer = self.writer.take().expect("Checked above");
+        writer.write_all(&gd3)?;
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&header)?;
+        writer.seek(SeekFrom::End(0))?;
+        writer.flush()?;
+        Ok(Some(writer))
+    }
+
+    // End the stream at the last write.
+    pub fn finalise(&mut self) -> io::Result<Option<W>> {
+        self.finalise_at(self.pending_cycles)
+    }
+}
+
+impl<W: Write + Seek> Drop for VgmWriter<W> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finalise() {
+            println!("Error while finalising vgm file. {}", e);
+        }
+    }
+}
+
+// Plays the PSG commands of a VGM file through the 'Sound' chip.
+pub struct VgmPlayer {
+    data: Vec<u8>,
+    position: usize,
+    loop_position: Option<usize>,
+    looping: bool,
+    pending_samples: u32,    // Samples to generate before the next command.
+    played_since_loop: bool, // A loop without any waits would never end.
+    finished: bool,
+}
+
+impl VgmPlayer {
+    pub fn load(filename: &str) -> Result<Self, String> {
+        let data = fs::read(filename).map_err(|e| format!("Unable to read {}. {}", filename, e))?;
+        VgmPlayer::new(data)
+    }
+
+    pub fn new(data: Vec<u8>) -> Result<Self, String> {
+        let read_u32 = |offset: usize| -> Option<u32> {
+            data.get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+
+        if data.len() < Constants::HEADER_SIZE as usize || &data[0..4] != b"Vgm " {
+            return Err("Not a VGM file (compressed .vgz files need to be unzipped).".to_string());
+        }
+        let version = read_u32(Constants::VERSION_OFFSET).unwrap_or(0);
+        let relative_offset = |offset: usize| -> Option<usize> {
+            match read_u32(offset) {
+                Some(0) | None => None,
+                Some(value) => Some(offset + value as usize),
+            }
+        };
+        // Before version 1.50 the data always started at 0x40.
+        let position = if version < 0x150 {
+            Constants::HEADER_SIZE as usize
+        } else {
+            relative_offset(Constants::DATA_OFFSET).unwrap_or(Constants::HEADER_SIZE as usize)
+        };
+        if position >= data.len() {
+            return Err("VGM data offset is beyond the end of the file.".to_string());
+        }
+        let loop_position = relative_offset(Constants::LOOP_OFFSET);
+
+        Ok(Self {
+            data,
+            position,
+            loop_position,
+            looping: false,
+            pending_samples: 0,
+            played_since_loop: true,
+            finished: false,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // Start again from the loop point at the end of the file (if there is one).
+    pub fn set_loop(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let value = self.data.get(self.position).copied();
+        self.position += 1;
+        value
+    }
+
+    fn skip(&mut self, count: usize) {
+        self.position += count;
+    }
+
+    // Run commands until there is a wait (or the end).
+    fn run_commands(&mut self, sound: &mut sound::Sound) {
+        while self.pending_samples == 0 && !self.finished {
+            let command = match self.next_byte() {
+                Some(command) => command,
+                None => {
+                    self.finished = true;
+                    break;
+                }
+            };
+            match command {
+                Constants::CMD_PSG_WRITE => {
+                    if let Some(data) = self.next_byte() {
+                        sound.write_data(data);
+                    }
+                }
+                Constants::CMD_GG_STEREO => self.skip(1),
+                Constants::CMD_WAIT => {
+                    let low = self.next_byte().unwrap_or(0) as u32;
+                    let high = self.next_byte().unwrap_or(0) as u32;
+                    self.pending_samples = low | (high << 8);
+                }
+                Constants::CMD_WAIT_NTSC_FRAME => {
+                    self.pending_samples = Constants::NTSC_FRAME_SAMPLES
+                }
+                Constants::CMD_WAIT_PAL_FRAME => {
+                    self.pending_samples = Constants::PAL_FRAME_SAMPLES
+                }
+                Constants::CMD_END => match self.loop_position {
+                    Some(loop_position) if self.looping && self.played_since_loop => {
+                        self.position = loop_position;
+                        self.played_since_loop = false;
+                    }
+                    _ => self.finished = true,
+                },
+                Constants::CMD_DATA_BLOCK => {
+                    // 0x67 0x66 tt ss ss ss ss (data)
+                    self.skip(2);
+                    let mut size = [0; 4];
+                    for byte in size.iter_mut() {
+                        *byte = self.next_byte().unwrap_or(0);
+                    }
+                    self.skip(u32::from_le_bytes(size) as usize);
+                }
+                0x70..=0x7F => self.pending_samples = (command & 0xF) as u32 + 1,
+                // YM2612 DAC write + wait (the write is ignored, no FM support)
+                0x80..=0x8F => self.pending_samples = (command & 0xF) as u32,
+                // Commands for other chips are skipped (by operand size).
+                0x30..=0x3F => self.skip(1),
+                0x40..=0x4E | 0x51..=0x5F | 0xA0..=0xBF => self.skip(2),
+                0xC0..=0xDF => self.skip(3),
+                0xE0..=0xFF => self.skip(4),
+                // PCM RAM writes and the DAC stream control commands.
+                0x68 => self.skip(11),
+                0x90 | 0x91 | 0x95 => self.skip(4),
+                0x92 => self.skip(5),
+                0x93 => self.skip(10),
+                0x94 => self.skip(1),
+                _ => {
+                    println!(
+                        "Unknown VGM command {:x} at {:x}, stopping.",
+                        command,
+                        self.position - 1
+                    );
+                    self.finished = true;
+                }
+            }
+        }
+    }
+
+    // Generate the next 'length' samples (per output channel).
+    pub fn get_next_audio_chunk(
+        &mut self,
+        sound: &mut sound::Sound,
+        length: u32,
+    ) -> Vec<soundchannel::PlaybackType> {
+        let mut stream = Vec::new();
+        let mut remaining = length;
+        while remaining > 0 {
+            self.run_commands(sound);
+            if self.finished {
+                // Pad out with silence.
+                stream.extend(vec![
+                    0;
+                    (remaining * sound::SDLUtility::MONO_STERO_FLAG as u32)
+                        as usize
+                ]);
+                break;
+            }
+            // The VGM and sound sample rates match, so waits map directly to samples.
+            let samples = remaining.min(self.pending_samples);
+            stream.extend(sound.get_next_audio_chunk(samples));
+            self.pending_samples -= samples;
+            self.played_since_loop = true;
+            remaining -= samples;
+        }
+        stream
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::audio::sound;
+    use crate::sega::audio::vgm;
+    use std::io;
+
+    fn record_test_vgm() -> Vec<u8> {
+        let gd3_tag = vgm::Gd3Tag {
+            game_name: "Test".to_string(),
+            ..Default::default()
+        };
+        // Use a CPU clock of 44100, so cycles map directly to samples.
+        let mut vgm_writer =
+            vgm::VgmWriter::new(io::Cursor::new(Vec::new()), gd3_tag, 44100, 1000).unwrap();
+        vgm_writer.write_psg(1000, 0x90).unwrap();
+        vgm_writer.write_psg(1005, 0x8F).unwrap();
+        vgm_writer.write_psg(1005 + 735, 0x01).unwrap();
+        vgm_writer
+            .finalise_at(1005 + 735 + 1000)
+            .unwrap()
+            .unwrap()
+            .into_inner()
+    }
+
+    #[test]
+    fn test_vgm_writer() {
+        let data = record_test_vgm();
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        assert_eq!(&data[0..4], b"Vgm ");
+        assert_eq!(read_u32(0x04) as usize, data.len() - 0x04);
+        assert_eq!(read_u32(0x08), 0x150);
+        assert_eq!(read_u32(0x0C), 3579545);
+        assert_eq!(read_u32(0x18), 5 + 735 + 1000);
+        assert_eq!(read_u32(0x34), 0x0C);
+
+        let commands = [
+            0x50, 0x90, // write
+            0x74, // wait 5
+            0x50, 0x8F, // write
+            0x62, // wait 735
+            0x50, 0x01, // write
+            0x61, 0xE8, 0x03, // wait 1000
+            0x66,
+        ];
+        assert_eq!(&data[0x40..0x40 + commands.len()], &commands);
+
+        let gd3_position = 0x14 + read_u32(0x14) as usize;
+        assert_eq!(gd3_position, 0x40 + commands.len());
+        assert_eq!(&data[gd3_position..gd3_position + 4], b"Gd3 ");
+        // Track name (empty), Japanese track name (empty), then the game name.
+        assert_eq!(
+            &data[gd3_position + 16..gd3_position + 24],
+            &[b'T', 0, b'e', 0, b's', 0, b't', 0]
+        );
+    }
+
+    #[test]
+    fn test_vgm_player() {
+        let mut player = vgm::VgmPlayer::new(record_test_vgm()).unwrap();
+        let mut sound = sound::Sound::new();
+
+        let chunk = player.get_next_audio_chunk(&mut sound, 100);
+        assert_eq!(
+            chunk.len(),
+            100 * sound::SDLUtility::MONO_STERO_FLAG as usize
+        );
+        assert!(!player.is_finished());
+
+        player.get_next_audio_chunk(&mut sound, 5 + 735 + 1000);
+        assert!(player.is_finished());
+
+        assert!(vgm::VgmPlayer::new(vec![0; 0x40]).is_err());
+    }
+
+    #[test]
+    fn test_vgm_player_skips_dac_streams() {
+        // Set up, start and stop a DAC stream before the PSG commands.
+        let mut data = record_test_vgm();
+        let stream_commands = [
+            0x90, 0x00, 0x02, 0x00, 0x2A, // setup stream control
+            0x92, 0x00, 0x44, 0xAC, 0x00, 0x00, // set stream frequency
+            0x93, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, // start stream
+            0x94, 0x00, // stop stream
+        ];
+        data.splice(0x40..0x40, stream_commands);
+        let mut player = vgm::VgmPlayer::new(data).unwrap();
+        let mut sound = sound::Sound::new();
+
+        player.get_next_audio_chunk(&mut sound, 100);
+        assert!(!player.is_finished());
+        player.get_next_audio_chunk(&mut sound, 5 + 735 + 1000);
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn test_vgm_player_empty_loop() {
+        // Loop back to the end command, there's nothing left to play.
+        let mut data = record_test_vgm();
+        let end_position = 0x40 + 11;
+        assert_eq!(data[end_position], 0x66);
+        let loop_offset = (end_position - 0x1C) as u32;
+        data[0x1C..0x20].copy_from_slice(&loop_offset.to_le_bytes());
+        let mut player = vgm::VgmPlayer::new(data).unwrap();
+        player.set_loop(true);
+        let mut sound = sound::Sound::new();
+
+        player.get_next_audio_chunk(&mut sound, 5 + 735 + 1000 + 100);
+        assert!(player.is_finished());
+    }
+}
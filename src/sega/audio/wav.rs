@@ -0,0 +1,126 @@
+use super::soundchannel;
+use std::fs;
+use std::io;
+use std::io::{Seek, SeekFrom, Write};
+
+pub type WavFileWriter = WavWriter<io::BufWriter<fs::File>>;
+
+// Writes 16-bit PCM samples to a RIFF/WAVE stream.
+// The RIFF and data chunk sizes aren't known until the recording stops, so
+// placeholders are written and patched up in 'finalise' (also called on drop).
+pub struct WavWriter<W: Write + Seek> {
+    writer: Option<W>,
+    data_bytes: u32,
+}
+
+impl WavFileWriter {
+    pub fn create(filename: &str, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let file = fs::File::create(filename)?;
+        WavWriter::new(io::BufWriter::new(file), sample_rate, channels)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    const HEADER_SIZE: u32 = 44;
+    const RIFF_SIZE_OFFSET: u64 = 4;
+    const DATA_SIZE_OFFSET: u64 = 40;
+    const FORMAT_PCM: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = (std::mem::size_of::<soundchannel::PlaybackType>() * 8) as u16;
+
+    pub fn new(mut writer: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let block_align = channels * Self::BITS_PER_SAMPLE / 8;
+        let byte_rate = sample_rate * block_align as u32;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(Self::HEADER_SIZE - 8).to_le_bytes())?; // Placeholder
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&Self::FORMAT_PCM.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&Self::BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?; // Placeholder
+
+        Ok(Self {
+            writer: Some(writer),
+            data_bytes: 0,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[soundchannel::PlaybackType]) -> io::Result<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            for sample in samples {
+                writer.write_all(&sample.to_le_bytes())?;
+            }
+            self.data_bytes = self
+                .data_bytes
+                .saturating_add(std::mem::size_of_val(samples) as u32);
+        }
+        Ok(())
+    }
+
+    // Patch the chunk sizes in the header and return the underlying writer.
+    pub fn finalise(&mut self) -> io::Result<Option<W>> {
+        match self.writer.take() {
+            Some(mut writer) => {
+                writer.seek(SeekFrom::Start(Self::RIFF_SIZE_OFFSET))?;
+                writer.write_all(
+                    &(Self::HEADER_SIZE - 8)
+                        .saturating_add(self.data_bytes)
+                        .to_le_bytes(),
+                )?;
+                writer.seek(SeekFrom::Start(Self::DATA_SIZE_OFFSET))?;
+                writer.write_all(&self.data_bytes.to_le_bytes())?;
+                writer.seek(SeekFrom::End(0))?;
+                writer.flush()?;
+                Ok(Some(writer))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if let Err(e) = self.finalise() {
+            println!("Error while finalising wav file. {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::audio::wav;
+    use std::io;
+
+    #[test]
+    fn test_wav_header() {
+        let mut wav_writer = wav::WavWriter::new(io::Cursor::new(Vec::new()), 44100, 2).unwrap();
+        wav_writer.write_samples(&[1, -1, 0x1234, -2]).unwrap();
+        let data = wav_writer.finalise().unwrap().unwrap().into_inner();
+
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes(data[22..24].try_into().unwrap()), 2);
+        assert_eq!(u32::from_le_bytes(data[24..28].try_into().unwrap()), 44100);
+        assert_eq!(
+            u32::from_le_bytes(data[28..32].try_into().unwrap()),
+            44100 * 4
+        );
+        assert_eq!(u16::from_le_bytes(data[34..36].try_into().unwrap()), 16);
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(data[40..44].try_into().unwrap()), 8);
+        assert_eq!(&data[44..48], &[0x01, 0x00, 0xFF, 0xFF]);
+
+        // Finalising again is a no-op.
+        assert!(wav_writer.finalise().unwrap().is_none());
+    }
+}
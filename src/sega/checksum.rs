@@ -0,0 +1,56 @@
+// CRC-32 (IEEE 802.3, as used by zip/png), used to identify ROMs and for file formats.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+// Continue a CRC-32 from a previous value (start with 0).
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    const POLYNOMIAL: u32 = 0xEDB88320;
+
+    let mut crc = !crc;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// Adler-32 (as used by zlib streams).
+pub fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for byte in data {
+        a = (a + *byte as u32) % MODULUS;
+        b = (b + a) % MODULUS;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::checksum;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(checksum::crc32(b""), 0);
+        assert_eq!(checksum::crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(
+            checksum::crc32_update(checksum::crc32(b"1234"), b"56789"),
+            0xCBF43926
+        );
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(checksum::adler32(b""), 1);
+        assert_eq!(checksum::adler32(b"Wikipedia"), 0x11E60398);
+    }
+}
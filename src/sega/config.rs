@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path;
+
+// Settings read from the user's config file.
+// The file uses a simple TOML/INI style format:
+//
+//    # Comment
+//    [keys]
+//    p1_fire_a = "Z, Space"
+//
+// Only '[section]' headers and 'name = value' lines are supported, values
+// may be quoted.
+pub struct Config {
+    sections: HashMap<String, Vec<(String, String)>>,
+}
+
+impl Config {
+    const APPLICATION_DIR: &'static str = "rustsega";
+    const FILENAME: &'static str = "config.toml";
+
+    pub fn new() -> Self {
+        Self {
+            sections: HashMap::new(),
+        }
+    }
+
+    // Location of the config file in the user config directory.
+    pub fn default_path() -> Option<path::PathBuf> {
+        let config_dir = if cfg!(target_os = "windows") {
+            env::var_os("APPDATA").map(path::PathBuf::from)
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .filter(|dir| !dir.is_empty())
+                .map(path::PathBuf::from)
+                .or_else(|| {
+                    env::var_os("HOME").map(|home| path::PathBuf::from(home).join(".config"))
+                })
+        };
+        config_dir.map(|dir| dir.join(Config::APPLICATION_DIR).join(Config::FILENAME))
+    }
+
+    pub fn load(filename: &path::Path) -> Result<Self, String> {
+        let text = fs::read_to_string(filename)
+            .map_err(|e| format!("Unable to read {}. {}", filename.display(), e))?;
+        Config::parse(&text).map_err(|e| format!("{}: {}", filename.display(), e))
+    }
+
+    // Load the config file from the default location, if there is one.
+    pub fn load_default() -> Result<Self, String> {
+        match Config::default_path() {
+            Some(filename) if filename.exists() => Config::load(&filename),
+            _ => Ok(Config::new()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::new();
+        let mut section = String::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = Config::strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                match name.strip_suffix(']') {
+                    Some(name) => section = name.trim().to_string(),
+                    None => return Err(format!("line {}: expected ']'", line_number + 1)),
+                }
+            } else if let Some((name, value)) = line.split_once('=') {
+                let name = name.trim();
+                if name.is_empty() {
+                    return Err(format!("line {}: missing name", line_number + 1));
+                }
+                let value = Config::unquote(value.trim())
+                    .ok_or(format!("line {}: unterminated string", line_number + 1))?;
+                config
+                    .sections
+                    .entry(section.clone())
+                    .or_default()
+                    .push((name.to_string(), value));
+            } else {
+                return Err(format!(
+                    "line {}: expected '[section]' or 'name = value'",
+                    line_number + 1
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn strip_comment(line: &str) -> &str {
+        let mut in_quotes = false;
+        for (index, character) in line.char_indices() {
+            match character {
+                '"' => in_quotes = !in_quotes,
+                '#' | ';' if !in_quotes => return &line[..index],
+                _ => {}
+            }
+        }
+        line
+    }
+
+    fn unquote(value: &str) -> Option<String> {
+        for quote in ['"', '\''] {
+            if let Some(quoted) = value.strip_prefix(quote) {
+                return quoted.strip_suffix(quote).map(|value| value.to_string());
+            }
+        }
+        Some(value.to_string())
+    }
+
+    // The entries of a section, in the order they appear in the file.
+    pub fn section(&self, section: &str) -> &[(String, String)] {
+        self.sections
+            .get(section)
+            .map(|entries| entries.as_slice())
+            .unwrap_or(&[])
+    }
+
+    // The value of an entry (the last one, if the entry is repeated).
+    pub fn get(&self, section: &str, name: &str) -> Option<&str> {
+        self.section(section)
+            .iter()
+            .rev()
+            .find(|(entry_name, _)| entry_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::config;
+
+    #[test]
+    fn test_parse_config() {
+        let config = config::Config::parse(
+            "# Comment\n\
+             top = 1\n\
+             [keys]\n\
+             p1_up = \"Up\"  # trailing comment\n\
+             p1_fire_a = 'Z, Space'\n\
+             quit = Escape\n\
+             \n\
+             [other]\n\
+             hash = \"#1\"\n\
+             quit = Q\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.get("", "top"), Some("1"));
+        assert_eq!(config.get("keys", "p1_up"), Some("Up"));
+        assert_eq!(config.get("keys", "p1_fire_a"), Some("Z, Space"));
+        assert_eq!(config.get("keys", "quit"), Some("Escape"));
+        assert_eq!(config.get("other", "hash"), Some("#1"));
+        assert_eq!(config.get("other", "quit"), Some("Q"));
+        assert_eq!(config.get("keys", "missing"), None);
+        assert_eq!(config.section("keys").len(), 3);
+        assert!(config.section("missing").is_empty());
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        assert!(config::Config::parse("[keys\n").is_err());
+        assert!(config::Config::parse("[keys]\nvalue\n").is_err());
+        assert!(config::Config::parse("[keys]\n = 1\n").is_err());
+        assert!(config::Config::parse("[keys]\nvalue = \"1\n").is_err());
+    }
+}
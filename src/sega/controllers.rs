@@ -0,0 +1,486 @@
+use super::config;
+use super::inputs;
+use sdl2::controller;
+use sdl2::event;
+use std::collections::HashMap;
+
+// Game controller (pad) support, the first two connected pads are players 1
+// and 2.  Pads can be connected/disconnected while running, a pad connected
+// while both players have one is used when a player's pad is removed.
+pub struct Controllers {
+    subsystem: Option<sdl2::GameControllerSubsystem>,
+    controllers: HashMap<u32, controller::GameController>, // By instance id
+    connection_order: Vec<u32>,
+    players: [Option<u32>; Controllers::PLAYERS],
+
+    button_mapping: HashMap<controller::Button, inputs::Action>, // Player 1 actions
+    deadzone: i16,
+
+    // Direction (up, down, left, right) state of each player from the d-pad and analog stick.
+    dpad: [[bool; 4]; Controllers::PLAYERS],
+    stick: [[bool; 4]; Controllers::PLAYERS],
+}
+
+impl Controllers {
+    const PLAYERS: usize = 2;
+    pub const CONFIG_SECTION: &'static str = "controller";
+    pub const DEFAULT_DEADZONE: i16 = 8000;
+
+    const UP: usize = 0;
+    const DOWN: usize = 1;
+    const LEFT: usize = 2;
+    const RIGHT: usize = 3;
+    const DIRECTION_ACTIONS: [inputs::Action; 4] = [
+        inputs::Action::P1Up,
+        inputs::Action::P1Down,
+        inputs::Action::P1Left,
+        inputs::Action::P1Right,
+    ];
+
+    // Button names, as used in the SDL controller mappings.
+    const BUTTON_NAMES: [(&'static str, controller::Button); 21] = [
+        ("a", controller::Button::A),
+        ("b", controller::Button::B),
+        ("x", controller::Button::X),
+        ("y", controller::Button::Y),
+        ("back", controller::Button::Back),
+        ("guide", controller::Button::Guide),
+        ("start", controller::Button::Start),
+        ("leftstick", controller::Button::LeftStick),
+        ("rightstick", controller::Button::RightStick),
+        ("leftshoulder", controller::Button::LeftShoulder),
+        ("rightshoulder", controller::Button::RightShoulder),
+        ("dpup", controller::Button::DPadUp),
+        ("dpdown", controller::Button::DPadDown),
+        ("dpleft", controller::Button::DPadLeft),
+        ("dpright", controller::Button::DPadRight),
+        ("misc1", controller::Button::Misc1),
+        ("paddle1", controller::Button::Paddle1),
+        ("paddle2", controller::Button::Paddle2),
+        ("paddle3", controller::Button::Paddle3),
+        ("paddle4", controller::Button::Paddle4),
+        ("touchpad", controller::Button::Touchpad),
+    ];
+
+    // Actions that can be mapped to buttons (named as in the config file).
+    const MAPPABLE_ACTIONS: [(&'static str, inputs::Action); 10] = [
+        ("up", inputs::Action::P1Up),
+        ("down", inputs::Action::P1Down),
+        ("left", inputs::Action::P1Left),
+        ("right", inputs::Action::P1Right),
+        ("fire_a", inputs::Action::P1FireA),
+        ("fire_b", inputs::Action::P1FireB),
+        ("turbo_a", inputs::Action::P1TurboA),
+        ("turbo_b", inputs::Action::P1TurboB),
+        ("pause", inputs::Action::Pause),
+        ("reset", inputs::Action::Reset),
+    ];
+
+    pub fn new() -> Self {
+        let mut controllers = Self {
+            subsystem: None,
+            controllers: HashMap::new(),
+            connection_order: Vec::new(),
+            players: [None; Controllers::PLAYERS],
+            button_mapping: HashMap::new(),
+            deadzone: Controllers::DEFAULT_DEADZONE,
+            dpad: [[false; 4]; Controllers::PLAYERS],
+            stick: [[false; 4]; Controllers::PLAYERS],
+        };
+        controllers.bind(inputs::Action::P1Up, &[controller::Button::DPadUp]);
+        controllers.bind(inputs::Action::P1Down, &[controller::Button::DPadDown]);
+        controllers.bind(inputs::Action::P1Left, &[controller::Button::DPadLeft]);
+        controllers.bind(inputs::Action::P1Right, &[controller::Button::DPadRight]);
+        controllers.bind(
+            inputs::Action::P1FireA,
+            &[controller::Button::A, controller::Button::Y],
+        );
+        controllers.bind(
+            inputs::Action::P1FireB,
+            &[controller::Button::B, controller::Button::X],
+        );
+        controllers.bind(inputs::Action::Pause, &[controller::Button::Start]);
+        controllers.bind(inputs::Action::Reset, &[controller::Button::Back]);
+        controllers
+    }
+
+    // Connected pads are reported as 'ControllerDeviceAdded' events (including
+    // those connected at start-up), so only the subsystem needs to be kept.
+    pub fn open(&mut self, sdl_context: &sdl2::Sdl) {
+        match sdl_context.game_controller() {
+            Ok(subsystem) => self.subsystem = Some(subsystem),
+            Err(e) => println!("Error while opening game controllers. {}", e),
+        }
+    }
+
+    pub fn button_from_name(name: &str) -> Option<controller::Button> {
+        Controllers::BUTTON_NAMES
+            .iter()
+            .find(|(button_name, _)| button_name.eq_ignore_ascii_case(name.trim()))
+            .map(|(_, button)| *button)
+    }
+
+    pub fn button_name(button: controller::Button) -> &'static str {
+        Controllers::BUTTON_NAMES
+            .iter()
+            .find(|(_, named_button)| *named_button == button)
+            .map(|(name, _)| *name)
+            .unwrap_or("unknown")
+    }
+
+    // Replace the buttons mapped to 'action' (a player 1 action, pause or reset).
+    pub fn bind(&mut self, action: inputs::Action, buttons: &[controller::Button]) {
+        self.button_mapping
+            .retain(|_, mapped_action| *mapped_action != action);
+        for button in buttons {
+            self.button_mapping.insert(*button, action);
+        }
+    }
+
+    // Load the button mapping and deadzone from the '[controller]' section, eg:
+    //    fire_a = "a, y"
+    //    deadzone = 8000
+    pub fn load_config(&mut self, config: &config::Config) {
+        for (name, value) in config.section(Controllers::CONFIG_SECTION) {
+            if name == "deadzone" {
+                match value.parse::<i16>() {
+                    Ok(deadzone) if deadzone >= 0 => self.deadzone = deadzone,
+                    _ => println!("Invalid controller deadzone in config: {}", value),
+                }
+                continue;
+            }
+
+            let action = match Controllers::MAPPABLE_ACTIONS
+                .iter()
+                .find(|(action_name, _)| action_name == name)
+            {
+                Some((_, action)) => *action,
+                None => {
+                    println!("Unknown controller action in config: {}", name);
+                    continue;
+                }
+            };
+            let mut buttons = Vec::new();
+            for button_name in value.split(',').filter(|button| !button.trim().is_empty()) {
+                match Controllers::button_from_name(button_name) {
+                    Some(button) => buttons.push(button),
+                    None => println!(
+                        "Unknown controller button '{}' for {} in config.",
+                        button_name.trim(),
+                        name
+                    ),
+                }
+            }
+            self.bind(action, &buttons);
+        }
+    }
+
+    pub fn print_buttons(&self) {
+        let describe = |action: inputs::Action| {
+            let mut names: Vec<&str> = self
+                .button_mapping
+                .iter()
+                .filter(|(_, mapped_action)| **mapped_action == action)
+                .map(|(button, _)| Controllers::button_name(*button))
+                .collect();
+            names.sort();
+            if names.is_empty() {
+                "(unmapped)".to_string()
+            } else {
+                names.join("/")
+            }
+        };
+        println!("Controller mappings (players 1 and 2, left stick also moves):");
+        println!(
+            "{}",
+            Controllers::MAPPABLE_ACTIONS
+                .iter()
+                .map(|(name, action)| format!("{}: {}", name, describe(*action)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+
+    fn player_for(&self, instance_id: u32) -> Option<usize> {
+        self.players
+            .iter()
+            .position(|player| *player == Some(instance_id))
+    }
+
+    // Give any players without a pad the next unassigned connected pad.
+    fn assign_players(&mut self) {
+        for player in 0..Controllers::PLAYERS {
+            if self.players[player].is_none() {
+                self.players[player] = self
+                    .connection_order
+                    .iter()
+                    .find(|instance_id| self.player_for(**instance_id).is_none())
+                    .copied();
+                if let Some(instance_id) = self.players[player] {
+                    let name = self
+                        .controllers
+                        .get(&instance_id)
+                        .map(|controller| controller.name())
+                        .unwrap_or_default();
+                    println!("Controller '{}' is player {}.", name, player + 1);
+                }
+            }
+        }
+    }
+
+    fn add_controller(&mut self, joystick_index: u32) {
+        let subsystem = match self.subsystem.as_ref() {
+            Some(subsystem) => subsystem,
+            None => return,
+        };
+        match subsystem.open(joystick_index) {
+            Ok(controller) => {
+                let instance_id = controller.instance_id();
+                if self.controllers.insert(instance_id, controller).is_none() {
+                    self.connection_order.push(instance_id);
+                }
+                self.assign_players();
+            }
+            Err(e) => println!("Error while opening game controller. {}", e),
+        }
+    }
+
+    fn remove_controller(&mut self, instance_id: u32, joystick: &mut inputs::Joystick) {
+        self.controllers.remove(&instance_id);
+        self.connection_order.retain(|id| *id != instance_id);
+        if let Some(player) = self.player_for(instance_id) {
+            println!("Controller for player {} removed.", player + 1);
+            self.players[player] = None;
+
+            // Release anything held on the removed pad.
+            self.dpad[player] = [false; 4];
+            self.stick[player] = [false; 4];
+            self.update_directions(player, joystick);
+            for action in [
+                inputs::Action::P1FireA,
+                inputs::Action::P1FireB,
+                inputs::Action::P1TurboA,
+                inputs::Action::P1TurboB,
+            ] {
+                Controllers::apply(action, player, false, joystick);
+            }
+            self.assign_players();
+        }
+    }
+
+    fn apply(
+        action: inputs::Action,
+        player: usize,
+        pressed: bool,
+        joystick: &mut inputs::Joystick,
+    ) {
+        let action = if player == 1 {
+            action.for_player2()
+        } else {
+            action
+        };
+        action.apply(joystick, pressed);
+    }
+
+    fn update_directions(&self, player: usize, joystick: &mut inputs::Joystick) {
+        for direction in [
+            Controllers::UP,
+            Controllers::DOWN,
+            Controllers::LEFT,
+            Controllers::RIGHT,
+        ] {
+            Controllers::apply(
+                Controllers::DIRECTION_ACTIONS[direction],
+                player,
+                self.dpad[player][direction] || self.stick[player][direction],
+                joystick,
+            );
+        }
+    }
+
+    fn handle_button(
+        &mut self,
+        player: usize,
+        button: controller::Button,
+        pressed: bool,
+        joystick: &mut inputs::Joystick,
+    ) {
+        if let Some(action) = self.button_mapping.get(&button).copied() {
+            match Controllers::DIRECTION_ACTIONS
+                .iter()
+                .position(|direction| *direction == action)
+            {
+                Some(direction) => {
+                    self.dpad[player][direction] = pressed;
+                    self.update_directions(player, joystick);
+                }
+                None => Controllers::apply(action, player, pressed, joystick),
+            }
+        }
+    }
+
+    fn handle_axis(
+        &mut self,
+        player: usize,
+        axis: controller::Axis,
+        value: i16,
+        joystick: &mut inputs::Joystick,
+    ) {
+        let (negative, positive) = match axis {
+            controller::Axis::LeftX => (Controllers::LEFT, Controllers::RIGHT),
+            controller::Axis::LeftY => (Controllers::UP, Controllers::DOWN),
+            _ => return,
+        };
+        self.stick[player][negative] = value < -self.deadzone;
+        self.stick[player][positive] = value > self.deadzone;
+        self.update_directions(player, joystick);
+    }
+
+    pub fn handle_events(&mut self, event: &event::Event, joystick: &mut inputs::Joystick) {
+        match event {
+            event::Event::ControllerDeviceAdded { which, .. } => self.add_controller(*which),
+            event::Event::ControllerDeviceRemoved { which, .. } => {
+                self.remove_controller(*which, joystick)
+            }
+            event::Event::ControllerButtonDown { which, button, .. } => {
+                if let Some(player) = self.player_for(*which) {
+                    self.handle_button(player, *button, true, joystick);
+                }
+            }
+            event::Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(player) = self.player_for(*which) {
+                    self.handle_button(player, *button, false, joystick);
+                }
+            }
+            event::Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                if let Some(player) = self.player_for(*which) {
+                    self.handle_axis(player, *axis, *value, joystick);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::config;
+    use crate::sega::controllers;
+    use crate::sega::inputs;
+    use sdl2::controller;
+    use sdl2::event;
+
+    fn button_event(which: u32, button: controller::Button, down: bool) -> event::Event {
+        if down {
+            event::Event::ControllerButtonDown {
+                timestamp: 0,
+                which,
+                button,
+            }
+        } else {
+            event::Event::ControllerButtonUp {
+                timestamp: 0,
+                which,
+                button,
+            }
+        }
+    }
+
+    fn axis_event(which: u32, axis: controller::Axis, value: i16) -> event::Event {
+        event::Event::ControllerAxisMotion {
+            timestamp: 0,
+            which,
+            axis,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_players() {
+        let mut controllers = controllers::Controllers::new();
+        let mut joystick = inputs::Joystick::new();
+        // Fake connected pads (instance ids 5 and 9).
+        controllers.connection_order = vec![5, 9];
+        controllers.assign_players();
+
+        controllers.handle_events(&button_event(5, controller::Button::A, true), &mut joystick);
+        assert_eq!(joystick.read_port1(), 0xEF); // Joystick 1 Fire A
+        controllers.handle_events(
+            &button_event(5, controller::Button::A, false),
+            &mut joystick,
+        );
+        controllers.handle_events(
+            &button_event(9, controller::Button::DPadUp, true),
+            &mut joystick,
+        );
+        assert_eq!(joystick.read_port1(), 0xBF); // Joystick 2 Up
+        assert_eq!(joystick.read_port2(), 0xFF);
+
+        // Removing player 1's pad releases its buttons.
+        controllers.handle_events(&button_event(5, controller::Button::B, true), &mut joystick);
+        controllers.remove_controller(5, &mut joystick);
+        assert_eq!(joystick.read_port1(), 0xBF);
+        assert_eq!(controllers.player_for(9), Some(1));
+
+        // Unassigned pads are ignored.
+        controllers.handle_events(&button_event(7, controller::Button::A, true), &mut joystick);
+        assert_eq!(joystick.read_port1(), 0xBF);
+    }
+
+    #[test]
+    fn test_analog_deadzone() {
+        let mut controllers = controllers::Controllers::new();
+        let mut joystick = inputs::Joystick::new();
+        controllers.connection_order = vec![1];
+        controllers.assign_players();
+
+        controllers.handle_events(&axis_event(1, controller::Axis::LeftX, 4000), &mut joystick);
+        assert_eq!(joystick.read_port1(), 0xFF);
+        controllers.handle_events(
+            &axis_event(1, controller::Axis::LeftX, 20000),
+            &mut joystick,
+        );
+        assert_eq!(joystick.read_port1(), 0xF7); // Right
+        controllers.handle_events(
+            &axis_event(1, controller::Axis::LeftX, -20000),
+            &mut joystick,
+        );
+        assert_eq!(joystick.read_port1(), 0xFB); // Left
+
+        // The d-pad holds the direction when the stick is centred.
+        controllers.handle_events(
+            &button_event(1, controller::Button::DPadLeft, true),
+            &mut joystick,
+        );
+        controllers.handle_events(&axis_event(1, controller::Axis::LeftX, 0), &mut joystick);
+        assert_eq!(joystick.read_port1(), 0xFB);
+        controllers.handle_events(
+            &button_event(1, controller::Button::DPadLeft, false),
+            &mut joystick,
+        );
+        assert_eq!(joystick.read_port1(), 0xFF);
+    }
+
+    #[test]
+    fn test_button_config() {
+        let mut controllers = controllers::Controllers::new();
+        let mut joystick = inputs::Joystick::new();
+        controllers.connection_order = vec![1];
+        controllers.assign_players();
+
+        controllers.load_config(
+            &config::Config::parse("[controller]\nfire_a = \"rightshoulder\"\ndeadzone = 100\n")
+                .unwrap(),
+        );
+        assert_eq!(controllers.deadzone, 100);
+        controllers.handle_events(&button_event(1, controller::Button::A, true), &mut joystick);
+        assert_eq!(joystick.read_port1(), 0xFF);
+        controllers.handle_events(
+            &button_event(1, controller::Button::RightShoulder, true),
+            &mut joystick,
+        );
+        assert_eq!(joystick.read_port1(), 0xEF);
+    }
+}
@@ -3,8 +3,14 @@ use super::super::graphics;
 use super::super::interruptor;
 use super::super::memory::memory;
 use super::super::ports;
+use super::super::snapshot;
+use super::super::watch;
+use super::disassembler;
+use super::error;
 use super::instructions;
 use super::pc_state;
+use super::trace;
+use std::collections::VecDeque;
 use std::thread;
 use std::time;
 
@@ -16,9 +22,13 @@ pub struct Core<M> {
     interruptor: interruptor::Interruptor,
     raw_display: Vec<u8>,
     start_time: time::SystemTime,
+    start_cycles: clocks::ClockType, // Clock cycles at 'start_time'.
+    speed: f64,                      // Realtime speed multiplier.
+    trace: Option<trace::TraceLogger>,
+    recent_pcs: VecDeque<u16>, // For the trace in a 'CpuError'.
 }
 
-struct Constants {}
+pub struct Constants {}
 
 impl Constants {
     pub const CLOCK_HZ: u32 = 3590000; // set to Z80 clock speed for SMS
@@ -26,6 +36,10 @@ impl Constants {
 
 impl<M: memory::MemoryRW> Core<M> {
     pub const IRQIM1ADDR: u16 = 0x38;
+    pub const NMIADDR: u16 = 0x66;
+    const NMI_CYCLES: u32 = 11;
+    const HALT_OPCODE: u8 = 0x76;
+    const RECENT_PC_COUNT: usize = 8;
 
     pub fn new(
         clock: clocks::Clock,
@@ -50,6 +64,10 @@ impl<M: memory::MemoryRW> Core<M> {
                     * (graphics::display::SDLUtility::bytes_per_pixel() as usize)
             ],
             start_time: time::SystemTime::now(),
+            start_cycles: 0,
+            speed: 1.0,
+            trace: None,
+            recent_pcs: VecDeque::with_capacity(Core::<M>::RECENT_PC_COUNT),
         }
     }
 
@@ -63,6 +81,7 @@ impl<M: memory::MemoryRW> Core<M> {
                 self.memory
                     .write(self.pc_state.get_sp(), self.pc_state.get_pc_low());
                 self.pc_state.set_pc(Core::<M>::IRQIM1ADDR);
+                self.pc_state.set_memptr(Core::<M>::IRQIM1ADDR);
 
                 // Disable mask-able interrupts
                 self.pc_state.set_iff1(false);
@@ -73,16 +92,103 @@ impl<M: memory::MemoryRW> Core<M> {
         }
     }
 
+    // Non-maskable interrupt (raised by the pause button).
+    fn nmi(&mut self) {
+        // HALT holds the PC on itself, the interrupt returns to the next instruction.
+        if self.memory.peek(self.pc_state.get_pc()) == Core::<M>::HALT_OPCODE {
+            self.pc_state.increment_pc(1);
+        }
+        self.pc_state.increment_sp(-1);
+        self.memory
+            .write(self.pc_state.get_sp(), self.pc_state.get_pc_high());
+        self.pc_state.increment_sp(-1);
+        self.memory
+            .write(self.pc_state.get_sp(), self.pc_state.get_pc_low());
+        self.pc_state.set_pc(Core::<M>::NMIADDR);
+        self.pc_state.set_memptr(Core::<M>::NMIADDR);
+
+        // Keep the mask-able interrupt state for 'RETN'.
+        self.pc_state.set_iff2(self.pc_state.get_iff1());
+        self.pc_state.set_iff1(false);
+        self.clock.increment(Core::<M>::NMI_CYCLES);
+    }
+
+    // Register access for the debugger.
+    pub fn get_pc_state(&self) -> &pc_state::PcState {
+        &self.pc_state
+    }
+
+    pub fn get_pc_state_mut(&mut self) -> &mut pc_state::PcState {
+        &mut self.pc_state
+    }
+
+    // Memory or port watch triggered since the last call.
+    pub fn take_watch_hit(&mut self) -> Option<watch::WatchHit> {
+        self.memory
+            .take_watch_hit()
+            .or_else(|| self.ports.take_watch_hit())
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.ports.frame_count()
+    }
+
+    pub fn border_colour(&self) -> Option<graphics::display::Colour> {
+        self.ports.border_colour()
+    }
+
     pub fn export(&mut self) -> bool {
         self.ports.export(&mut self.raw_display)
     }
 
     pub fn reset(&mut self) {
         self.pc_state = pc_state::PcState::new();
+        self.rebase_timing();
+    }
+
+    // Restart the realtime comparison from the current clock, so a change of
+    // speed (or a pause) doesn't try to catch up (or wait for) the lost time.
+    pub fn rebase_timing(&mut self) {
         self.start_time = time::SystemTime::now();
+        self.start_cycles = self.clock.cycles;
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+        self.rebase_timing();
+    }
+
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    // Instruction trace logging ('None' to stop).
+    pub fn set_trace(&mut self, trace: Option<trace::TraceLogger>) {
+        self.trace = trace;
     }
 
-    pub fn step(&mut self, debug: bool, realtime: bool) {
+    pub fn get_trace_mut(&mut self) -> Option<&mut trace::TraceLogger> {
+        self.trace.as_mut()
+    }
+
+    // The instructions before the current one (oldest first), disassembled.
+    fn recent_instructions(&self) -> Vec<String> {
+        let read = |address: u16| self.memory.peek(address);
+        self.recent_pcs
+            .iter()
+            .map(|&pc| {
+                let disassembly = disassembler::Disassembler::disassemble(&read, pc);
+                match self.memory.get_bank(pc) {
+                    Some(bank) => format!("{:02X}:{:04X} {}", bank, pc, disassembly.text),
+                    None => format!("--:{:04X} {}", pc, disassembly.text),
+                }
+            })
+            .collect()
+    }
+
+    // Run one instruction.  On an unimplemented opcode the PC is left after
+    // it (so carrying on treats it as a NOP).
+    pub fn step(&mut self, debug: bool, realtime: bool) -> Result<(), error::CpuError> {
         // Start with 'expanded' version of step
 
         if realtime {
@@ -91,43 +197,74 @@ impl<M: memory::MemoryRW> Core<M> {
                 .elapsed()
                 .expect("Error getting eplapsed")
                 .as_millis() as u64;
-            if 1000 * self.clock.cycles / Constants::CLOCK_HZ as u64 > in_ms {
-                let required_sleep =
-                    (1000 * self.clock.cycles / Constants::CLOCK_HZ as u64) - in_ms;
+            let emulated_ms = ((self.clock.cycles - self.start_cycles) as f64 * 1000.0
+                / (Constants::CLOCK_HZ as f64 * self.speed)) as u64;
+            if emulated_ms > in_ms {
+                let required_sleep = emulated_ms - in_ms;
                 thread::sleep(time::Duration::from_millis(required_sleep));
             }
         }
 
         self.interruptor.set_cycle(self.clock.cycles);
 
+        if let Some(trace) = self.trace.as_mut() {
+            if trace.is_due(self.pc_state.get_pc(), self.ports.frame_count()) {
+                trace.log(self.clock.cycles, &self.pc_state, &self.memory);
+            }
+        }
+
         let op_code = self.memory.read(self.pc_state.get_pc());
 
         if debug {
+            let read = |address: u16| self.memory.peek(address);
+            let disassembly =
+                disassembler::Disassembler::disassemble(&read, self.pc_state.get_pc());
             print!(
-                "{} {:x} {:x} ({:x} {:x}) ",
+                "{} {:x} {:x} {:<18} ",
                 self.clock.cycles,
                 op_code,
                 self.pc_state.get_pc(),
-                op_code,
-                self.memory.read(self.pc_state.get_pc() + 1)
+                disassembly.text
             );
             println!("{}", self.pc_state);
         }
+        let pc = self.pc_state.get_pc();
         self.pc_state.increment_pc(1);
-        instructions::Instruction::execute(
+        self.pc_state.latch_q();
+        if let Err(mut error) = instructions::Instruction::execute(
             op_code,
             &mut self.clock,
             &mut self.memory,
             &mut self.pc_state,
             &mut self.ports,
             &mut self.interruptor,
-        );
+        ) {
+            error.pc = pc;
+            error.bank = self.memory.get_bank(pc);
+            error.trace = self.recent_instructions();
+            self.clock.increment(4); // As a NOP, so time still moves on.
+            return Err(error);
+        }
+        if self.recent_pcs.len() == Core::<M>::RECENT_PC_COUNT {
+            self.recent_pcs.pop_front();
+        }
+        self.recent_pcs.push_back(pc);
         if self
             .ports
             .poll_interrupts(&mut self.raw_display, &self.clock)
         {
             self.interupt();
         }
+
+        if self.ports.joysticks.take_pause_request() {
+            self.nmi();
+        }
+        Ok(())
+    }
+
+    // Last exported display (see 'export').
+    pub fn get_display(&self) -> &[u8] {
+        &self.raw_display
     }
 
     pub fn generate_display(&mut self, buffer: &mut [u8]) {
@@ -136,6 +273,25 @@ impl<M: memory::MemoryRW> Core<M> {
     }
 }
 
+// The whole machine state (apart from the live input), taken between steps.
+impl<M: memory::MemoryRW + snapshot::Snapshot> snapshot::Snapshot for Core<M> {
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        writer.write_u64(self.clock.cycles);
+        self.pc_state.save_state(writer);
+        self.memory.save_state(writer);
+        self.ports.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        self.clock.cycles = reader.read_u64()?;
+        self.pc_state.load_state(reader)?;
+        self.memory.load_state(reader)?;
+        self.ports.load_state(reader)?;
+        self.rebase_timing();
+        Ok(())
+    }
+}
+
 #[test]
 fn test_core_creation() {
     use super::super::graphics::vdp;
@@ -149,7 +305,101 @@ fn test_core_creation() {
     ports.add_device(Box::new(vdp));
     let mut core = Core::new(clock, memory, pc_state, ports, interruptor);
 
-    core.step(true, false);
+    core.step(true, false).unwrap();
     println!("{}", core.pc_state);
-    core.step(true, false);
+    core.step(true, false).unwrap();
+}
+
+#[test]
+fn test_speed_rebase() {
+    let mut core = Core::new(
+        clocks::Clock::new(),
+        memory::MemoryAbsolute::new(),
+        pc_state::PcState::new(),
+        ports::Ports::new(),
+        interruptor::Interruptor::new(),
+    );
+
+    // Pretend a second has already been emulated, a speed change starts the
+    // realtime comparison from here (so the next step doesn't sleep for it).
+    core.clock.cycles = Constants::CLOCK_HZ as u64;
+    core.set_speed(0.25);
+    assert_eq!(core.get_speed(), 0.25);
+    assert_eq!(core.start_cycles, Constants::CLOCK_HZ as u64);
+
+    let start = time::Instant::now();
+    core.step(false, true).unwrap();
+    assert!(start.elapsed() < time::Duration::from_millis(500));
+}
+
+#[test]
+fn test_all_opcodes_implemented() {
+    use super::super::memory::flat;
+
+    let mut core = Core::new(
+        clocks::Clock::new(),
+        flat::FlatMemory::new(),
+        pc_state::PcState::new(),
+        ports::Ports::new(),
+        interruptor::Interruptor::new(),
+    );
+    // Every opcode decodes (the undocumented ones included), so nothing
+    // reaches the 'CpuError' path.
+    let prefixes: [&[u8]; 7] = [
+        &[],
+        &[0xCB],
+        &[0xDD],
+        &[0xED],
+        &[0xFD],
+        &[0xDD, 0xCB, 0x00],
+        &[0xFD, 0xCB, 0x00],
+    ];
+    for prefix in prefixes {
+        for op_code in 0..=0xFF {
+            core.memory
+                .load(0x0100, &[prefix, &[op_code, 0x00, 0x00]].concat());
+            core.pc_state.set_pc(0x0100);
+            if let Err(error) = core.step(false, false) {
+                panic!("{}", error);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_nmi() {
+    use super::super::memory::flat;
+    use super::super::memory::memory::MemoryRW;
+
+    let mut memory = flat::FlatMemory::new();
+    memory.load(0x0100, &[0xFB, 0x76]); // EI, HALT
+    memory.load(0x0066, &[0xED, 0x45]); // RETN
+    let mut pc_state = pc_state::PcState::new();
+    pc_state.set_pc(0x0100);
+    pc_state.sp_reg.set(0xE000);
+    let mut core = Core::new(
+        clocks::Clock::new(),
+        memory,
+        pc_state,
+        ports::Ports::new(),
+        interruptor::Interruptor::new(),
+    );
+
+    // EI runs the HALT as well, which then stays put.
+    core.step(false, false).unwrap();
+    core.step(false, false).unwrap();
+    assert_eq!(core.get_pc_state().get_pc(), 0x0101);
+
+    // The pause button leaves the HALT, returning after it.
+    core.ports.joysticks.pause(false);
+    let cycles = core.clock.cycles;
+    core.step(false, false).unwrap();
+    assert_eq!(core.clock.cycles - cycles, 4 + 11);
+    assert_eq!(core.get_pc_state().get_pc(), 0x0066);
+    assert!(!core.get_pc_state().get_iff1());
+    assert_eq!(core.memory.read16(0xDFFE), 0x0102);
+
+    core.step(false, false).unwrap();
+    assert_eq!(core.get_pc_state().get_pc(), 0x0102);
+    assert!(core.get_pc_state().get_iff1());
 }
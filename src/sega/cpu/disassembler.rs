@@ -0,0 +1,413 @@
+// Z80 disassembler, decodes the documented and undocumented instructions
+// (including the CB, DD, ED, FD, DDCB and FDCB prefixes) to Zilog
+// mnemonics.  Opcodes are split into fields (see "Decoding Z80 Opcodes"):
+//   x = bits 7-6, y = bits 5-3, z = bits 2-0, p = bits 5-4, q = bit 3
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Disassembly {
+    pub text: String,
+    pub length: u16,
+}
+
+pub struct Disassembler {}
+
+const R: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const RP: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const RP2: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const CC: [&str; 8] = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+const ALU: [&str; 8] = [
+    "ADD A,", "ADC A,", "SUB ", "SBC A,", "AND ", "XOR ", "OR ", "CP ",
+];
+const ROT: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SLL", "SRL"];
+const IM: [&str; 8] = ["0", "0", "1", "2", "0", "0", "1", "2"];
+const BLOCK: [[&str; 4]; 4] = [
+    ["LDI", "CPI", "INI", "OUTI"],
+    ["LDD", "CPD", "IND", "OUTD"],
+    ["LDIR", "CPIR", "INIR", "OTIR"],
+    ["LDDR", "CPDR", "INDR", "OTDR"],
+];
+
+// Reads the instruction bytes in order, with the index register (for the
+// DD/FD prefixes) used in place of HL.
+struct Decoder<'a> {
+    read: &'a dyn Fn(u16) -> u8,
+    address: u16,
+    length: u16,
+    index: Option<&'static str>, // "IX" or "IY".
+    displacement: Option<i8>,    // Read once (with the first use).
+}
+
+impl<'a> Decoder<'a> {
+    fn next(&mut self) -> u8 {
+        let value = (self.read)(self.address.wrapping_add(self.length));
+        self.length += 1;
+        value
+    }
+
+    fn n(&mut self) -> String {
+        format!("${:02X}", self.next())
+    }
+
+    fn nn(&mut self) -> String {
+        let low = self.next() as u16;
+        let high = self.next() as u16;
+        format!("${:04X}", (high << 8) | low)
+    }
+
+    // Relative jump target.
+    fn d(&mut self) -> String {
+        let offset = self.next() as i8;
+        let target = self
+            .address
+            .wrapping_add(self.length)
+            .wrapping_add(offset as u16);
+        format!("${:04X}", target)
+    }
+
+    fn indexed(&mut self) -> String {
+        let index = self.index.unwrap_or("HL");
+        let displacement = match self.displacement {
+            Some(displacement) => displacement,
+            None => {
+                let displacement = self.next() as i8;
+                self.displacement = Some(displacement);
+                displacement
+            }
+        };
+        if displacement < 0 {
+            format!("({}-${:02X})", index, displacement.unsigned_abs())
+        } else {
+            format!("({}+${:02X})", index, displacement)
+        }
+    }
+
+    // 8-bit register, 'H' and 'L' are the index halves (unless the
+    // instruction also uses '(IX+d)').
+    fn r(&mut self, index: u8, halves: bool) -> String {
+        match (index, self.index) {
+            (6, Some(_)) => self.indexed(),
+            (4, Some(register)) if halves => format!("{}H", register),
+            (5, Some(register)) if halves => format!("{}L", register),
+            _ => R[index as usize].to_string(),
+        }
+    }
+
+    fn hl(&self) -> &'static str {
+        self.index.unwrap_or("HL")
+    }
+
+    fn rp(&self, index: u8) -> &'static str {
+        if index == 2 {
+            self.hl()
+        } else {
+            RP[index as usize]
+        }
+    }
+
+    fn rp2(&self, index: u8) -> &'static str {
+        if index == 2 {
+            self.hl()
+        } else {
+            RP2[index as usize]
+        }
+    }
+
+    fn unprefixed(&mut self, op_code: u8) -> String {
+        let (x, y, z) = (op_code >> 6, (op_code >> 3) & 7, op_code & 7);
+        let (p, q) = (y >> 1, y & 1);
+        match (x, z) {
+            (0, 0) => match y {
+                0 => "NOP".to_string(),
+                1 => "EX AF,AF'".to_string(),
+                2 => format!("DJNZ {}", self.d()),
+                3 => format!("JR {}", self.d()),
+                _ => format!("JR {},{}", CC[y as usize - 4], self.d()),
+            },
+            (0, 1) => match q {
+                0 => format!("LD {},{}", self.rp(p), self.nn()),
+                _ => format!("ADD {},{}", self.hl(), self.rp(p)),
+            },
+            (0, 2) => match (q, p) {
+                (0, 0) => "LD (BC),A".to_string(),
+                (0, 1) => "LD (DE),A".to_string(),
+                (0, 2) => format!("LD ({}),{}", self.nn(), self.hl()),
+                (0, _) => format!("LD ({}),A", self.nn()),
+                (_, 0) => "LD A,(BC)".to_string(),
+                (_, 1) => "LD A,(DE)".to_string(),
+                (_, 2) => format!("LD {},({})", self.hl(), self.nn()),
+                (_, _) => format!("LD A,({})", self.nn()),
+            },
+            (0, 3) => match q {
+                0 => format!("INC {}", self.rp(p)),
+                _ => format!("DEC {}", self.rp(p)),
+            },
+            (0, 4) => format!("INC {}", self.r(y, true)),
+            (0, 5) => format!("DEC {}", self.r(y, true)),
+            (0, 6) => {
+                let register = self.r(y, true);
+                format!("LD {},{}", register, self.n())
+            }
+            (0, _) => {
+                ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"][y as usize].to_string()
+            }
+            (1, _) => {
+                if y == 6 && z == 6 {
+                    "HALT".to_string()
+                } else {
+                    let halves = y != 6 && z != 6;
+                    let destination = self.r(y, halves);
+                    format!("LD {},{}", destination, self.r(z, halves))
+                }
+            }
+            (2, _) => format!("{}{}", ALU[y as usize], self.r(z, true)),
+            (_, 0) => format!("RET {}", CC[y as usize]),
+            (_, 1) => match (q, p) {
+                (0, _) => format!("POP {}", self.rp2(p)),
+                (_, 0) => "RET".to_string(),
+                (_, 1) => "EXX".to_string(),
+                (_, 2) => format!("JP ({})", self.hl()),
+                (_, _) => format!("LD SP,{}", self.hl()),
+            },
+            (_, 2) => format!("JP {},{}", CC[y as usize], self.nn()),
+            (_, 3) => match y {
+                0 => format!("JP {}", self.nn()),
+                1 => self.cb(),
+                2 => format!("OUT ({}),A", self.n()),
+                3 => format!("IN A,({})", self.n()),
+                4 => format!("EX (SP),{}", self.hl()),
+                5 => "EX DE,HL".to_string(),
+                6 => "DI".to_string(),
+                _ => "EI".to_string(),
+            },
+            (_, 4) => format!("CALL {},{}", CC[y as usize], self.nn()),
+            (_, 5) => match (q, p) {
+                (0, _) => format!("PUSH {}", self.rp2(p)),
+                (_, 0) => format!("CALL {}", self.nn()),
+                (_, 2) => self.ed(),
+                (_, _) => "NOP".to_string(), // DD/FD prefix (handled by 'instruction').
+            },
+            (_, 6) => format!("{}{}", ALU[y as usize], self.n()),
+            (_, _) => format!("RST ${:02X}", y * 8),
+        }
+    }
+
+    fn cb(&mut self) -> String {
+        // With an index prefix, the displacement comes before the op code.
+        if self.index.is_some() {
+            self.indexed();
+        }
+        let op_code = self.next();
+        let (x, y, z) = (op_code >> 6, (op_code >> 3) & 7, op_code & 7);
+        let operand = if self.index.is_some() {
+            self.indexed()
+        } else {
+            R[z as usize].to_string()
+        };
+        // Undocumented DDCB/FDCB forms also copy the result to a register.
+        let copy = if self.index.is_some() && z != 6 && x != 1 {
+            format!(",{}", R[z as usize])
+        } else {
+            String::new()
+        };
+        match x {
+            0 => format!("{} {}{}", ROT[y as usize], operand, copy),
+            1 => format!("BIT {},{}", y, operand),
+            2 => format!("RES {},{}{}", y, operand, copy),
+            _ => format!("SET {},{}{}", y, operand, copy),
+        }
+    }
+
+    fn ed(&mut self) -> String {
+        // The index prefix has no effect on ED instructions.
+        self.index = None;
+        let op_code = self.next();
+        let (x, y, z) = (op_code >> 6, (op_code >> 3) & 7, op_code & 7);
+        let (p, q) = (y >> 1, y & 1);
+        match (x, z) {
+            (1, 0) if y == 6 => "IN F,(C)".to_string(),
+            (1, 0) => format!("IN {},(C)", R[y as usize]),
+            (1, 1) if y == 6 => "OUT (C),0".to_string(),
+            (1, 1) => format!("OUT (C),{}", R[y as usize]),
+            (1, 2) if q == 0 => format!("SBC HL,{}", RP[p as usize]),
+            (1, 2) => format!("ADC HL,{}", RP[p as usize]),
+            (1, 3) if q == 0 => format!("LD ({}),{}", self.nn(), RP[p as usize]),
+            (1, 3) => format!("LD {},({})", RP[p as usize], self.nn()),
+            (1, 4) => "NEG".to_string(),
+            (1, 5) if y == 1 => "RETI".to_string(),
+            (1, 5) => "RETN".to_string(),
+            (1, 6) => format!("IM {}", IM[y as usize]),
+            (1, _) => [
+                "LD I,A", "LD R,A", "LD A,I", "LD A,R", "RRD", "RLD", "NOP", "NOP",
+            ][y as usize]
+                .to_string(),
+            (2, 0..=3) if y >= 4 => BLOCK[y as usize - 4][z as usize].to_string(),
+            _ => "NOP".to_string(), // Invalid, runs as a (two byte) NOP.
+        }
+    }
+
+    fn instruction(&mut self) -> String {
+        let op_code = self.next();
+        match op_code {
+            0xDD | 0xFD => {
+                let next = (self.read)(self.address.wrapping_add(self.length));
+                if matches!(next, 0xDD | 0xED | 0xFD) {
+                    // A prefix followed by another prefix does nothing.
+                    return "NOP".to_string();
+                }
+                self.index = Some(if op_code == 0xDD { "IX" } else { "IY" });
+                let op_code = self.next();
+                self.unprefixed(op_code)
+            }
+            0xED => self.ed(),
+            _ => self.unprefixed(op_code),
+        }
+    }
+}
+
+impl Disassembler {
+    // Disassemble the instruction at 'address', reading the bytes with 'read'.
+    pub fn disassemble(read: &dyn Fn(u16) -> u8, address: u16) -> Disassembly {
+        let mut decoder = Decoder {
+            read,
+            address,
+            length: 0,
+            index: None,
+            displacement: None,
+        };
+        let text = decoder.instruction();
+        Disassembly {
+            text,
+            length: decoder.length,
+        }
+    }
+
+    // Listing of 'length' bytes from 'address', one instruction a line:
+    // address, bytes, then the instruction.
+    pub fn listing(read: &dyn Fn(u16) -> u8, address: u16, length: u32) -> String {
+        let mut listing = String::new();
+        let mut offset: u32 = 0;
+        while offset < length {
+            let instruction_address = address.wrapping_add(offset as u16);
+            let disassembly = Disassembler::disassemble(read, instruction_address);
+            let bytes: Vec<String> = (0..disassembly.length)
+                .map(|i| format!("{:02X}", read(instruction_address.wrapping_add(i))))
+                .collect();
+            listing.push_str(&format!(
+                "{:04X}: {:<12} {}\n",
+                instruction_address,
+                bytes.join(" "),
+                disassembly.text
+            ));
+            offset += disassembly.length as u32;
+        }
+        listing
+    }
+
+    // Listing of a 16K ROM bank, at the address it's normally paged in at
+    // (bank 0 at 0x0000, bank 1 at 0x4000, others at 0x8000).
+    pub fn bank_listing(rom: &[u8], bank: usize) -> Result<String, String> {
+        const BANK_SIZE: usize = 0x4000;
+        let start = bank * BANK_SIZE;
+        if start >= rom.len() {
+            return Err(format!(
+                "Bank {} is past the end of the ROM ({} banks).",
+                bank,
+                rom.len().div_ceil(BANK_SIZE)
+            ));
+        }
+        let data = &rom[start..rom.len().min(start + BANK_SIZE)];
+        let origin = (bank.min(2) * BANK_SIZE) as u16;
+        let read = |address: u16| {
+            data.get(address.wrapping_sub(origin) as usize)
+                .copied()
+                .unwrap_or(0)
+        };
+        Ok(Disassembler::listing(&read, origin, data.len() as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::cpu::disassembler;
+
+    fn disassemble(bytes: &[u8]) -> (String, u16) {
+        let read = |address: u16| bytes.get(address as usize).copied().unwrap_or(0);
+        let disassembly = disassembler::Disassembler::disassemble(&read, 0);
+        (disassembly.text, disassembly.length)
+    }
+
+    #[test]
+    fn test_unprefixed() {
+        for (bytes, text) in [
+            (&[0x00][..], "NOP"),
+            (&[0x08], "EX AF,AF'"),
+            (&[0x10, 0xFE], "DJNZ $0000"),
+            (&[0x20, 0x05], "JR NZ,$0007"),
+            (&[0x21, 0x34, 0x12], "LD HL,$1234"),
+            (&[0x32, 0x00, 0xC0], "LD ($C000),A"),
+            (&[0x36, 0x7F], "LD (HL),$7F"),
+            (&[0x41], "LD B,C"),
+            (&[0x76], "HALT"),
+            (&[0x9E], "SBC A,(HL)"),
+            (&[0xC2, 0x00, 0x80], "JP NZ,$8000"),
+            (&[0xD3, 0xBF], "OUT ($BF),A"),
+            (&[0xE3], "EX (SP),HL"),
+            (&[0xF5], "PUSH AF"),
+            (&[0xFE, 0x10], "CP $10"),
+            (&[0xFF], "RST $38"),
+        ] {
+            assert_eq!(disassemble(bytes), (text.to_string(), bytes.len() as u16));
+        }
+    }
+
+    #[test]
+    fn test_prefixed() {
+        for (bytes, text) in [
+            (&[0xCB, 0x07][..], "RLC A"),
+            (&[0xCB, 0x36], "SLL (HL)"),
+            (&[0xCB, 0x7E], "BIT 7,(HL)"),
+            (&[0xCB, 0xC1], "SET 0,C"),
+            (&[0xED, 0x44], "NEG"),
+            (&[0xED, 0x4C], "NEG"),
+            (&[0xED, 0x70], "IN F,(C)"),
+            (&[0xED, 0x71], "OUT (C),0"),
+            (&[0xED, 0x43, 0x00, 0xD0], "LD ($D000),BC"),
+            (&[0xED, 0x56], "IM 1"),
+            (&[0xED, 0xB0], "LDIR"),
+            (&[0xED, 0x00], "NOP"),
+            (&[0xDD, 0x21, 0x00, 0x10], "LD IX,$1000"),
+            (&[0xDD, 0x7E, 0x05], "LD A,(IX+$05)"),
+            (&[0xFD, 0x66, 0xFE], "LD H,(IY-$02)"),
+            (&[0xFD, 0x75, 0x00], "LD (IY+$00),L"),
+            (&[0xDD, 0x36, 0x03, 0x42], "LD (IX+$03),$42"),
+            (&[0xDD, 0x65], "LD IXH,IXL"),
+            (&[0xFD, 0x84], "ADD A,IYH"),
+            (&[0xDD, 0x09], "ADD IX,BC"),
+            (&[0xDD, 0xE9], "JP (IX)"),
+            (&[0xDD, 0xEB], "EX DE,HL"),
+            (&[0xDD, 0xDD], "NOP"),
+            (&[0xDD, 0xCB, 0x02, 0x06], "RLC (IX+$02)"),
+            (&[0xFD, 0xCB, 0xFF, 0x46], "BIT 0,(IY-$01)"),
+            (&[0xFD, 0xCB, 0x01, 0x4F], "BIT 1,(IY+$01)"),
+            (&[0xDD, 0xCB, 0x04, 0xC0], "SET 0,(IX+$04),B"),
+        ] {
+            let expected_length = if bytes == [0xDD, 0xDD] {
+                1
+            } else {
+                bytes.len() as u16
+            };
+            assert_eq!(disassemble(bytes), (text.to_string(), expected_length));
+        }
+    }
+
+    #[test]
+    fn test_bank_listing() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x4000..0x4004].copy_from_slice(&[0xC3, 0x00, 0x40, 0x00]);
+        let listing = disassembler::Disassembler::bank_listing(&rom, 1).unwrap();
+        let mut lines = listing.lines();
+        assert_eq!(lines.next(), Some("4000: C3 00 40     JP $4000"));
+        assert_eq!(lines.next(), Some("4003: 00           NOP"));
+        assert!(disassembler::Disassembler::bank_listing(&rom, 2).is_err());
+    }
+}
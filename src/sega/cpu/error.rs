@@ -0,0 +1,67 @@
+use std::fmt;
+
+// An opcode the CPU can't execute, returned by 'Core::step' (rather than
+// panicking) so the frontend can decide how to carry on.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CpuError {
+    pub prefix: Vec<u8>, // Eg, [0xDD, 0xCB], empty for an unprefixed opcode.
+    pub op_code: u8,
+    pub pc: u16, // Start of the instruction.
+    pub bank: Option<u8>,
+    pub trace: Vec<String>, // The instructions leading up to it (oldest first).
+}
+
+impl CpuError {
+    // The location and trace are filled in by 'Core::step'.
+    pub fn unimplemented(prefix: &[u8], op_code: u8) -> Self {
+        Self {
+            prefix: prefix.to_vec(),
+            op_code,
+            pc: 0,
+            bank: None,
+            trace: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Opcode not implemented:")?;
+        for byte in self.prefix.iter().chain([self.op_code].iter()) {
+            write!(f, " {:02X}", byte)?;
+        }
+        match self.bank {
+            Some(bank) => write!(f, " at {:02X}:{:04X}", bank, self.pc)?,
+            None => write!(f, " at {:04X}", self.pc)?,
+        }
+        if !self.trace.is_empty() {
+            write!(f, ", after:")?;
+            for line in &self.trace {
+                write!(f, "\n    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::cpu::error;
+
+    #[test]
+    fn test_error_message() {
+        let mut error = error::CpuError::unimplemented(&[0xED], 0xBA);
+        error.pc = 0x0103;
+        error.trace = vec!["--:0100 NOP".to_string(), "--:0101 LD A,$42".to_string()];
+        assert_eq!(
+            error.to_string(),
+            "Opcode not implemented: ED BA at 0103, after:\n    --:0100 NOP\n    --:0101 LD A,$42"
+        );
+        error.bank = Some(0x02);
+        error.trace.clear();
+        assert_eq!(
+            error.to_string(),
+            "Opcode not implemented: ED BA at 02:0103"
+        );
+    }
+}
@@ -81,41 +81,25 @@ pub fn ld_i_nn<M, R16>(
     clock.increment(14);
 }
 
-// LD I, (nn)
-// LD IX, (nn); LD IY, (nn)
-// was ld_i__nn
-pub fn ld_i_mem_nn<M, R16>(
-    clock: &mut clocks::Clock,
-    memory: &mut M,
-    pc_reg: &mut R16,
-    i16_reg: &mut R16,
-) where
-    M: memory::MemoryRW,
-    R16: pc_state::Reg16RW,
-{
-    i16_reg.set(memory.read16(memory.read16(pc_reg.get())));
-    pc_state::PcState::increment_reg(pc_reg, 2);
-    clock.increment(20);
-}
-
 // LD (nn), HL (Extended)
 // same as ld_nn_hl, but part of the extended group?
 // pub fn ld_nn_hl_extended
 // pub fn ld_nn_hl
 // pub fn ld_nn_I
-pub fn ld_mem_nn_reg16<M, R16>(
+pub fn ld_mem_nn_reg16<M>(
     clock: &mut clocks::Clock,
     memory: &mut M,
-    pc_reg: &mut R16,
-    reg16: &R16,
+    pc_state: &mut pc_state::PcState,
+    reg16_value: u16,
 ) where
     M: memory::MemoryRW,
-    R16: pc_state::Reg16RW,
 {
-    memory.write(memory.read16(pc_reg.get()), reg16.get_low());
-    memory.write(memory.read16(pc_reg.get()) + 1, reg16.get_high());
+    let address = memory.read16(pc_state.get_pc());
+    memory.write(address, (reg16_value & 0xFF) as u8);
+    memory.write(address.wrapping_add(1), (reg16_value >> 8) as u8);
+    pc_state.set_memptr(address.wrapping_add(1));
 
-    pc_state::PcState::increment_reg(pc_reg, 2);
+    pc_state.increment_pc(2);
     clock.increment(20);
 }
 
@@ -131,7 +115,9 @@ pub fn ld_dd_mem_nn<M, F: FnMut(&mut pc_state::PcState, u16)>(
 ) where
     M: memory::MemoryRW,
 {
-    reg16(pc_state, memory.read16(memory.read16(pc_state.get_pc())));
+    let address = memory.read16(pc_state.get_pc());
+    reg16(pc_state, memory.read16(address));
+    pc_state.set_memptr(address.wrapping_add(1));
 
     pc_state.increment_pc(2);
     clock.increment(20);
@@ -255,25 +241,35 @@ pub fn push_i<M, R16>(
     clock.increment(15);
 }
 
+// Flags for LDI, LDD, LDIR and LDDR, 'value' is the byte copied.  X/Y come
+// from bits 3 and 1 of (A + value).
+fn block_load_flags(pc_state: &mut pc_state::PcState, value: u8) {
+    let n = pc_state.get_a().wrapping_add(value);
+    let mut f_status = pc_state.get_f();
+    f_status.set_h(0);
+    f_status.set_n(0);
+    f_status.set_pv((pc_state.bc_reg.get() != 0) as u8);
+    f_status.set_x1((n >> 3) & 0x1);
+    f_status.set_x2((n >> 1) & 0x1);
+    pc_state.set_f(f_status);
+}
+
 // LDDR
 pub fn lddr<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut pc_state::PcState)
 where
     M: memory::MemoryRW,
 {
-    memory.write(pc_state.de_reg.get(), memory.read(pc_state.hl_reg.get()));
+    let value = memory.read(pc_state.hl_reg.get());
+    memory.write(pc_state.de_reg.get(), value);
     pc_state::PcState::increment_reg(&mut pc_state.de_reg, -1);
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, -1);
     pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
+    block_load_flags(pc_state, value);
     if pc_state.bc_reg.get() == 0 {
-        let mut f_status = pc_state.get_f();
-        f_status.set_h(0);
-        f_status.set_n(0);
-        f_status.set_pv(0);
-        pc_state.set_f(f_status);
-
         clock.increment(16);
     } else {
         pc_state::PcState::increment_reg(&mut pc_state.pc_reg, -2);
+        pc_state.set_memptr(pc_state.get_pc().wrapping_add(1));
         // This branch is longer because the PC is actually 'decremented' by two
         clock.increment(21);
     }
@@ -284,28 +280,23 @@ pub fn ldir<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut pc_stat
 where
     M: memory::MemoryRW,
 {
-    pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
-    memory.write(pc_state.de_reg.get(), memory.read(pc_state.hl_reg.get()));
+    let value = memory.read(pc_state.hl_reg.get());
+    memory.write(pc_state.de_reg.get(), value);
     pc_state::PcState::increment_reg(&mut pc_state.de_reg, 1);
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, 1);
-
-    let mut f_status = pc_state.get_f();
-    f_status.set_h(0);
-    f_status.set_pv(0);
+    pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
+    block_load_flags(pc_state, value);
     if pc_state.bc_reg.get() == 0 {
-        f_status.set_n(0);
         clock.increment(16);
     } else {
-        // This branch is longer because the PC is actually 'decremented' by two
-        f_status.set_n(1); // Not sure.
         pc_state::PcState::increment_reg(&mut pc_state.pc_reg, -2);
+        pc_state.set_memptr(pc_state.get_pc().wrapping_add(1));
+        // This branch is longer because the PC is actually 'decremented' by two
         clock.increment(21);
     }
-    pc_state.set_f(f_status);
 }
 
 // OTIR
-// Flags match emulator, not z80 document
 pub fn otir<M>(
     clock: &mut clocks::Clock,
     memory: &mut M,
@@ -314,48 +305,99 @@ pub fn otir<M>(
 ) where
     M: memory::MemoryRW,
 {
+    let value = memory.read(pc_state.hl_reg.get());
     pc_state.set_b(pc_state.get_b().wrapping_sub(1));
-    ports.port_write(clock, pc_state.get_c(), memory.read(pc_state.hl_reg.get()));
+    ports.port_write(clock, pc_state.get_c(), value);
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, 1);
+    pc_state.set_memptr(pc_state.get_bc().wrapping_add(1));
+    block_io_flags(pc_state, value, value as u16 + pc_state.get_l() as u16);
 
-    let mut f_status = pc_state.get_f();
-    // ??? status.set_c(0)
-    f_status.set_s(0); // Unknown
-    f_status.set_h(0); // Unknown
-    f_status.set_pv(0); // Unknown
-    f_status.set_n(1);
     if pc_state.get_b() == 0 {
-        f_status.set_z(1);
         clock.increment(16);
     } else {
-        f_status.set_z(0);
         pc_state.increment_pc(-2);
         clock.increment(21);
     }
+}
 
-    pc_state.set_f(f_status);
+// OTDR
+pub fn otdr<M>(
+    clock: &mut clocks::Clock,
+    memory: &mut M,
+    pc_state: &mut pc_state::PcState,
+    ports: &mut ports::Ports,
+) where
+    M: memory::MemoryRW,
+{
+    let value = memory.read(pc_state.hl_reg.get());
+    pc_state.set_b(pc_state.get_b().wrapping_sub(1));
+    ports.port_write(clock, pc_state.get_c(), value);
+    pc_state::PcState::increment_reg(&mut pc_state.hl_reg, -1);
+    pc_state.set_memptr(pc_state.get_bc().wrapping_sub(1));
+    block_io_flags(pc_state, value, value as u16 + pc_state.get_l() as u16);
+
+    if pc_state.get_b() == 0 {
+        clock.increment(16);
+    } else {
+        pc_state.increment_pc(-2);
+        clock.increment(21);
+    }
 }
 
-// EX SP I
-pub fn ex_sp_i<M, R16>(
+// INIR
+pub fn inir<M>(
     clock: &mut clocks::Clock,
     memory: &mut M,
-    pc_reg: &mut R16,
-    sp_reg: &mut R16,
-    i16_reg: &mut R16,
+    pc_state: &mut pc_state::PcState,
+    ports: &mut ports::Ports,
 ) where
     M: memory::MemoryRW,
-    R16: pc_state::Reg16RW,
 {
-    let mut tmp8 = memory.read(sp_reg.get());
-    memory.write(sp_reg.get(), i16_reg.get_low());
-    i16_reg.set_low(tmp8);
+    pc_state.set_memptr(pc_state.get_bc().wrapping_add(1));
+    pc_state.set_b(pc_state.get_b().wrapping_sub(1));
+    let value = ports.port_read(clock, pc_state.get_c());
+    memory.write(pc_state.hl_reg.get(), value);
+    pc_state::PcState::increment_reg(&mut pc_state.hl_reg, 1);
+    block_io_flags(
+        pc_state,
+        value,
+        value as u16 + pc_state.get_c().wrapping_add(1) as u16,
+    );
 
-    tmp8 = memory.read(sp_reg.get() + 1);
-    memory.write(sp_reg.get() + 1, i16_reg.get_high());
-    i16_reg.set_high(tmp8);
+    if pc_state.get_b() == 0 {
+        clock.increment(16);
+    } else {
+        pc_state.increment_pc(-2);
+        clock.increment(21);
+    }
+}
 
-    clock.increment(23);
+// INDR
+pub fn indr<M>(
+    clock: &mut clocks::Clock,
+    memory: &mut M,
+    pc_state: &mut pc_state::PcState,
+    ports: &mut ports::Ports,
+) where
+    M: memory::MemoryRW,
+{
+    pc_state.set_memptr(pc_state.get_bc().wrapping_sub(1));
+    pc_state.set_b(pc_state.get_b().wrapping_sub(1));
+    let value = ports.port_read(clock, pc_state.get_c());
+    memory.write(pc_state.hl_reg.get(), value);
+    pc_state::PcState::increment_reg(&mut pc_state.hl_reg, -1);
+    block_io_flags(
+        pc_state,
+        value,
+        value as u16 + pc_state.get_c().wrapping_sub(1) as u16,
+    );
+
+    if pc_state.get_b() == 0 {
+        clock.increment(16);
+    } else {
+        pc_state.increment_pc(-2);
+        clock.increment(21);
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////
@@ -380,21 +422,19 @@ pub fn bit_b_r<R16, F16>(
 }
 
 // BIT b, (HL)
-pub fn bit_b_mem<M, R16, F16>(
+// X/Y come from the high byte of MEMPTR (left by an earlier instruction).
+pub fn bit_b_mem<M>(
     clock: &mut clocks::Clock,
     memory: &mut M,
     bit_pos: u8,
-    pc_reg: &mut R16,
-    af_reg: &mut F16,
-    addr_reg: &R16,
+    pc_state: &mut pc_state::PcState,
 ) where
     M: memory::MemoryRW,
-    R16: pc_state::Reg16RW,
-    F16: pc_state::FlagReg,
 {
-    let mut f_status = af_reg.get_flags();
-    status_flags::set_bit_test_flags(memory.read(addr_reg.get()), bit_pos, &mut f_status);
-    af_reg.set_flags(&f_status);
+    let mut f_status = pc_state.get_f();
+    status_flags::set_bit_test_flags(memory.read(pc_state.get_hl()), bit_pos, &mut f_status);
+    status_flags::xy_flags(&mut f_status, (pc_state.get_memptr() >> 8) as u8);
+    pc_state.set_f(f_status);
     clock.increment(12);
 }
 
@@ -459,17 +499,20 @@ pub fn res_b_mem<M, R16>(
     clock.increment(12);
 }
 
-// BIT b, (IY+d),  BIT b, (IX+d) (if mem at pc + 3 -> 0b01XXXXXX)
-// RES b, (IY+d),  RES b, (IX+d) (if mem at pc + 3 -> 0b10XXXXXX)
-// SET b, (IY+d),  SET b, (IX+d) (if mem at pc + 3 -> 0b11XXXXXX)
-// (if mem at pc + 3 -> 0b11XXXXXX) -> ERROR
-pub fn bit_res_set_b_i_d<M, R16, F16>(
+// RLC, RRC, RL, RR, SLA, SRA, SLL, SRL (IY+d) (if mem at pc + 3 -> 0b00oooXXX)
+// BIT b, (IY+d),  BIT b, (IX+d) (if mem at pc + 3 -> 0b01bbbXXX)
+// RES b, (IY+d),  RES b, (IX+d) (if mem at pc + 3 -> 0b10bbbXXX)
+// SET b, (IY+d),  SET b, (IX+d) (if mem at pc + 3 -> 0b11bbbXXX)
+// Returns the value written back (not for BIT), which the undocumented
+// variants also copy to a register.
+pub fn rot_bit_res_set_i_d<M, R16, F16>(
     clock: &mut clocks::Clock,
     memory: &mut M,
     pc_reg: &mut R16,
     af_reg: &mut F16,
     i16_reg: &R16,
-) where
+) -> Option<u8>
+where
     M: memory::MemoryRW,
     R16: pc_state::Reg16RW,
     F16: pc_state::FlagReg,
@@ -478,31 +521,39 @@ pub fn bit_res_set_b_i_d<M, R16, F16>(
     let test_value = memory.read(tmp16);
     let op_details = memory.read(pc_reg.get() + 1);
     let bit_pos = (op_details >> 3) & 0x7;
+    pc_state::PcState::increment_reg(pc_reg, 2);
 
-    match op_details >> 6 {
+    let new_value = match op_details >> 6 {
+        0b00 => {
+            /* Rotate/shift */
+            let mut f_status = af_reg.get_flags();
+            let (new_value, carry) =
+                rotate_shift_by_index(bit_pos, test_value, f_status.get_c() == 1);
+            status_flags::set_shift_register_flags(new_value, carry, &mut f_status);
+            af_reg.set_flags(&f_status);
+            new_value
+        }
         0b01 => {
-            /* BIT b */
+            /* BIT b, X/Y come from the high byte of the address */
             let mut f_status = af_reg.get_flags();
             status_flags::set_bit_test_flags(test_value, bit_pos, &mut f_status);
+            status_flags::xy_flags(&mut f_status, (tmp16 >> 8) as u8);
             af_reg.set_flags(&f_status);
             clock.increment(20);
+            return None;
         }
         0b10 => {
             /* RES b */
-            memory.write(tmp16, test_value & !(0x1 << bit_pos));
-            clock.increment(23);
-        }
-        0b11 => {
-            /* SET b */
-            memory.write(tmp16, test_value | (0x1 << bit_pos));
-            clock.increment(23);
+            test_value & !(0x1 << bit_pos)
         }
         _ => {
-            panic!("Unsupported byte value! {}", op_details);
+            /* SET b */
+            test_value | (0x1 << bit_pos)
         }
-    }
-
-    pc_state::PcState::increment_reg(pc_reg, 2);
+    };
+    memory.write(tmp16, new_value);
+    clock.increment(23);
+    Some(new_value)
 }
 
 ///////////////////////////////////////////////////////////////////////
@@ -541,29 +592,32 @@ pub fn cp_i_d<M, R16, AF>(
     clock.increment(19);
 }
 
+// Flags for CPI, CPD, CPIR and CPDR (carry not affected).  X/Y come from
+// bits 3 and 1 of (A - value - H).
+fn block_compare_flags(pc_state: &mut pc_state::PcState, value: u8) {
+    let original_carry = pc_state.get_f().get_c();
+    // This function sets the 'pc_state.f'
+    let result = instruction_set::sub8(pc_state.get_a(), value, &mut pc_state.af_reg);
+    let mut f_status = pc_state.get_f();
+    let n = result.wrapping_sub(f_status.get_h());
+    f_status.set_c(original_carry);
+    f_status.set_pv((pc_state.bc_reg.get() != 0) as u8);
+    f_status.set_x1((n >> 3) & 0x1);
+    f_status.set_x2((n >> 1) & 0x1);
+    pc_state.set_f(f_status);
+}
+
 // CPI
 // Compare accumulator with contents of memory address HL, increment HL
 pub fn cpi<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut pc_state::PcState)
 where
     M: memory::MemoryRW,
 {
-    // This function sets the 'pc_state.f'
-    instruction_set::cp_flags(
-        pc_state.get_a(),
-        memory.read(pc_state.hl_reg.get()),
-        &mut pc_state.af_reg,
-    );
-
+    let value = memory.read(pc_state.hl_reg.get());
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, 1);
     pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
-    let mut f_status = pc_state.get_f();
-    if pc_state.bc_reg.get() == 0 {
-        f_status.set_pv(1);
-    } else {
-        f_status.set_pv(0);
-    }
-
-    pc_state.set_f(f_status);
+    block_compare_flags(pc_state, value);
+    pc_state.set_memptr(pc_state.get_memptr().wrapping_add(1));
 
     clock.increment(16);
 }
@@ -574,23 +628,11 @@ pub fn cpd<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut pc_state
 where
     M: memory::MemoryRW,
 {
-    // This function sets the 'pc_state.f'
-    instruction_set::cp_flags(
-        pc_state.get_a(),
-        memory.read(pc_state.hl_reg.get()),
-        &mut pc_state.af_reg,
-    );
-
+    let value = memory.read(pc_state.hl_reg.get());
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, -1);
     pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
-    let mut f_status = pc_state.get_f();
-    if pc_state.bc_reg.get() == 0 {
-        f_status.set_pv(1);
-    } else {
-        f_status.set_pv(0);
-    }
-
-    pc_state.set_f(f_status);
+    block_compare_flags(pc_state, value);
+    pc_state.set_memptr(pc_state.get_memptr().wrapping_sub(1));
 
     clock.increment(16);
 }
@@ -601,21 +643,13 @@ pub fn ldi<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut pc_state
 where
     M: memory::MemoryRW,
 {
-    memory.write(pc_state.de_reg.get(), memory.read(pc_state.hl_reg.get()));
+    let value = memory.read(pc_state.hl_reg.get());
+    memory.write(pc_state.de_reg.get(), value);
 
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, 1);
     pc_state::PcState::increment_reg(&mut pc_state.de_reg, 1);
     pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
-    let mut f_status = pc_state.get_f();
-    if pc_state.bc_reg.get() == 0 {
-        f_status.set_pv(1);
-    } else {
-        f_status.set_pv(0);
-    }
-    f_status.set_h(0);
-    f_status.set_n(0);
-
-    pc_state.set_f(f_status);
+    block_load_flags(pc_state, value);
 
     clock.increment(16);
 }
@@ -626,21 +660,13 @@ pub fn ldd<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut pc_state
 where
     M: memory::MemoryRW,
 {
-    memory.write(pc_state.de_reg.get(), memory.read(pc_state.hl_reg.get()));
+    let value = memory.read(pc_state.hl_reg.get());
+    memory.write(pc_state.de_reg.get(), value);
 
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, -1);
     pc_state::PcState::increment_reg(&mut pc_state.de_reg, -1);
     pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
-    let mut f_status = pc_state.get_f();
-    if pc_state.bc_reg.get() == 0 {
-        f_status.set_pv(1);
-    } else {
-        f_status.set_pv(0);
-    }
-    f_status.set_h(0);
-    f_status.set_n(0);
-
-    pc_state.set_f(f_status);
+    block_load_flags(pc_state, value);
 
     clock.increment(16);
 }
@@ -651,27 +677,18 @@ pub fn cpir<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut pc_stat
 where
     M: memory::MemoryRW,
 {
-    // This function sets the 'pc_state.f'
-    let original_carry = pc_state.get_f().get_c();
-    pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
-    instruction_set::cp_flags(
-        pc_state.get_a(),
-        memory.read(pc_state.hl_reg.get()),
-        &mut pc_state.af_reg,
-    );
+    let value = memory.read(pc_state.hl_reg.get());
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, 1);
-    let mut f_status = pc_state.get_f();
-    f_status.set_c(original_carry);
-    if (pc_state.bc_reg.get() == 0) || f_status.get_z() == 1 {
-        f_status.set_pv(0);
+    pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
+    block_compare_flags(pc_state, value);
+    if (pc_state.bc_reg.get() == 0) || pc_state.get_f().get_z() == 1 {
+        pc_state.set_memptr(pc_state.get_memptr().wrapping_add(1));
         clock.increment(16);
     } else {
-        f_status.set_pv(1);
         pc_state.increment_pc(-2);
+        pc_state.set_memptr(pc_state.get_pc().wrapping_add(1));
         clock.increment(21);
     }
-
-    pc_state.set_f(f_status);
 }
 
 // CPDR
@@ -680,27 +697,18 @@ pub fn cpdr<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut pc_stat
 where
     M: memory::MemoryRW,
 {
-    // This function sets the 'pc_state.f'
-    let original_carry = pc_state.get_f().get_c();
-    pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
-    instruction_set::cp_flags(
-        pc_state.get_a(),
-        memory.read(pc_state.hl_reg.get()),
-        &mut pc_state.af_reg,
-    );
+    let value = memory.read(pc_state.hl_reg.get());
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, -1);
-    let mut f_status = pc_state.get_f();
-    f_status.set_c(original_carry);
-    if (pc_state.bc_reg.get() == 0) || f_status.get_z() == 1 {
-        f_status.set_pv(0);
+    pc_state::PcState::increment_reg(&mut pc_state.bc_reg, -1);
+    block_compare_flags(pc_state, value);
+    if (pc_state.bc_reg.get() == 0) || pc_state.get_f().get_z() == 1 {
+        pc_state.set_memptr(pc_state.get_memptr().wrapping_sub(1));
         clock.increment(16);
     } else {
-        f_status.set_pv(1);
         pc_state.increment_pc(-2);
+        pc_state.set_memptr(pc_state.get_pc().wrapping_add(1));
         clock.increment(21);
     }
-
-    pc_state.set_f(f_status);
 }
 
 // RTI
@@ -715,6 +723,22 @@ where
     pc_state.increment_sp(1);
     pc_state.set_pc_high(memory.read(pc_state.sp_reg.get()));
     pc_state.increment_sp(1);
+    pc_state.set_memptr(pc_state.get_pc());
+
+    clock.increment(14);
+}
+
+// RETN, return from the non-maskable interrupt (restore IFF1)
+pub fn retn<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut pc_state::PcState)
+where
+    M: memory::MemoryRW,
+{
+    pc_state.set_pc_low(memory.read(pc_state.sp_reg.get()));
+    pc_state.increment_sp(1);
+    pc_state.set_pc_high(memory.read(pc_state.sp_reg.get()));
+    pc_state.increment_sp(1);
+    pc_state.set_iff1(pc_state.get_iff2());
+    pc_state.set_memptr(pc_state.get_pc());
 
     clock.increment(14);
 }
@@ -886,6 +910,33 @@ pub fn sub_i_d<M, R16, F16>(
     clock.increment(19);
 }
 
+// SBC A, (IX+d),
+// SBC A, (IY+d),
+pub fn sbc_i_d<M, R16, F16>(
+    clock: &mut clocks::Clock,
+    memory: &mut M,
+    pc_reg: &mut R16,
+    i16_reg: &R16,
+    af_reg: &mut F16,
+) where
+    M: memory::MemoryRW,
+    R16: pc_state::Reg16RW,
+    F16: pc_state::FlagReg + pc_state::AfRegister,
+{
+    let address = get_i_d_address(memory, pc_reg, i16_reg);
+
+    let new_value = instruction_set::sub8c(
+        af_reg.get_a(),
+        memory.read(address),
+        af_reg.get_flags().get_c() == 1,
+        af_reg,
+    );
+    af_reg.set_a(new_value);
+
+    pc_state::PcState::increment_reg(pc_reg, 1);
+    clock.increment(19);
+}
+
 // AND (IX+d),
 // AND (IY+d),
 pub fn and_i_d<M, R16, F16>(
@@ -1003,6 +1054,21 @@ fn common_rotate_shift<F: FnMut(&mut pc_state::PcState, u8), Rot: Fn(u8, bool) -
     clock.increment(8);
 }
 
+// The rotate/shift selected by bits 3-5 of a 0xCB op code:
+// RLC, RRC, RL, RR, SLA, SRA, SLL, SRL
+fn rotate_shift_by_index(index: u8, input: u8, carry: bool) -> (u8, bool) {
+    match index & 0x7 {
+        0 => instruction_set::rotate_left_carry(input),
+        1 => instruction_set::rotate_right_carry(input),
+        2 => instruction_set::rotate_left(input, carry),
+        3 => instruction_set::rotate_right(input, carry),
+        4 => instruction_set::shift_left_arithmetic(input),
+        5 => instruction_set::shift_right_arithmetic(input),
+        6 => instruction_set::shift_left_logical(input),
+        _ => instruction_set::shift_right_logical(input),
+    }
+}
+
 // RRC r
 // Rotate Right with carry
 pub fn rrc_r<F: FnMut(&mut pc_state::PcState, u8)>(
@@ -1179,6 +1245,52 @@ pub fn rrc_hl<M, R16, F16>(
     clock.increment(15);
 }
 
+// RL (HL)
+pub fn rl_hl<M, R16, F16>(
+    clock: &mut clocks::Clock,
+    memory: &mut M,
+    pc_reg: &mut R16,
+    af_reg: &mut F16,
+    addr_reg: &R16,
+) where
+    M: memory::MemoryRW,
+    R16: pc_state::Reg16RW,
+    F16: pc_state::FlagReg,
+{
+    let src = memory.read(addr_reg.get());
+    let mut f_value = af_reg.get_flags();
+
+    let (new_value, carry) = instruction_set::rotate_left(src, f_value.get_c() == 1);
+    status_flags::set_shift_register_flags(new_value, carry, &mut f_value);
+    af_reg.set_flags(&f_value);
+    memory.write(addr_reg.get(), new_value);
+
+    clock.increment(15);
+}
+
+// RR (HL)
+pub fn rr_hl<M, R16, F16>(
+    clock: &mut clocks::Clock,
+    memory: &mut M,
+    pc_reg: &mut R16,
+    af_reg: &mut F16,
+    addr_reg: &R16,
+) where
+    M: memory::MemoryRW,
+    R16: pc_state::Reg16RW,
+    F16: pc_state::FlagReg,
+{
+    let src = memory.read(addr_reg.get());
+    let mut f_value = af_reg.get_flags();
+
+    let (new_value, carry) = instruction_set::rotate_right(src, f_value.get_c() == 1);
+    status_flags::set_shift_register_flags(new_value, carry, &mut f_value);
+    af_reg.set_flags(&f_value);
+    memory.write(addr_reg.get(), new_value);
+
+    clock.increment(15);
+}
+
 // SLA (HL)
 pub fn sla_hl<M, R16, F16>(
     clock: &mut clocks::Clock,
@@ -1225,6 +1337,29 @@ pub fn sra_hl<M, R16, F16>(
     clock.increment(15);
 }
 
+// SLL (HL), undocumented
+pub fn sll_hl<M, R16, F16>(
+    clock: &mut clocks::Clock,
+    memory: &mut M,
+    pc_reg: &mut R16,
+    af_reg: &mut F16,
+    addr_reg: &R16,
+) where
+    M: memory::MemoryRW,
+    R16: pc_state::Reg16RW,
+    F16: pc_state::FlagReg,
+{
+    let src = memory.read(addr_reg.get());
+    let mut f_value = af_reg.get_flags();
+
+    let (new_value, carry) = instruction_set::shift_left_logical(src);
+    status_flags::set_shift_register_flags(new_value, carry, &mut f_value);
+    af_reg.set_flags(&f_value);
+    memory.write(addr_reg.get(), new_value);
+
+    clock.increment(15);
+}
+
 // SRL (HL)
 pub fn srl_hl<M, R16, F16>(
     clock: &mut clocks::Clock,
@@ -1270,6 +1405,7 @@ where
     status_flags::rotate_decimal_flags(&mut f_value, new_value);
 
     pc_state.set_f(f_value);
+    pc_state.set_memptr(pc_state.get_hl().wrapping_add(1));
 
     clock.increment(18);
 }
@@ -1295,6 +1431,7 @@ where
     let mut f_value = pc_state.get_f();
     status_flags::rotate_decimal_flags(&mut f_value, new_value);
     pc_state.set_f(f_value);
+    pc_state.set_memptr(pc_state.get_hl().wrapping_add(1));
 
     clock.increment(18);
 }
@@ -1307,7 +1444,15 @@ pub fn in_r<F: FnMut(&mut pc_state::PcState, u8)>(
     mut dst_fn: F,
     ports: &mut ports::Ports,
 ) {
-    dst_fn(pc_state, ports.port_read(clock, src_val));
+    let value = ports.port_read(clock, src_val);
+    pc_state.set_memptr(pc_state.get_bc().wrapping_add(1));
+    dst_fn(pc_state, value);
+
+    // Same flags as RRD/RLD (carry not affected).
+    let mut f_status = pc_state.get_f();
+    status_flags::rotate_decimal_flags(&mut f_status, value);
+    pc_state.set_f(f_status);
+
     clock.increment(12);
 }
 
@@ -1320,9 +1465,22 @@ pub fn out_r(
     ports: &mut ports::Ports,
 ) {
     ports.port_write(clock, src_val, out);
+    pc_state.set_memptr(pc_state.get_bc().wrapping_add(1));
     clock.increment(12);
 }
 
+// Flags for the block I/O instructions, 'value' is the byte transferred and
+// 'k' is it plus C +/- 1 (for input) or L (for output).
+fn block_io_flags(pc_state: &mut pc_state::PcState, value: u8, k: u16) {
+    let mut f_status = pc_state.get_f();
+    status_flags::zero_and_sign_flags(&mut f_status, pc_state.get_b());
+    f_status.set_n(value >> 7);
+    f_status.set_h((k > 0xFF) as u8);
+    f_status.set_c((k > 0xFF) as u8);
+    f_status.set_pv(status_flags::calculate_parity((k as u8 & 0x7) ^ pc_state.get_b()) as u8);
+    pc_state.set_f(f_status);
+}
+
 // OUTI
 pub fn outi<M>(
     clock: &mut clocks::Clock,
@@ -1332,18 +1490,12 @@ pub fn outi<M>(
 ) where
     M: memory::MemoryRW,
 {
+    let value = memory.read(pc_state.hl_reg.get());
     pc_state.set_b(pc_state.get_b().wrapping_sub(1));
-    ports.port_write(clock, pc_state.get_c(), memory.read(pc_state.hl_reg.get()));
+    ports.port_write(clock, pc_state.get_c(), value);
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, 1);
-
-    let mut f_status = pc_state.get_f();
-    if pc_state.get_b() == 0 {
-        f_status.set_z(1);
-    } else {
-        f_status.set_z(0);
-    }
-    f_status.set_n(1);
-    pc_state.set_f(f_status);
+    pc_state.set_memptr(pc_state.get_bc().wrapping_add(1));
+    block_io_flags(pc_state, value, value as u16 + pc_state.get_l() as u16);
 
     clock.increment(16);
 }
@@ -1357,21 +1509,39 @@ pub fn ini<M>(
 ) where
     M: memory::MemoryRW,
 {
+    pc_state.set_memptr(pc_state.get_bc().wrapping_add(1));
     pc_state.set_b(pc_state.get_b().wrapping_sub(1));
-    memory.write(
-        pc_state.hl_reg.get(),
-        ports.port_read(clock, pc_state.get_c()),
-    );
+    let value = ports.port_read(clock, pc_state.get_c());
+    memory.write(pc_state.hl_reg.get(), value);
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, 1);
+    block_io_flags(
+        pc_state,
+        value,
+        value as u16 + pc_state.get_c().wrapping_add(1) as u16,
+    );
 
-    let mut f_status = pc_state.get_f();
-    if pc_state.get_b() == 0 {
-        f_status.set_z(1);
-    } else {
-        f_status.set_z(0);
-    }
-    f_status.set_n(1);
-    pc_state.set_f(f_status);
+    clock.increment(16);
+}
+
+// IND
+pub fn ind<M>(
+    clock: &mut clocks::Clock,
+    memory: &mut M,
+    pc_state: &mut pc_state::PcState,
+    ports: &mut ports::Ports,
+) where
+    M: memory::MemoryRW,
+{
+    pc_state.set_memptr(pc_state.get_bc().wrapping_sub(1));
+    pc_state.set_b(pc_state.get_b().wrapping_sub(1));
+    let value = ports.port_read(clock, pc_state.get_c());
+    memory.write(pc_state.hl_reg.get(), value);
+    pc_state::PcState::increment_reg(&mut pc_state.hl_reg, -1);
+    block_io_flags(
+        pc_state,
+        value,
+        value as u16 + pc_state.get_c().wrapping_sub(1) as u16,
+    );
 
     clock.increment(16);
 }
@@ -1385,19 +1555,13 @@ pub fn outd<M>(
 ) where
     M: memory::MemoryRW,
 {
+    let value = memory.read(pc_state.hl_reg.get());
     pc_state.set_b(pc_state.get_b().wrapping_sub(1));
-    ports.port_write(clock, pc_state.get_c(), memory.read(pc_state.hl_reg.get()));
-
-    let mut f_status = pc_state.get_f();
-    if pc_state.get_b() == 0 {
-        f_status.set_z(1);
-    } else {
-        f_status.set_z(0);
-    }
-    f_status.set_n(1);
-    pc_state.set_f(f_status);
-
+    ports.port_write(clock, pc_state.get_c(), value);
     pc_state::PcState::increment_reg(&mut pc_state.hl_reg, -1);
+    pc_state.set_memptr(pc_state.get_bc().wrapping_sub(1));
+    block_io_flags(pc_state, value, value as u16 + pc_state.get_l() as u16);
+
     clock.increment(16);
 }
 
@@ -1412,51 +1576,39 @@ pub fn neg(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState) {
     clock.increment(8);
 }
 
-pub fn im_1(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState) {
-    pc_state.set_im(1);
+// IM 0, IM 1, IM 2
+pub fn im(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState, mode: u8) {
+    pc_state.set_im(mode);
     clock.increment(8);
 }
 
-pub fn sbc_hl_r16<R16, F16>(
-    clock: &mut clocks::Clock,
-    src_value: u16,
-    pc_reg: &mut R16,
-    hl_reg: &mut R16,
-    af_reg: &mut F16,
-) where
-    R16: pc_state::Reg16RW,
-    F16: pc_state::FlagReg,
-{
-    hl_reg.set(sub16c(
-        hl_reg.get(),
-        src_value,
-        af_reg.get_flags().get_c() == 1,
-        af_reg,
-    ));
-    clock.increment(15);
+// Invalid 0xED op codes do nothing (undocumented).
+pub fn nop_ed(clock: &mut clocks::Clock) {
+    clock.increment(8);
 }
 
-pub fn adc_hl_r16<R16, F16>(
-    clock: &mut clocks::Clock,
-    src_value: u16,
-    pc_reg: &mut R16,
-    hl_reg: &mut R16,
-    af_reg: &mut F16,
-) where
-    R16: pc_state::Reg16RW,
-    F16: pc_state::FlagReg,
-{
-    hl_reg.set(add16c(
-        hl_reg.get(),
+pub fn sbc_hl_r16(clock: &mut clocks::Clock, src_value: u16, pc_state: &mut pc_state::PcState) {
+    pc_state.set_memptr(pc_state.get_hl().wrapping_add(1));
+    let result = sub16c(
+        pc_state.get_hl(),
         src_value,
-        af_reg.get_flags().get_c() == 1,
-        af_reg,
-    ));
+        pc_state.get_f().get_c() == 1,
+        &mut pc_state.af_reg,
+    );
+    pc_state.set_hl(result);
     clock.increment(15);
 }
 
-pub fn sbc_i() {
-    panic!("SBC A, (IX+d), SBC A, (IY+d) not implemented");
+pub fn adc_hl_r16(clock: &mut clocks::Clock, src_value: u16, pc_state: &mut pc_state::PcState) {
+    pc_state.set_memptr(pc_state.get_hl().wrapping_add(1));
+    let result = add16c(
+        pc_state.get_hl(),
+        src_value,
+        pc_state.get_f().get_c() == 1,
+        &mut pc_state.af_reg,
+    );
+    pc_state.set_hl(result);
+    clock.increment(15);
 }
 
 #[cfg(test)]
@@ -19,7 +19,9 @@ pub fn in_a_n<M>(
 ) where
     M: memory::MemoryRW,
 {
-    pc_state.set_a(ports.port_read(clock, memory.read(pc_state.get_pc())));
+    let port = memory.read(pc_state.get_pc());
+    pc_state.set_memptr((((pc_state.get_a() as u16) << 8) | port as u16).wrapping_add(1));
+    pc_state.set_a(ports.port_read(clock, port));
     pc_state.increment_pc(1);
     clock.increment(11);
 }
@@ -34,7 +36,9 @@ pub fn out_n_a<M>(
 ) where
     M: memory::MemoryRW,
 {
-    ports.port_write(clock, memory.read(pc_state.pc_reg.get()), pc_state.get_a());
+    let port = memory.read(pc_state.pc_reg.get());
+    ports.port_write(clock, port, pc_state.get_a());
+    pc_state.set_memptr(((pc_state.get_a() as u16) << 8) | port.wrapping_add(1) as u16);
     pc_state.increment_pc(1);
     clock.increment(11);
 }
@@ -159,6 +163,11 @@ where
 {
     // CP flags calculated set the same as for subtaction, but the result is ignored.
     sub8(a, b, af_reg);
+
+    // Apart from X/Y, which are copied from the operand.
+    let mut f_status = af_reg.get_flags();
+    status_flags::xy_flags(&mut f_status, b);
+    af_reg.set_flags(&f_status);
 }
 
 // Subtract two 8 bit ints and the carry bit, set flags accordingly
@@ -174,95 +183,31 @@ where
     result
 }
 
-// Calculate the result of the DAA functio
-fn calculate_daa_add(pc_state: &mut pc_state::PcState) {
-    let upper = (pc_state.get_a() >> 4) & 0xF;
-    let lower = pc_state.get_a() & 0xF;
-
+// Decimal adjust the accumulator, after a BCD add (or subtract, if N is set).
+fn calculate_daa(pc_state: &mut pc_state::PcState) {
+    let a = pc_state.get_a();
     let mut f_status = pc_state.get_f();
 
-    if f_status.get_c() == 0 {
-        if (upper <= 9) && (f_status.get_h() == 0) && (lower <= 9) {
-            // Do nothing
-        } else if ((upper <= 8) && (f_status.get_h() == 0) && (0xA..=0xF).contains(&lower))
-            || ((upper <= 9) && (f_status.get_h() == 1) && (lower <= 0x3))
-        {
-            pc_state.set_a(pc_state.get_a().wrapping_add(0x06));
-        } else if (0xA..=0xF).contains(&upper) && (f_status.get_h() == 0) && (lower <= 0x9) {
-            pc_state.set_a(pc_state.get_a().wrapping_add(0x60));
-            f_status.set_c(1);
-        } else if ((0x9..=0xF).contains(&upper)
-            && (f_status.get_h() == 0)
-            && (0xA..=0xF).contains(&lower))
-            || ((0xA..=0xF).contains(&upper) && (f_status.get_h() == 1) && (lower <= 0x3))
-        {
-            pc_state.set_a(pc_state.get_a().wrapping_add(0x66));
-            f_status.set_c(1);
-        }
-    } else if (upper <= 0x2) && (f_status.get_h() == 0) && (lower <= 0x9) {
-        pc_state.set_a(pc_state.get_a().wrapping_add(0x60));
-    } else if ((upper <= 0x2) && (f_status.get_h() == 0) && (0xA..=0xF).contains(&lower))
-        || ((upper <= 0x3) && (f_status.get_h() == 1) && (lower <= 0x3))
-    {
-        pc_state.set_a(pc_state.get_a().wrapping_add(0x66));
+    let mut correction = 0;
+    if f_status.get_h() == 1 || (a & 0xF) > 0x9 {
+        correction |= 0x06;
     }
-
-    f_status.set_pv(u8::from(status_flags::calculate_parity(pc_state.get_a())));
-    if (pc_state.get_a() & 0x80) != 0 {
-        // Is negative
-        f_status.set_s(1);
-    } else {
-        f_status.set_s(0);
+    if f_status.get_c() == 1 || a > 0x99 {
+        correction |= 0x60;
+        f_status.set_c(1);
     }
 
-    if pc_state.get_a() == 0 {
-        // Is zero
-        f_status.set_z(1);
+    let result = if f_status.get_n() == 0 {
+        f_status.set_h(((a & 0xF) > 0x9) as u8);
+        a.wrapping_add(correction)
     } else {
-        f_status.set_z(0);
-    }
-
-    pc_state.set_f(f_status)
-}
-
-// Fcpu_state->IXME, table in z80 guide is wrong, need to check values by hand
-fn calculate_daa_sub(pc_state: &mut pc_state::PcState) {
-    let upper = (pc_state.get_a() >> 4) & 0xF;
-    let lower = pc_state.get_a() & 0xF;
-
-    let mut f_status = pc_state.get_f();
-
-    if f_status.get_c() == 0 {
-        if (upper <= 9) && (f_status.get_h() == 0) && (lower <= 9) {
-            // Do nothing
-        } else if (upper <= 0x8) && (f_status.get_h() == 1) && (0x6..=0xF).contains(&lower) {
-            pc_state.set_a(pc_state.get_a().wrapping_add(0xFA));
-        }
-    } else if (0x7..=0xF).contains(&upper) && (f_status.get_h() == 0) && (lower <= 0x9) {
-        pc_state.set_a(pc_state.get_a().wrapping_add(0xA0));
-    } else if (0x6..=0xF).contains(&upper)
-        && (f_status.get_h() == 1)
-        && (0x6..=0xF).contains(&lower)
-    {
-        f_status.set_h(0);
-        pc_state.set_a(pc_state.get_a().wrapping_add(0x9A));
-    }
-
-    f_status.set_pv(u8::from(status_flags::calculate_parity(pc_state.get_a())));
-    if (pc_state.get_a() & 0x80) != 0 {
-        // Is negative
-        f_status.set_s(1);
-    } else {
-        f_status.set_s(0);
-    }
-
-    if pc_state.get_a() == 0 {
-        // Is zero
-        f_status.set_z(1);
-    } else {
-        f_status.set_z(0);
-    }
+        f_status.set_h((f_status.get_h() == 1 && (a & 0xF) < 0x6) as u8);
+        a.wrapping_sub(correction)
+    };
 
+    f_status.set_pv(u8::from(status_flags::calculate_parity(result)));
+    status_flags::zero_and_sign_flags(&mut f_status, result);
+    pc_state.set_a(result);
     pc_state.set_f(f_status);
 }
 
@@ -314,6 +259,34 @@ pub fn ld_r_r<F: FnMut(&mut pc_state::PcState, u8)>(
     clock.increment(4);
 }
 
+// LD (BC), A; LD (DE), A
+pub fn ld_mem_a<M>(
+    clock: &mut clocks::Clock,
+    memory: &mut M,
+    pc_state: &mut pc_state::PcState,
+    address: u16,
+) where
+    M: memory::MemoryRW,
+{
+    memory.write(address, pc_state.get_a());
+    pc_state.set_memptr(((pc_state.get_a() as u16) << 8) | (address.wrapping_add(1) & 0xFF));
+    clock.increment(7);
+}
+
+// LD A, (BC); LD A, (DE)
+pub fn ld_a_mem<M>(
+    clock: &mut clocks::Clock,
+    memory: &mut M,
+    pc_state: &mut pc_state::PcState,
+    address: u16,
+) where
+    M: memory::MemoryRW,
+{
+    pc_state.set_a(memory.read(address));
+    pc_state.set_memptr(address.wrapping_add(1));
+    clock.increment(7);
+}
+
 // LD r, (16 REG)
 // eg LD r, (HL)
 pub fn ld_r_mem<M, F: FnMut(&mut pc_state::PcState, u8)>(
@@ -345,17 +318,18 @@ pub fn ld_r<M, F: FnMut(&mut pc_state::PcState, u8)>(
 
 // LD r, (nn)
 // Load the value from the 16-bit address into the 16-bit register
-pub fn ld_r16_mem<M, R16>(
+pub fn ld_r16_mem<M, F: FnMut(&mut pc_state::PcState, u16)>(
     clock: &mut clocks::Clock,
     memory: &mut M,
-    pc_reg: &mut R16,
-    r16_reg: &mut R16,
+    pc_state: &mut pc_state::PcState,
+    mut dst_fn: F,
 ) where
     M: memory::MemoryRW,
-    R16: pc_state::Reg16RW,
 {
-    r16_reg.set(memory.read16(memory.read16(pc_reg.get())));
-    pc_state::PcState::increment_reg(pc_reg, 2);
+    let address = memory.read16(pc_state.get_pc());
+    dst_fn(pc_state, memory.read16(address));
+    pc_state.set_memptr(address.wrapping_add(1));
+    pc_state.increment_pc(2);
     clock.increment(16);
 }
 
@@ -389,7 +363,9 @@ pub fn ld_r8_mem<M, F: FnMut(&mut pc_state::PcState, u8)>(
 ) where
     M: memory::MemoryRW,
 {
-    dst_fn(pc_state, memory.read(memory.read16(pc_state.get_pc())));
+    let address = memory.read16(pc_state.get_pc());
+    dst_fn(pc_state, memory.read(address));
+    pc_state.set_memptr(address.wrapping_add(1));
     pc_state.increment_pc(2);
     clock.increment(13);
 }
@@ -406,13 +382,14 @@ where
 
 // LD (nn), r
 // eg LD (nn), A   - Which is the only version of this function.
-pub fn ld_nn_r<M, R16>(clock: &mut clocks::Clock, memory: &mut M, r: u8, pc_reg: &mut R16)
+pub fn ld_nn_r<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut pc_state::PcState)
 where
     M: memory::MemoryRW,
-    R16: pc_state::Reg16RW,
 {
-    memory.write(memory.read16(pc_reg.get()), r);
-    pc_state::PcState::increment_reg(pc_reg, 2);
+    let address = memory.read16(pc_state.get_pc());
+    memory.write(address, pc_state.get_a());
+    pc_state.set_memptr(((pc_state.get_a() as u16) << 8) | (address.wrapping_add(1) & 0xFF));
+    pc_state.increment_pc(2);
     clock.increment(13);
 }
 
@@ -469,6 +446,7 @@ where
     M: memory::MemoryRW,
 {
     pc_state.set_pc(memory.read16(pc_state.get_pc()));
+    pc_state.set_memptr(pc_state.get_pc());
     clock.increment(10);
 }
 
@@ -494,11 +472,13 @@ pub fn jr_cc_e<M>(
 {
     clock.increment(7);
 
+    let offset = memory.read(pc_state.get_pc()) as i8;
+    pc_state.increment_pc(1);
     if condition {
-        pc_state.increment_pc(memory.read(pc_state.get_pc()) as i8);
+        pc_state.increment_pc(offset);
+        pc_state.set_memptr(pc_state.get_pc());
         clock.increment(5);
     }
-    pc_state.increment_pc(1);
 }
 
 // JR NZ, e
@@ -553,8 +533,11 @@ pub fn jump_cc_nn<M>(
 ) where
     M: memory::MemoryRW,
 {
+    // MEMPTR is set to the address, even if the jump isn't taken.
+    let address = memory.read16(pc_state.get_pc());
+    pc_state.set_memptr(address);
     if condition {
-        pc_state.set_pc(memory.read16(pc_state.get_pc()));
+        pc_state.set_pc(address);
     } else {
         pc_state.increment_pc(2);
     }
@@ -567,14 +550,15 @@ where
     M: memory::MemoryRW,
 {
     pc_state.set_b(pc_state.get_b().wrapping_sub(1));
+    let offset = memory.read(pc_state.pc_reg.get()) as i8;
+    pc_state.increment_pc(1);
     if pc_state.get_b() != 0 {
-        pc_state.increment_pc(memory.read(pc_state.pc_reg.get()) as i8);
+        pc_state.increment_pc(offset);
+        pc_state.set_memptr(pc_state.get_pc());
         clock.increment(13);
     } else {
         clock.increment(8);
     }
-
-    pc_state.increment_pc(1);
 }
 
 // Note, could also add '#[derive(Copy, Clone)]' to 'Reg16'
@@ -623,6 +607,7 @@ where
     tmp8 = memory.read(pc_state.sp_reg.get() + 1);
     memory.write(pc_state.sp_reg.get() + 1, pc_state.get_h());
     pc_state.set_h(tmp8);
+    pc_state.set_memptr(pc_state.get_hl());
 
     clock.increment(19);
 }
@@ -637,6 +622,8 @@ pub fn call_cc_nn<M>(
 ) where
     M: memory::MemoryRW,
 {
+    // MEMPTR is set to the address, even if the call isn't taken.
+    pc_state.set_memptr(memory.read16(pc_state.get_pc()));
     pc_state.increment_pc(2);
     if condition {
         pc_state.increment_sp(-1);
@@ -677,6 +664,7 @@ pub fn rst<M>(
     memory.write(pc_state.sp_reg.get(), pc_state.get_pc_low());
 
     pc_state.set_pc(rst_addr as u16);
+    pc_state.set_memptr(rst_addr as u16);
 
     clock.increment(11);
 }
@@ -806,8 +794,10 @@ pub fn ld_mem_nn_hl<M>(clock: &mut clocks::Clock, memory: &mut M, pc_state: &mut
 where
     M: memory::MemoryRW,
 {
-    memory.write(memory.read16(pc_state.get_pc()), pc_state.get_l());
-    memory.write(memory.read16(pc_state.get_pc()) + 1, pc_state.get_h());
+    let address = memory.read16(pc_state.get_pc());
+    memory.write(address, pc_state.get_l());
+    memory.write(address.wrapping_add(1), pc_state.get_h());
+    pc_state.set_memptr(address.wrapping_add(1));
 
     pc_state.increment_pc(2);
     clock.increment(16);
@@ -1073,24 +1063,16 @@ where
 ////////////////////////////////////////////////////
 
 // ADD HL, ss
-pub fn add16<R16, F16>(
-    clock: &mut clocks::Clock,
-    src_value: u16,
-    pc_reg: &mut R16,
-    hl_reg: &mut R16,
-    af_reg: &mut F16,
-) where
-    R16: pc_state::Reg16RW,
-    F16: pc_state::FlagReg,
-{
-    let mut f_status = af_reg.get_flags();
-    hl_reg.set(status_flags::u16_no_carry(
-        hl_reg.get(),
+pub fn add16(clock: &mut clocks::Clock, src_value: u16, pc_state: &mut pc_state::PcState) {
+    let mut f_status = pc_state.get_f();
+    pc_state.set_memptr(pc_state.get_hl().wrapping_add(1));
+    pc_state.set_hl(status_flags::u16_no_carry(
+        pc_state.get_hl(),
         src_value,
         &mut f_status,
     ));
     f_status.set_n(0);
-    af_reg.set_flags(&f_status);
+    pc_state.set_f(f_status);
     clock.increment(11);
 }
 
@@ -1110,7 +1092,7 @@ pub fn rrca<F: FnMut(&mut pc_state::PcState, u8)>(
 
     dst_fn(pc_state, new_value);
     let mut f_value = pc_state.get_f();
-    status_flags::set_rotate_accumulator_flags(carry, &mut f_value);
+    status_flags::set_rotate_accumulator_flags(new_value, carry, &mut f_value);
     pc_state.set_f(f_value);
     clock.increment(4);
 }
@@ -1127,7 +1109,7 @@ pub fn rra<F: FnMut(&mut pc_state::PcState, u8)>(
     let (new_value, carry) = rotate_right(src, f_value.get_c() == 1);
 
     dst_fn(pc_state, new_value);
-    status_flags::set_rotate_accumulator_flags(carry, &mut f_value);
+    status_flags::set_rotate_accumulator_flags(new_value, carry, &mut f_value);
     pc_state.set_f(f_value);
     clock.increment(4);
 }
@@ -1144,7 +1126,7 @@ pub fn rlca<F: FnMut(&mut pc_state::PcState, u8)>(
 
     dst_fn(pc_state, new_value);
     let mut f_value = pc_state.get_f();
-    status_flags::set_rotate_accumulator_flags(carry, &mut f_value);
+    status_flags::set_rotate_accumulator_flags(new_value, carry, &mut f_value);
     pc_state.set_f(f_value);
     clock.increment(4);
 }
@@ -1161,7 +1143,7 @@ pub fn rla<F: FnMut(&mut pc_state::PcState, u8)>(
     let (new_value, carry) = rotate_left(src, f_value.get_c() == 1);
 
     dst_fn(pc_state, new_value);
-    status_flags::set_rotate_accumulator_flags(carry, &mut f_value);
+    status_flags::set_rotate_accumulator_flags(new_value, carry, &mut f_value);
     pc_state.set_f(f_value);
     clock.increment(4);
 }
@@ -1175,6 +1157,7 @@ where
     pc_state.increment_sp(1);
     pc_state.set_pc_high(memory.read(pc_state.sp_reg.get()));
     pc_state.increment_sp(1);
+    pc_state.set_memptr(pc_state.get_pc());
 
     clock.increment(10);
 }
@@ -1193,6 +1176,7 @@ pub fn ret_cc<M>(
         pc_state.increment_sp(1);
         pc_state.set_pc_high(memory.read(pc_state.sp_reg.get()));
         pc_state.increment_sp(1);
+        pc_state.set_memptr(pc_state.get_pc());
         clock.increment(11);
     } else {
         clock.increment(5);
@@ -1241,14 +1225,7 @@ pub fn push<M, R1, R2>(
 }
 
 pub fn daa(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState) {
-    if pc_state.get_f().get_n() == 0 {
-        // perform addition
-        calculate_daa_add(pc_state);
-    } else {
-        // perform Subtraction
-        calculate_daa_sub(pc_state);
-    }
-
+    calculate_daa(pc_state);
     clock.increment(4);
 }
 /*************************************************************************************/
@@ -1259,42 +1236,46 @@ pub fn daa(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState) {
 // one's complement of 'A'
 pub fn cpl(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState) {
     let mut f_status = pc_state.get_f();
+    pc_state.set_a(pc_state.get_a() ^ 0xFF);
     f_status.set_h(1);
     f_status.set_n(1);
+    status_flags::xy_flags(&mut f_status, pc_state.get_a());
     pc_state.set_f(f_status);
-    pc_state.set_a(pc_state.get_a() ^ 0xFF);
 
     clock.increment(4);
 }
 
+// X/Y for SCF and CCF, from A, or'ed with the previous flags if the last
+// instruction didn't set them (Q is 0).
+fn carry_xy_flags(pc_state: &pc_state::PcState, f_status: &mut pc_state::PcStatusFlagFields) {
+    status_flags::xy_flags(
+        f_status,
+        (pc_state.get_q() ^ pc_state.get_f().0) | pc_state.get_a(),
+    );
+}
+
 // SCF
 // Set the carry flag
-pub fn scf<R16, F16>(clock: &mut clocks::Clock, pc_reg: &mut R16, af_reg: &mut F16)
-where
-    R16: pc_state::Reg16RW,
-    F16: pc_state::FlagReg,
-{
-    let mut f_status = af_reg.get_flags();
+pub fn scf(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState) {
+    let mut f_status = pc_state.get_f();
     f_status.set_h(0);
     f_status.set_n(0);
     f_status.set_c(1);
-    af_reg.set_flags(&f_status);
+    carry_xy_flags(pc_state, &mut f_status);
+    pc_state.set_f(f_status);
 
     clock.increment(4);
 }
 
 // CCF
 // Compliment/Invert the carry flag
-pub fn ccf<R16, F16>(clock: &mut clocks::Clock, pc_reg: &mut R16, af_reg: &mut F16)
-where
-    R16: pc_state::Reg16RW,
-    F16: pc_state::FlagReg,
-{
-    let mut f_status = af_reg.get_flags();
+pub fn ccf(clock: &mut clocks::Clock, pc_state: &mut pc_state::PcState) {
+    let mut f_status = pc_state.get_f();
     f_status.set_h(f_status.get_c());
     f_status.set_n(0);
     f_status.set_c(f_status.get_c() ^ 1);
-    af_reg.set_flags(&f_status);
+    carry_xy_flags(pc_state, &mut f_status);
+    pc_state.set_f(f_status);
 
     clock.increment(4);
 }
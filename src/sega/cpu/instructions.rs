@@ -2,6 +2,7 @@ use super::super::clocks;
 use super::super::interruptor;
 use super::super::memory::memory;
 use super::super::ports;
+use super::error;
 use super::extended_instruction_set;
 use super::instruction_set;
 use super::pc_state;
@@ -100,6 +101,19 @@ fn get_16_bit_ss_set_function(reg_select: u8) -> impl FnMut(&mut pc_state::PcSta
     }
 }
 
+// Swap HL with IX (or IY), so an unprefixed instruction uses IXH/IXL (or
+// IYH/IYL) in place of H/L.
+fn swap_hl_index<FM: FnMut(&mut pc_state::IndexRegisters) -> &mut pc_state::Reg16>(
+    pc_state: &mut pc_state::PcState,
+    index_reg_fn_mut: &mut FM,
+) {
+    let hl = pc_state.get_hl();
+    let index_reg = index_reg_fn_mut(&mut pc_state.index_registers);
+    let index = index_reg.get();
+    index_reg.set(hl);
+    pc_state.set_hl(index);
+}
+
 impl Instruction {
     pub fn execute<M>(
         op_code: u8,
@@ -108,29 +122,31 @@ impl Instruction {
         pc_state: &mut pc_state::PcState,
         ports: &mut ports::Ports,
         _interruptor: &mut interruptor::Interruptor,
-    ) where
+    ) -> Result<(), error::CpuError>
+    where
         M: memory::MemoryRW,
     {
         match op_code {
             // Extended op codes, not executed directly
             0xcb => {
-                Self::execute_cb(clock, memory, pc_state);
+                Self::execute_cb(clock, memory, pc_state)?;
             }
             0xdd => {
-                Self::execute_dd(clock, memory, pc_state);
+                Self::execute_dd(clock, memory, pc_state, ports, _interruptor)?;
             }
             0xed => {
-                Self::execute_ed(clock, memory, pc_state, ports);
+                Self::execute_ed(clock, memory, pc_state, ports)?;
             }
             0xfd => {
-                Self::execute_fd(clock, memory, pc_state);
+                Self::execute_fd(clock, memory, pc_state, ports, _interruptor)?;
             }
 
             0xfb => {
                 // Perform a 'step' before enabling interrupts.
                 let next_op_code = memory.read(pc_state.get_pc());
                 pc_state.increment_pc(1);
-                Self::execute(next_op_code, clock, memory, pc_state, ports, _interruptor);
+                pc_state.latch_q();
+                Self::execute(next_op_code, clock, memory, pc_state, ports, _interruptor)?;
 
                 instruction_set::ei(clock, pc_state);
                 // TODO: Add polling as part of ei. Currently leaving it to outside of this call.
@@ -148,33 +164,15 @@ impl Instruction {
                 );
             } // LD dd, nn : for BC
             0x02 => {
-                instruction_set::ld_mem_r(
-                    clock,
-                    memory,
-                    pc_state.get_a(),
-                    &mut pc_state.pc_reg,
-                    &pc_state.bc_reg,
-                );
+                instruction_set::ld_mem_a(clock, memory, pc_state, pc_state.get_bc());
             } // LD (BC), A
             0x12 => {
-                instruction_set::ld_mem_r(
-                    clock,
-                    memory,
-                    pc_state.get_a(),
-                    &mut pc_state.pc_reg,
-                    &pc_state.de_reg,
-                );
+                instruction_set::ld_mem_a(clock, memory, pc_state, pc_state.get_de());
             } // LD (DE), A
 
             n if (n & 0b11001111 == 0b00001001) => {
                 let ss = (n >> 4) & 0x3;
-                instruction_set::add16(
-                    clock,
-                    select_16_bit_read_register(pc_state, ss),
-                    &mut pc_state.pc_reg,
-                    &mut pc_state.hl_reg,
-                    &mut pc_state.af_reg,
-                );
+                instruction_set::add16(clock, select_16_bit_read_register(pc_state, ss), pc_state);
             }
 
             0x0f => {
@@ -233,8 +231,8 @@ impl Instruction {
                 instruction_set::ld_r16_mem(
                     clock,
                     memory,
-                    &mut pc_state.pc_reg,
-                    &mut pc_state.hl_reg,
+                    pc_state,
+                    |state: &mut pc_state::PcState, x| state.set_hl(x),
                 );
             } // LD HL, (nn)
             0x31 => {
@@ -432,22 +430,10 @@ impl Instruction {
                 // LD r, (HL)
             }
             0x0a => {
-                instruction_set::ld_r_mem(
-                    clock,
-                    memory,
-                    pc_state,
-                    |state: &mut pc_state::PcState, x| state.set_a(x),
-                    pc_state.bc_reg.get(),
-                );
+                instruction_set::ld_a_mem(clock, memory, pc_state, pc_state.get_bc());
             } // LD A, (BC)
             0x1a => {
-                instruction_set::ld_r_mem(
-                    clock,
-                    memory,
-                    pc_state,
-                    |state: &mut pc_state::PcState, x| state.set_a(x),
-                    pc_state.de_reg.get(),
-                );
+                instruction_set::ld_a_mem(clock, memory, pc_state, pc_state.get_de());
             } // LD A, (DE)
 
             // LD r,n
@@ -502,16 +488,16 @@ impl Instruction {
                 instruction_set::jrnc_e(clock, memory, pc_state);
             }
             0x32 => {
-                instruction_set::ld_nn_r(clock, memory, pc_state.get_a(), &mut pc_state.pc_reg);
+                instruction_set::ld_nn_r(clock, memory, pc_state);
             }
             0x37 => {
-                instruction_set::scf(clock, &mut pc_state.pc_reg, &mut pc_state.af_reg);
+                instruction_set::scf(clock, pc_state);
             }
             0x38 => {
                 instruction_set::jrc_e(clock, memory, pc_state);
             }
             0x3f => {
-                instruction_set::ccf(clock, &mut pc_state.pc_reg, &mut pc_state.af_reg);
+                instruction_set::ccf(clock, pc_state);
             }
             0x76 => {
                 instruction_set::halt(clock, &mut pc_state.pc_reg);
@@ -711,9 +697,10 @@ impl Instruction {
             }
 
             _ => {
-                panic!("Opcode not implemented: {:x}", op_code);
+                return Err(error::CpuError::unimplemented(&[], op_code));
             }
         }
+        Ok(())
     }
 
     // Extended instructions
@@ -721,7 +708,8 @@ impl Instruction {
         clock: &mut clocks::Clock,
         memory: &mut M,
         pc_state: &mut pc_state::PcState,
-    ) where
+    ) -> Result<(), error::CpuError>
+    where
         M: memory::MemoryRW,
     {
         let op_code = memory.read(pc_state.get_pc());
@@ -744,14 +732,7 @@ impl Instruction {
             }
             n if (n & 0b11000110 == 0b01000110) => {
                 let bit_pos = (n >> 3) & 0x7;
-                extended_instruction_set::bit_b_mem(
-                    clock,
-                    memory,
-                    bit_pos,
-                    &mut pc_state.pc_reg,
-                    &mut pc_state.af_reg,
-                    &pc_state.hl_reg,
-                );
+                extended_instruction_set::bit_b_mem(clock, memory, bit_pos, pc_state);
             }
 
             // SET b, r
@@ -901,7 +882,34 @@ impl Instruction {
                     &pc_state.hl_reg,
                 );
             }
+            0x16 => {
+                extended_instruction_set::rl_hl(
+                    clock,
+                    memory,
+                    &mut pc_state.pc_reg,
+                    &mut pc_state.af_reg,
+                    &pc_state.hl_reg,
+                );
+            }
+            0x1e => {
+                extended_instruction_set::rr_hl(
+                    clock,
+                    memory,
+                    &mut pc_state.pc_reg,
+                    &mut pc_state.af_reg,
+                    &pc_state.hl_reg,
+                );
+            }
             0x36 => {
+                extended_instruction_set::sll_hl(
+                    clock,
+                    memory,
+                    &mut pc_state.pc_reg,
+                    &mut pc_state.af_reg,
+                    &pc_state.hl_reg,
+                );
+            }
+            0x3e => {
                 extended_instruction_set::srl_hl(
                     clock,
                     memory,
@@ -912,51 +920,74 @@ impl Instruction {
             }
 
             _ => {
-                panic!("Extended(0xCB) Opcode not implemented: {:x}", op_code);
+                return Err(error::CpuError::unimplemented(&[0xcb], op_code));
             }
         }
+        Ok(())
     }
 
     // Extended instructions
-    pub fn execute_index<
-        M,
-        F: Fn(&pc_state::IndexRegisters) -> &pc_state::Reg16,
-        FM: FnMut(&mut pc_state::IndexRegisters) -> &mut pc_state::Reg16,
-    >(
+    pub fn execute_index<M, FM: FnMut(&mut pc_state::IndexRegisters) -> &mut pc_state::Reg16>(
         clock: &mut clocks::Clock,
         memory: &mut M,
         pc_state: &mut pc_state::PcState,
-        index_reg_fn: F,
+        ports: &mut ports::Ports,
+        interruptor: &mut interruptor::Interruptor,
+        prefix: u8, // 0xDD or 0xFD.
         mut index_reg_fn_mut: FM,
-    ) where
+    ) -> Result<(), error::CpuError>
+    where
         M: memory::MemoryRW,
     {
         let op_code = memory.read(pc_state.get_pc());
         pc_state.increment_pc(1);
+
+        // Instructions using (IX+d) leave the address in MEMPTR.
+        let uses_displacement = match op_code {
+            0x34..=0x36 | 0xcb => true,
+            0x76 => false,
+            n => {
+                (n & 0b11000111 == 0b01000110)
+                    || (n & 0b11111000 == 0b01110000)
+                    || (n & 0b11000111 == 0b10000110)
+            }
+        };
+        if uses_displacement {
+            let displacement = memory.read(pc_state.get_pc()) as i8 as u16;
+            let index = index_reg_fn_mut(&mut pc_state.index_registers).get();
+            pc_state.set_memptr(index.wrapping_add(displacement));
+        }
+
         match op_code {
+            // 0xDD, 0xCB, 0bdddddddd, 0booobbbrrr
             0xcb => {
-                extended_instruction_set::bit_res_set_b_i_d(
+                let reg_index = memory.read(pc_state.get_pc().wrapping_add(1)) & 0x7;
+                let result = extended_instruction_set::rot_bit_res_set_i_d(
                     clock,
                     memory,
                     &mut pc_state.pc_reg,
                     &mut pc_state.af_reg,
-                    index_reg_fn(&mut pc_state.index_registers),
+                    index_reg_fn_mut(&mut pc_state.index_registers),
                 );
+                // Undocumented, the result is also copied to the register.
+                if let Some(value) = result {
+                    if reg_index != 0b110 {
+                        get_8_bit_register_set_function(reg_index)(pc_state, value);
+                    }
+                }
             }
             0x22 => {
-                extended_instruction_set::ld_mem_nn_reg16(
-                    clock,
-                    memory,
-                    &mut pc_state.pc_reg,
-                    index_reg_fn_mut(&mut pc_state.index_registers),
-                );
+                let index = index_reg_fn_mut(&mut pc_state.index_registers).get();
+                extended_instruction_set::ld_mem_nn_reg16(clock, memory, pc_state, index);
             }
             0x2A => {
-                extended_instruction_set::ld_i_mem_nn(
+                extended_instruction_set::ld_dd_mem_nn(
                     clock,
                     memory,
-                    &mut pc_state.pc_reg,
-                    index_reg_fn_mut(&mut pc_state.index_registers),
+                    |state: &mut pc_state::PcState, x| {
+                        index_reg_fn_mut(&mut state.index_registers).set(x)
+                    },
+                    pc_state,
                 );
             }
             0x36 => {
@@ -968,20 +999,11 @@ impl Instruction {
                 );
             }
 
-            0x9e => {
-                extended_instruction_set::sbc_i();
-            }
-
             n if (n & 0b11000111 == 0b01000110) && ((n >> 3) & 0b111 != 0b110) => {
                 let reg_index = (n >> 3) & 0x7;
                 let dst_fn = get_8_bit_register_set_function(reg_index);
-                extended_instruction_set::ld_r_i_d(
-                    clock,
-                    memory,
-                    pc_state,
-                    index_reg_fn(&pc_state.index_registers).get(),
-                    dst_fn,
-                );
+                let index = index_reg_fn_mut(&mut pc_state.index_registers).get();
+                extended_instruction_set::ld_r_i_d(clock, memory, pc_state, index, dst_fn);
             }
 
             // LD (IX+d)
@@ -1022,17 +1044,6 @@ impl Instruction {
                 );
             }
 
-            n if (n & 0b11001111 == 0b00001001) => {
-                let ss = (n >> 4) & 0x3;
-                extended_instruction_set::add16(
-                    clock,
-                    select_16_bit_read_register(pc_state, ss),
-                    &mut pc_state.pc_reg,
-                    index_reg_fn_mut(&mut pc_state.index_registers),
-                    &mut pc_state.af_reg,
-                );
-            }
-
             0x23 => {
                 extended_instruction_set::inc_16(
                     clock,
@@ -1092,6 +1103,15 @@ impl Instruction {
                     &mut pc_state.af_reg,
                 );
             }
+            0x9E => {
+                extended_instruction_set::sbc_i_d(
+                    clock,
+                    memory,
+                    &mut pc_state.pc_reg,
+                    index_reg_fn_mut(&mut pc_state.index_registers),
+                    &mut pc_state.af_reg,
+                );
+            }
             0xAE => {
                 extended_instruction_set::xor_i_d(
                     clock,
@@ -1137,15 +1157,6 @@ impl Instruction {
                     index_reg_fn_mut(&mut pc_state.index_registers),
                 );
             }
-            0xE3 => {
-                extended_instruction_set::ex_sp_i(
-                    clock,
-                    memory,
-                    &mut pc_state.pc_reg,
-                    &mut pc_state.sp_reg,
-                    index_reg_fn_mut(&mut pc_state.index_registers),
-                );
-            }
             0xF9 => {
                 extended_instruction_set::ld_sp_i(
                     clock,
@@ -1155,20 +1166,47 @@ impl Instruction {
                 );
             }
 
+            // Another prefix, this one is ignored (it acts as a NOP).
+            0xdd | 0xed | 0xfd => {
+                pc_state.increment_pc(-1);
+                clock.increment(4);
+            }
+
+            // Instructions that don't use HL run as usual.
+            0xd9 | 0xeb | 0xfb => {
+                clock.increment(4);
+                Self::execute(op_code, clock, memory, pc_state, ports, interruptor)?;
+            }
+
+            // The rest use IXH/IXL (or IYH/IYL) in place of H/L (undocumented),
+            // or IX (or IY) in place of HL.
             _ => {
-                panic!("Extended(0xDD) Opcode not implemented: {:x}", op_code);
+                clock.increment(4);
+                swap_hl_index(pc_state, &mut index_reg_fn_mut);
+                let result = Self::execute(op_code, clock, memory, pc_state, ports, interruptor);
+                swap_hl_index(pc_state, &mut index_reg_fn_mut);
+                result.map_err(|mut error| {
+                    error.prefix.insert(0, prefix);
+                    error
+                })?;
             }
         }
+        Ok(())
     }
     // Extended instructions
     pub fn execute_dd<M>(
         clock: &mut clocks::Clock,
         memory: &mut M,
         pc_state: &mut pc_state::PcState,
-    ) where
+        ports: &mut ports::Ports,
+        interruptor: &mut interruptor::Interruptor,
+    ) -> Result<(), error::CpuError>
+    where
         M: memory::MemoryRW,
     {
-        Self::execute_index(clock, memory, pc_state, |x| &x.ix_reg, |x| &mut x.ix_reg);
+        Self::execute_index(clock, memory, pc_state, ports, interruptor, 0xdd, |x| {
+            &mut x.ix_reg
+        })
     }
 
     // Extended instructions
@@ -1176,10 +1214,15 @@ impl Instruction {
         clock: &mut clocks::Clock,
         memory: &mut M,
         pc_state: &mut pc_state::PcState,
-    ) where
+        ports: &mut ports::Ports,
+        interruptor: &mut interruptor::Interruptor,
+    ) -> Result<(), error::CpuError>
+    where
         M: memory::MemoryRW,
     {
-        Self::execute_index(clock, memory, pc_state, |x| &x.iy_reg, |x| &mut x.iy_reg);
+        Self::execute_index(clock, memory, pc_state, ports, interruptor, 0xfd, |x| {
+            &mut x.iy_reg
+        })
     }
 
     // Extended instructions
@@ -1188,17 +1231,14 @@ impl Instruction {
         memory: &mut M,
         pc_state: &mut pc_state::PcState,
         ports: &mut ports::Ports,
-    ) where
+    ) -> Result<(), error::CpuError>
+    where
         M: memory::MemoryRW,
     {
         let op_code = memory.read(pc_state.get_pc());
         pc_state.increment_pc(1);
 
         match op_code {
-            0x00 => {
-                instruction_set::noop(clock, pc_state);
-            }
-
             // 0b01dd1011 -> BC 00, DE 01, HL 10, SP 11
             n if (n & 0b11001111 == 0b01001011) => {
                 let dd = (n >> 4) & 0x3;
@@ -1215,32 +1255,32 @@ impl Instruction {
                 extended_instruction_set::ld_mem_nn_reg16(
                     clock,
                     memory,
-                    &mut pc_state.pc_reg,
-                    &pc_state.bc_reg,
+                    pc_state,
+                    pc_state.get_bc(),
                 );
             }
             0x53 => {
                 extended_instruction_set::ld_mem_nn_reg16(
                     clock,
                     memory,
-                    &mut pc_state.pc_reg,
-                    &pc_state.de_reg,
+                    pc_state,
+                    pc_state.get_de(),
                 );
             }
             0x63 => {
                 extended_instruction_set::ld_mem_nn_reg16(
                     clock,
                     memory,
-                    &mut pc_state.pc_reg,
-                    &pc_state.hl_reg,
+                    pc_state,
+                    pc_state.get_hl(),
                 );
             }
             0x73 => {
                 extended_instruction_set::ld_mem_nn_reg16(
                     clock,
                     memory,
-                    &mut pc_state.pc_reg,
-                    &pc_state.sp_reg,
+                    pc_state,
+                    pc_state.get_sp(),
                 );
             }
 
@@ -1264,6 +1304,16 @@ impl Instruction {
                 extended_instruction_set::rld(clock, memory, pc_state);
             }
 
+            // IN F (C), only sets the flags (undocumented)
+            0x70 => {
+                extended_instruction_set::in_r(clock, pc_state.get_c(), pc_state, |_, _| {}, ports);
+            }
+
+            // OUT (C), 0 (undocumented)
+            0x71 => {
+                extended_instruction_set::out_r(clock, pc_state.get_c(), pc_state, 0, ports);
+            }
+
             // IN r (C) 0xED, 01rrr000
             n if (n & 0b11000111 == 0b01000000) && ((n >> 3) & 0b111 != 0b110) => {
                 let reg_index = (n >> 3) & 0x7;
@@ -1289,16 +1339,24 @@ impl Instruction {
             0xAB => {
                 extended_instruction_set::outd(clock, memory, pc_state, ports);
             }
-            0x44 => {
+            // NEG, 0xED, 0b01xxx100 (all but 0x44 undocumented)
+            n if (n & 0b11000111 == 0b01000100) => {
                 extended_instruction_set::neg(clock, pc_state);
             }
             0x4D => {
                 extended_instruction_set::reti(clock, memory, pc_state);
             }
+            // RETN, 0xED, 0b01xxx101 (all but 0x45 undocumented)
+            n if (n & 0b11000111 == 0b01000101) => {
+                extended_instruction_set::retn(clock, memory, pc_state);
+            }
 
             0xB3 => {
                 extended_instruction_set::otir(clock, memory, pc_state, ports);
             }
+            0xBB => {
+                extended_instruction_set::otdr(clock, memory, pc_state, ports);
+            }
             0xB8 => {
                 extended_instruction_set::lddr(clock, memory, pc_state);
             }
@@ -1308,6 +1366,15 @@ impl Instruction {
             0xA2 => {
                 extended_instruction_set::ini(clock, memory, pc_state, ports);
             }
+            0xAA => {
+                extended_instruction_set::ind(clock, memory, pc_state, ports);
+            }
+            0xB2 => {
+                extended_instruction_set::inir(clock, memory, pc_state, ports);
+            }
+            0xBA => {
+                extended_instruction_set::indr(clock, memory, pc_state, ports);
+            }
             0xA1 => {
                 extended_instruction_set::cpi(clock, memory, pc_state);
             }
@@ -1326,8 +1393,10 @@ impl Instruction {
             0xA8 => {
                 extended_instruction_set::ldd(clock, memory, pc_state);
             }
-            0x56 => {
-                extended_instruction_set::im_1(clock, pc_state);
+            // IM 0, 1, 2, 0xED, 0b01xmm110 (0x46, 0x56 and 0x5E documented)
+            n if (n & 0b11000111 == 0b01000110) => {
+                let mode = [0, 0, 1, 2][((n >> 3) & 0x3) as usize];
+                extended_instruction_set::im(clock, pc_state, mode);
             }
 
             // ADC HL, ss
@@ -1337,9 +1406,7 @@ impl Instruction {
                 extended_instruction_set::adc_hl_r16(
                     clock,
                     select_16_bit_read_register(pc_state, ss),
-                    &mut pc_state.pc_reg,
-                    &mut pc_state.hl_reg,
-                    &mut pc_state.af_reg,
+                    pc_state,
                 );
             }
 
@@ -1350,16 +1417,25 @@ impl Instruction {
                 extended_instruction_set::sbc_hl_r16(
                     clock,
                     select_16_bit_read_register(pc_state, ss),
-                    &mut pc_state.pc_reg,
-                    &mut pc_state.hl_reg,
-                    &mut pc_state.af_reg,
+                    pc_state,
                 );
             }
 
+            // Invalid op codes (undocumented NOPs)
+            n if !(0x40..0xC0).contains(&n)
+                || (0x80..0xA0).contains(&n)
+                || (n & 0b11100100 == 0b10100100)
+                || n == 0x77
+                || n == 0x7F =>
+            {
+                extended_instruction_set::nop_ed(clock);
+            }
+
             _ => {
-                panic!("Extended(0xED) Opcode not implemented: {:x}", op_code);
+                return Err(error::CpuError::unimplemented(&[0xed], op_code));
             }
         }
+        Ok(())
     }
 }
 
@@ -1372,6 +1448,7 @@ mod tests {
     use crate::sega::interruptor;
     use crate::sega::memory::memory;
     use crate::sega::ports;
+    use crate::sega::watch;
 
     // Create a 'test memory' class, to allow simple/arbitrary population of memory.
     pub struct TestMemory {
@@ -1430,7 +1507,8 @@ mod tests {
             &mut test_core.pc_state,
             &mut test_core.ports,
             &mut test_core.interruptor,
-        );
+        )
+        .unwrap();
     }
 
     #[test]
@@ -1495,7 +1573,8 @@ mod tests {
             &mut test_core.pc_state,
             &mut test_core.ports,
             &mut test_core.interruptor,
-        ); // LD r,'r  C -> B
+        )
+        .unwrap(); // LD r,'r  C -> B
         assert_eq!(test_core.pc_state.get_b(), 0x42);
         assert_eq!(test_core.clock.cycles, 4);
     }
@@ -1513,7 +1592,8 @@ mod tests {
             &mut test_core.pc_state,
             &mut test_core.ports,
             &mut test_core.interruptor,
-        ); // JP (HL)
+        )
+        .unwrap(); // JP (HL)
         assert_eq!(test_core.pc_state.get_pc(), 0x4233);
     }
 
@@ -1532,7 +1612,8 @@ mod tests {
             &mut test_core.pc_state,
             &mut test_core.ports,
             &mut test_core.interruptor,
-        ); // dec_r, for h
+        )
+        .unwrap(); // dec_r, for h
         assert_eq!(test_core.pc_state.get_h(), 0x7F);
         assert_eq!(test_core.pc_state.get_f().get_h(), 1);
         assert_eq!(test_core.pc_state.get_f().get_c(), 1);
@@ -1552,7 +1633,8 @@ mod tests {
             &mut test_core.pc_state,
             &mut test_core.ports,
             &mut test_core.interruptor,
-        ); // no-op
+        )
+        .unwrap(); // no-op
         assert_eq!(test_core.pc_state.get_pc(), 0x1);
         assert_eq!(test_core.clock.cycles, 4);
 
@@ -1570,7 +1652,8 @@ mod tests {
             &mut test_core.pc_state,
             &mut test_core.ports,
             &mut test_core.interruptor,
-        );
+        )
+        .unwrap();
         assert_eq!(test_core.pc_state.get_pc(), 0x3);
         assert_eq!(test_core.pc_state.get_bc(), 0x3310);
         assert_eq!(test_core.clock.cycles, 10);
@@ -1756,8 +1839,8 @@ mod tests {
         default_pc_op_code_check(vec![0x98], vec![4], "SBC A,r"); // SBC A,s
         default_pc_op_code_check(vec![0xDE, 0x00], vec![4, 3], "SBC A,n"); // SBC A,s
         default_pc_op_code_check(vec![0x9E], vec![4, 3], "SBC A,(HL)"); // SBC A,s
-                                                                        // Not ipmlemented        default_pc_op_code_check(vec![0xDD,0x9E,0x00], vec![4,4,3,5,3], "SBC A,(IY+d)"); // SBC A,s
-                                                                        // Not ipmlemented        default_pc_op_code_check(vec![0xFD,0x9E,0x00], vec![4,4,3,5,3], "SBC A,(IY+d)"); // SBC A,s
+        default_pc_op_code_check(vec![0xDD, 0x9E, 0x00], vec![4, 4, 3, 5, 3], "SBC A,(IX+d)"); // SBC A,s
+        default_pc_op_code_check(vec![0xFD, 0x9E, 0x00], vec![4, 4, 3, 5, 3], "SBC A,(IY+d)"); // SBC A,s
 
         default_pc_op_code_check(vec![0xA0], vec![4], "AND r"); // AND s
         default_pc_op_code_check(vec![0xE6, 0x00], vec![4, 3], "AND n"); // AND s
@@ -1795,6 +1878,18 @@ mod tests {
 
         // General purpose arithmetic and CPU control groups
         // Page 172
+
+        // Undocumented
+        default_pc_op_code_check(vec![0xDD, 0x44], vec![4, 4], "LD B,IXH");
+        default_pc_op_code_check(vec![0xFD, 0x2E, 0x00], vec![4, 4, 3], "LD IYL,n");
+        default_pc_op_code_check(vec![0xDD, 0x85], vec![4, 4], "ADD A,IXL");
+        default_pc_op_code_check(vec![0xFD, 0x24], vec![4, 4], "INC IYH");
+        default_pc_op_code_check(vec![0xDD, 0x29], vec![4, 4, 4, 3], "ADD IX,IX");
+        default_pc_op_code_check(vec![0xED, 0x4C], vec![4, 4], "NEG");
+        default_pc_op_code_check(vec![0xED, 0x5E], vec![4, 4], "IM 2");
+        default_pc_op_code_check(vec![0xED, 0x71], vec![4, 4, 4], "OUT (C),0");
+        default_pc_op_code_check(vec![0xED, 0x70], vec![4, 4, 4], "IN F,(C)");
+        default_pc_op_code_check(vec![0xED, 0x77], vec![4, 4], "NOP");
     }
 
     #[test]
@@ -1857,6 +1952,197 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_undocumented_opcodes() {
+        let mut test_core = TestCore::new();
+
+        // LD IXH,$12; LD IYL,$34; ADD A,IXH; LD B,IYL (H and L unchanged)
+        test_core.pc_state.set_hl(0x5678);
+        test_core.pc_state.set_a(0x01);
+        simple_execute(&mut test_core, vec![0xDD, 0x26, 0x12]);
+        simple_execute(&mut test_core, vec![0xFD, 0x2E, 0x34]);
+        simple_execute(&mut test_core, vec![0xDD, 0x84]);
+        simple_execute(&mut test_core, vec![0xFD, 0x45]);
+        assert_eq!(test_core.pc_state.index_registers.ix_reg.get(), 0x1200);
+        assert_eq!(test_core.pc_state.index_registers.iy_reg.get(), 0x0034);
+        assert_eq!(test_core.pc_state.get_a(), 0x13);
+        assert_eq!(test_core.pc_state.get_b(), 0x34);
+        assert_eq!(test_core.pc_state.get_hl(), 0x5678);
+
+        // ADD IX,IX
+        simple_execute(&mut test_core, vec![0xDD, 0x29]);
+        assert_eq!(test_core.pc_state.index_registers.ix_reg.get(), 0x2400);
+
+        // RLC (IX+1),B and SET 0,(IX+1),C copy the result to the register.
+        test_core.pc_state.index_registers.ix_reg.set(0x0003);
+        test_core.pc_state.set_f(pc_state::PcStatusFlagFields(0));
+        simple_execute(&mut test_core, vec![0xDD, 0xCB, 0x01, 0x00, 0x81]);
+        assert_eq!(test_core.memory.dummy_memory[4], 0x03);
+        assert_eq!(test_core.pc_state.get_b(), 0x03);
+        assert_eq!(test_core.pc_state.get_f().get_c(), 1);
+        assert_eq!(test_core.pc_state.get_pc(), 4);
+        assert_eq!(test_core.clock.cycles, 23);
+        simple_execute(&mut test_core, vec![0xDD, 0xCB, 0x01, 0xC1, 0x00]);
+        assert_eq!(test_core.memory.dummy_memory[4], 0x01);
+        assert_eq!(test_core.pc_state.get_c(), 0x01);
+
+        // SLL (HL), SRL (HL)
+        test_core.pc_state.set_hl(0x0002);
+        simple_execute(&mut test_core, vec![0xCB, 0x36, 0x40]);
+        assert_eq!(test_core.memory.dummy_memory[2], 0x81);
+        simple_execute(&mut test_core, vec![0xCB, 0x3E, 0x81]);
+        assert_eq!(test_core.memory.dummy_memory[2], 0x40);
+        assert_eq!(test_core.pc_state.get_f().get_c(), 1);
+
+        // NEG and IM duplicates
+        test_core.pc_state.set_a(0x01);
+        simple_execute(&mut test_core, vec![0xED, 0x6C]);
+        assert_eq!(test_core.pc_state.get_a(), 0xFF);
+        simple_execute(&mut test_core, vec![0xED, 0x7E]);
+        assert_eq!(test_core.pc_state.get_im(), 2);
+
+        // OUT (C),0
+        test_core.pc_state.set_c(0x10);
+        test_core.ports.watches.add(watch::Watch::new(
+            watch::Access::Write,
+            0x10,
+            0x10,
+            Some(0x00),
+        ));
+        simple_execute(&mut test_core, vec![0xED, 0x71]);
+        assert_eq!(test_core.ports.take_watch_hit().unwrap().address, 0x10);
+        test_core.ports.watches.clear();
+
+        // RETN duplicates pop the PC and restore IFF1 from IFF2.
+        for op_code in [0x55, 0x5D, 0x65, 0x6D, 0x75, 0x7D] {
+            test_core.pc_state.sp_reg.set(0x0002);
+            test_core.pc_state.set_iff1(false);
+            test_core.pc_state.set_iff2(true);
+            simple_execute(&mut test_core, vec![0xED, op_code, 0x34, 0x12]);
+            assert_eq!(test_core.pc_state.get_pc(), 0x1234);
+            assert_eq!(test_core.pc_state.get_sp(), 0x0004);
+            assert!(test_core.pc_state.get_iff1());
+        }
+
+        // EXX with a prefix swaps HL, not the index register.
+        test_core.pc_state.set_hl(0x1111);
+        test_core.pc_state.shadow_hl_reg.set(0x2222);
+        test_core.pc_state.index_registers.ix_reg.set(0x3333);
+        test_core.pc_state.index_registers.iy_reg.set(0x4444);
+        simple_execute(&mut test_core, vec![0xDD, 0xD9]);
+        assert_eq!(test_core.pc_state.get_hl(), 0x2222);
+        assert_eq!(test_core.pc_state.shadow_hl_reg.get(), 0x1111);
+        assert_eq!(test_core.pc_state.index_registers.ix_reg.get(), 0x3333);
+        simple_execute(&mut test_core, vec![0xFD, 0xD9]);
+        assert_eq!(test_core.pc_state.get_hl(), 0x1111);
+        assert_eq!(test_core.pc_state.shadow_hl_reg.get(), 0x2222);
+        assert_eq!(test_core.pc_state.index_registers.iy_reg.get(), 0x4444);
+
+        // A repeated prefix is skipped (as a NOP).
+        simple_execute(&mut test_core, vec![0xDD, 0xFD, 0x21, 0x00, 0x00]);
+        assert_eq!(test_core.pc_state.get_pc(), 1);
+        assert_eq!(test_core.clock.cycles, 4);
+    }
+
+    #[test]
+    fn test_undocumented_flags() {
+        let mut test_core = TestCore::new();
+
+        // ADD HL,BC sets MEMPTR (to HL+1), BIT 0,(HL) takes X/Y from its high byte.
+        test_core.pc_state.set_hl(0x2700);
+        test_core.pc_state.set_f(pc_state::PcStatusFlagFields(0));
+        simple_execute(&mut test_core, vec![0x09]);
+        assert_eq!(test_core.pc_state.get_memptr(), 0x2701);
+        test_core.pc_state.set_hl(0x0002);
+        simple_execute(&mut test_core, vec![0xCB, 0x46, 0x01]);
+        assert_eq!(test_core.pc_state.get_f().0, 0x30); // Y, H
+
+        // LD A,(IX+d) leaves the address in MEMPTR.
+        test_core.pc_state.index_registers.ix_reg.set(0x0004);
+        simple_execute(&mut test_core, vec![0xDD, 0x7E, 0xFF, 0x42]);
+        assert_eq!(test_core.pc_state.get_a(), 0x42);
+        assert_eq!(test_core.pc_state.get_memptr(), 0x0003);
+
+        // CP takes X/Y from the operand, not the result.
+        test_core.pc_state.set_a(0x00);
+        simple_execute(&mut test_core, vec![0xFE, 0x28]);
+        assert_eq!(test_core.pc_state.get_f().0, 0xBB); // S, Y, H, X, N, C
+
+        // DAA after an add and a subtract.
+        test_core.pc_state.set_a(0x15);
+        simple_execute(&mut test_core, vec![0xC6, 0x27]);
+        simple_execute(&mut test_core, vec![0x27]);
+        assert_eq!(test_core.pc_state.get_a(), 0x42);
+        assert_eq!(test_core.pc_state.get_f().0, 0x14); // H, P
+        simple_execute(&mut test_core, vec![0xD6, 0x15]);
+        simple_execute(&mut test_core, vec![0x27]);
+        assert_eq!(test_core.pc_state.get_a(), 0x27);
+        assert_eq!(test_core.pc_state.get_f().0, 0x26); // Y, P, N
+
+        // LDI, X/Y from bits 3 and 1 of A + (HL).
+        test_core.pc_state.set_a(0x00);
+        test_core.pc_state.set_f(pc_state::PcStatusFlagFields(0));
+        test_core.pc_state.set_hl(0x0002);
+        test_core.pc_state.set_de(0x0003);
+        test_core.pc_state.set_bc(0x0002);
+        simple_execute(&mut test_core, vec![0xED, 0xA0, 0x0A, 0x00]);
+        assert_eq!(test_core.memory.dummy_memory[3], 0x0A);
+        assert_eq!(test_core.pc_state.get_f().0, 0x2C); // Y, X, P
+
+        // SCF, X/Y from A if the previous instruction set the flags (as
+        // latched by 'Core::step'), otherwise from A | F.
+        test_core.pc_state.set_f(pc_state::PcStatusFlagFields(0x28));
+        test_core.pc_state.latch_q();
+        simple_execute(&mut test_core, vec![0x37]);
+        assert_eq!(test_core.pc_state.get_f().0, 0x01);
+        test_core.pc_state.set_f(pc_state::PcStatusFlagFields(0x28));
+        test_core.pc_state.latch_q();
+        test_core.pc_state.latch_q();
+        simple_execute(&mut test_core, vec![0x37]);
+        assert_eq!(test_core.pc_state.get_f().0, 0x29);
+    }
+
+    #[test]
+    fn test_block_io() {
+        let mut test_core = TestCore::new();
+
+        // INIR repeats (moving the PC back) until B reaches 0.
+        test_core.pc_state.set_bc(0x0280);
+        test_core.pc_state.set_hl(0x0002);
+        simple_execute(&mut test_core, vec![0xED, 0xB2, 0xFF, 0xFF]);
+        assert_eq!(test_core.clock.cycles, 21);
+        assert_eq!(test_core.pc_state.get_pc(), 0x0000);
+        assert_eq!(test_core.pc_state.get_memptr(), 0x0281);
+        assert_eq!(test_core.memory.dummy_memory[2], 0x00);
+        assert_eq!(test_core.pc_state.get_hl(), 0x0003);
+
+        // IND/INDR count HL down, MEMPTR is BC - 1.
+        test_core.pc_state.set_bc(0x0180);
+        test_core.pc_state.set_hl(0x0003);
+        simple_execute(&mut test_core, vec![0xED, 0xBA, 0xFF, 0xFF]);
+        assert_eq!(test_core.clock.cycles, 16);
+        assert_eq!(test_core.pc_state.get_pc(), 0x0002);
+        assert_eq!(test_core.pc_state.get_memptr(), 0x017F);
+        assert_eq!(test_core.memory.dummy_memory[3], 0x00);
+        assert_eq!(test_core.pc_state.get_hl(), 0x0002);
+        assert_eq!(test_core.pc_state.get_b(), 0x00);
+        test_core.pc_state.set_bc(0x0280);
+        test_core.pc_state.set_hl(0x0003);
+        simple_execute(&mut test_core, vec![0xED, 0xAA, 0xFF, 0xFF]);
+        assert_eq!(test_core.clock.cycles, 16);
+        assert_eq!(test_core.pc_state.get_pc(), 0x0002);
+        assert_eq!(test_core.pc_state.get_b(), 0x01);
+
+        // OTDR.
+        test_core.pc_state.set_bc(0x0280);
+        test_core.pc_state.set_hl(0x0003);
+        simple_execute(&mut test_core, vec![0xED, 0xBB, 0x00, 0x00]);
+        assert_eq!(test_core.clock.cycles, 21);
+        assert_eq!(test_core.pc_state.get_pc(), 0x0000);
+        assert_eq!(test_core.pc_state.get_memptr(), 0x017F);
+        assert_eq!(test_core.pc_state.get_hl(), 0x0002);
+    }
+
     #[test]
     fn test_sequence_from_mask() {
         assert_eq!(vec![0, 2, 8, 10], sequence_from_mask(0xA));
@@ -1,6 +1,10 @@
 pub mod core;
+pub mod disassembler;
+pub mod error;
 pub mod extended_instruction_set;
 pub mod instruction_set;
 pub mod instructions;
 pub mod pc_state;
 pub mod status_flags;
+pub mod trace;
+pub mod zex;
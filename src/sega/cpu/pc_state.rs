@@ -1,3 +1,4 @@
+use super::super::snapshot;
 use bitfield::bitfield;
 use std::fmt;
 
@@ -50,6 +51,7 @@ pub struct FlagReg16 {
     // TODO: I'm sure there's a better way to handle the flag overlay with
     // 'af'.  This works, can revisit later.
     reg16: Reg16,
+    flags_set: bool, // Flags set by an instruction, since the last 'latch_q'.
 }
 
 pub struct IndexRegisters {
@@ -88,6 +90,8 @@ pub struct PcState {
     iff1: bool,
     iff2: bool,
     im: u8,
+    memptr: u16, // Internal register (aka WZ), shows in the X/Y flags of 'BIT b, (HL)'.
+    q: u8,       // Flags set by the previous instruction (0 if it didn't), used by SCF/CCF.
 }
 
 impl fmt::Display for PcState {
@@ -160,6 +164,7 @@ impl FlagReg16 {
     fn new() -> Self {
         Self {
             reg16: Reg16::new(),
+            flags_set: false,
         }
     }
 }
@@ -171,6 +176,7 @@ impl FlagReg for FlagReg16 {
 
     fn set_flags(&mut self, flags: &PcStatusFlagFields) {
         self.reg16.low = flags.0;
+        self.flags_set = true;
     }
 }
 
@@ -239,6 +245,8 @@ impl PcState {
             iff1: false,
             iff2: false,
             im: 0,
+            memptr: 0,
+            q: 0,
         }
     }
 
@@ -327,6 +335,12 @@ impl PcState {
     pub fn get_im(&self) -> u8 {
         self.im
     }
+    pub fn get_memptr(&self) -> u16 {
+        self.memptr
+    }
+    pub fn get_q(&self) -> u8 {
+        self.q
+    }
 
     pub fn set_b(&mut self, input: u8) {
         self.bc_reg.high = input;
@@ -394,6 +408,20 @@ impl PcState {
     pub fn set_im(&mut self, input: u8) {
         self.im = input;
     }
+    pub fn set_memptr(&mut self, input: u16) {
+        self.memptr = input;
+    }
+
+    // Called before each instruction, Q keeps the flags if the previous
+    // instruction set them (POP AF and EX AF, AF' don't count).
+    pub fn latch_q(&mut self) {
+        self.q = if self.af_reg.flags_set {
+            self.get_f().0
+        } else {
+            0
+        };
+        self.af_reg.flags_set = false;
+    }
 
     // Additional utility functions, intended to simplify some of the calls.
     pub fn increment_reg(register: &mut dyn Reg16RW, increment: i8) {
@@ -409,6 +437,61 @@ impl PcState {
     }
 }
 
+impl snapshot::Snapshot for PcState {
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        for reg in [
+            &self.bc_reg,
+            &self.de_reg,
+            &self.af_reg.reg16,
+            &self.hl_reg,
+            &self.pc_reg,
+            &self.sp_reg,
+            &self.index_registers.ix_reg,
+            &self.index_registers.iy_reg,
+            &self.shadow_bc_reg,
+            &self.shadow_de_reg,
+            &self.shadow_hl_reg,
+            &self.shadow_af_reg.reg16,
+        ] {
+            writer.write_u16(reg.get());
+        }
+        writer.write_u8(self.r);
+        writer.write_u8(self.i);
+        writer.write_bool(self.iff1);
+        writer.write_bool(self.iff2);
+        writer.write_u8(self.im);
+        writer.write_u16(self.memptr);
+        writer.write_bool(self.af_reg.flags_set); // Q is latched from this.
+    }
+
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        for reg in [
+            &mut self.bc_reg,
+            &mut self.de_reg,
+            &mut self.af_reg.reg16,
+            &mut self.hl_reg,
+            &mut self.pc_reg,
+            &mut self.sp_reg,
+            &mut self.index_registers.ix_reg,
+            &mut self.index_registers.iy_reg,
+            &mut self.shadow_bc_reg,
+            &mut self.shadow_de_reg,
+            &mut self.shadow_hl_reg,
+            &mut self.shadow_af_reg.reg16,
+        ] {
+            reg.set(reader.read_u16()?);
+        }
+        self.r = reader.read_u8()?;
+        self.i = reader.read_u8()?;
+        self.iff1 = reader.read_bool()?;
+        self.iff2 = reader.read_bool()?;
+        self.im = reader.read_u8()?;
+        self.memptr = reader.read_u16()?;
+        self.af_reg.flags_set = reader.read_bool()?;
+        Ok(())
+    }
+}
+
 #[test]
 fn test_pc_status_flag_fields() {
     let mut pc_status_flags = PcStatusFlagFields(0);
@@ -0,0 +1,371 @@
+use super::super::memory::memory;
+use super::disassembler;
+use super::pc_state;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::sync;
+
+// Instruction trace, written to a file.  Each line has the selected fields
+// for the instruction about to run:
+//   cycles, bank:pc, disassembly, registers, flags
+// In ring buffer mode only the last instructions are kept, and they're
+// written out by 'dump' (eg, on an unimplemented opcode) or on a panic.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TraceFields {
+    pub cycles: bool,
+    pub pc: bool,
+    pub disassembly: bool,
+    pub registers: bool,
+    pub flags: bool,
+}
+
+impl TraceFields {
+    pub const NAMES: &'static str = "cycles, pc, disasm, regs, flags";
+
+    pub fn all() -> Self {
+        Self {
+            cycles: true,
+            pc: true,
+            disassembly: true,
+            registers: true,
+            flags: true,
+        }
+    }
+
+    // Parse a comma separated list of field names.
+    pub fn from_names(names: &str) -> Option<TraceFields> {
+        let mut fields = TraceFields {
+            cycles: false,
+            pc: false,
+            disassembly: false,
+            registers: false,
+            flags: false,
+        };
+        for name in names.split(',') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "cycles" => fields.cycles = true,
+                "pc" => fields.pc = true,
+                "disasm" | "disassembly" => fields.disassembly = true,
+                "regs" | "registers" => fields.registers = true,
+                "flags" => fields.flags = true,
+                _ => return None,
+            }
+        }
+        Some(fields)
+    }
+}
+
+// The last instructions (for ring buffer mode), shared with the panic hook.
+struct TraceRing {
+    lines: VecDeque<String>,
+    size: usize,
+    filename: String,
+}
+
+impl TraceRing {
+    fn dump(&mut self) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(&self.filename)?);
+        for line in self.lines.drain(..) {
+            writeln!(file, "{}", line)?;
+        }
+        file.flush()
+    }
+}
+
+// The ring dumped by the panic hook (the most recently created one).  The
+// hook is installed once, so creating more loggers doesn't chain hooks.
+static PANIC_RING: sync::Mutex<Option<sync::Weak<sync::Mutex<TraceRing>>>> = sync::Mutex::new(None);
+static PANIC_HOOK: sync::Once = sync::Once::new();
+
+enum TraceOutput {
+    File(io::BufWriter<fs::File>),
+    Ring(sync::Arc<sync::Mutex<TraceRing>>),
+}
+
+pub struct TraceLogger {
+    fields: TraceFields,
+    start_pc: Option<u16>,      // Start tracing once this PC is reached.
+    stop_pc: Option<u16>,       // Stop tracing once this PC is reached.
+    frames: Option<(u64, u64)>, // Only trace within these frames (inclusive).
+    started: bool,
+    stopped: bool,
+    output: TraceOutput,
+}
+
+impl TraceLogger {
+    // Trace to 'filename', 'ring_size' keeps only the last instructions.
+    pub fn create(filename: &str, ring_size: Option<usize>) -> io::Result<Self> {
+        let output = match ring_size {
+            Some(size) => {
+                let ring = sync::Arc::new(sync::Mutex::new(TraceRing {
+                    lines: VecDeque::with_capacity(size),
+                    size: size.max(1),
+                    filename: filename.to_string(),
+                }));
+                TraceLogger::dump_on_panic(sync::Arc::clone(&ring));
+                TraceOutput::Ring(ring)
+            }
+            None => TraceOutput::File(io::BufWriter::new(fs::File::create(filename)?)),
+        };
+        Ok(Self {
+            fields: TraceFields::all(),
+            start_pc: None,
+            stop_pc: None,
+            frames: None,
+            started: true,
+            stopped: false,
+            output,
+        })
+    }
+
+    fn dump_on_panic(ring: sync::Arc<sync::Mutex<TraceRing>>) {
+        if let Ok(mut current) = PANIC_RING.lock() {
+            *current = Some(sync::Arc::downgrade(&ring));
+        }
+        PANIC_HOOK.call_once(|| {
+            let previous_hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                let ring = PANIC_RING
+                    .try_lock()
+                    .ok()
+                    .and_then(|current| current.as_ref().and_then(sync::Weak::upgrade));
+                if let Some(ring) = ring {
+                    if let Ok(mut ring) = ring.try_lock() {
+                        match ring.dump() {
+                            Ok(()) => println!("Trace written to {}", ring.filename),
+                            Err(e) => println!("Error writing trace. {}", e),
+                        }
+                    }
+                }
+                previous_hook(info);
+            }));
+        });
+    }
+
+    pub fn set_fields(&mut self, fields: TraceFields) {
+        self.fields = fields;
+    }
+
+    pub fn set_start_pc(&mut self, pc: u16) {
+        self.start_pc = Some(pc);
+        self.started = false;
+    }
+
+    pub fn set_stop_pc(&mut self, pc: u16) {
+        self.stop_pc = Some(pc);
+    }
+
+    pub fn set_frames(&mut self, first: u64, last: u64) {
+        self.frames = Some((first, last));
+    }
+
+    // Parse a frame range, "first-last" (or a single frame).
+    pub fn parse_frames(text: &str) -> Option<(u64, u64)> {
+        let (first, last) = text.split_once('-').unwrap_or((text, text));
+        let first = first.trim().parse().ok()?;
+        let last = last.trim().parse().ok()?;
+        if first <= last {
+            Some((first, last))
+        } else {
+            None
+        }
+    }
+
+    // Check the trigger conditions for the instruction at 'pc'.
+    pub fn is_due(&mut self, pc: u16, frame_count: u64) -> bool {
+        if self.stopped {
+            return false;
+        }
+        if !self.started {
+            if self.start_pc != Some(pc) {
+                return false;
+            }
+            self.started = true;
+        }
+        if self.stop_pc == Some(pc) {
+            self.stopped = true;
+            return false;
+        }
+        self.frames
+            .is_none_or(|(first, last)| (first..=last).contains(&frame_count))
+    }
+
+    pub fn log<M: memory::MemoryRW>(
+        &mut self,
+        cycles: u64,
+        pc_state: &pc_state::PcState,
+        memory: &M,
+    ) {
+        let line = self.format_line(cycles, pc_state, memory);
+        match &mut self.output {
+            TraceOutput::File(file) => {
+                if let Err(e) = writeln!(file, "{}", line) {
+                    println!("Error writing trace, stopping. {}", e);
+                    self.stopped = true;
+                }
+            }
+            TraceOutput::Ring(ring) => {
+                let mut ring = ring.lock().unwrap();
+                if ring.lines.len() == ring.size {
+                    ring.lines.pop_front();
+                }
+                ring.lines.push_back(line);
+            }
+        }
+    }
+
+    // Write out the ring buffer (or flush the file).
+    pub fn dump(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            TraceOutput::File(file) => file.flush(),
+            TraceOutput::Ring(ring) => ring.lock().unwrap().dump(),
+        }
+    }
+
+    fn format_line<M: memory::MemoryRW>(
+        &self,
+        cycles: u64,
+        pc_state: &pc_state::PcState,
+        memory: &M,
+    ) -> String {
+        let pc = pc_state.get_pc();
+        let mut fields = Vec::new();
+        if self.fields.cycles {
+            fields.push(format!("{:>10}", cycles));
+        }
+        if self.fields.pc {
+            fields.push(match memory.get_bank(pc) {
+                Some(bank) => format!("{:02X}:{:04X}", bank, pc),
+                None => format!("--:{:04X}", pc),
+            });
+        }
+        if self.fields.disassembly {
+            let read = |address: u16| memory.peek(address);
+            fields.push(format!(
+                "{:<20}",
+                disassembler::Disassembler::disassemble(&read, pc).text
+            ));
+        }
+        if self.fields.registers {
+            fields.push(format!(
+                "AF={:04X} BC={:04X} DE={:04X} HL={:04X} IX={:04X} IY={:04X} SP={:04X}",
+                pc_state.get_af(),
+                pc_state.get_bc(),
+                pc_state.get_de(),
+                pc_state.get_hl(),
+                pc_state.index_registers.ix_reg.get(),
+                pc_state.index_registers.iy_reg.get(),
+                pc_state.get_sp()
+            ));
+        }
+        if self.fields.flags {
+            // Set flags are shown by letter (bit 7 to 0), clear ones by '-'.
+            let flags = pc_state.get_f().0;
+            fields.push(
+                "SZ5H3PNC"
+                    .chars()
+                    .enumerate()
+                    .map(|(i, name)| if flags & (0x80 >> i) != 0 { name } else { '-' })
+                    .collect(),
+            );
+        }
+        fields.join(" ").trim_end().to_string()
+    }
+}
+
+impl Drop for TraceLogger {
+    fn drop(&mut self) {
+        if let TraceOutput::File(file) = &mut self.output {
+            file.flush().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::cpu::pc_state;
+    use crate::sega::cpu::trace;
+    use crate::sega::memory::flat;
+
+    #[test]
+    fn test_trace_line() {
+        let filename = std::env::temp_dir().join("rustsega_test_trace_line.txt");
+        let mut logger = trace::TraceLogger::create(filename.to_str().unwrap(), None).unwrap();
+        let mut memory = flat::FlatMemory::new();
+        memory.load(0x0100, &[0x3E, 0x42]); // LD A,$42
+        let mut pc_state = pc_state::PcState::new();
+        pc_state.set_pc(0x0100);
+        pc_state.set_hl(0x1234);
+        pc_state.set_f(pc_state::PcStatusFlagFields(0x41));
+
+        assert_eq!(
+            logger.format_line(1234, &pc_state, &memory),
+            "      1234 --:0100 LD A,$42             \
+             AF=0041 BC=0000 DE=0000 HL=1234 IX=0000 IY=0000 SP=0000 -Z-----C"
+        );
+        logger.set_fields(trace::TraceFields::from_names("pc, disasm").unwrap());
+        assert_eq!(
+            logger.format_line(1234, &pc_state, &memory),
+            "--:0100 LD A,$42"
+        );
+        assert!(trace::TraceFields::from_names("pc,bank").is_none());
+        std::fs::remove_file(filename).ok();
+    }
+
+    #[test]
+    fn test_trace_triggers() {
+        let filename = std::env::temp_dir().join("rustsega_test_trace_triggers.txt");
+        let mut logger = trace::TraceLogger::create(filename.to_str().unwrap(), None).unwrap();
+        logger.set_start_pc(0x10);
+        logger.set_stop_pc(0x20);
+        logger.set_frames(2, 3);
+        assert!(!logger.is_due(0x00, 2));
+        assert!(logger.is_due(0x10, 2));
+        assert!(logger.is_due(0x00, 3));
+        assert!(!logger.is_due(0x00, 4));
+        assert!(!logger.is_due(0x20, 3));
+        assert!(!logger.is_due(0x10, 3));
+
+        assert_eq!(trace::TraceLogger::parse_frames("10-20"), Some((10, 20)));
+        assert_eq!(trace::TraceLogger::parse_frames("7"), Some((7, 7)));
+        assert_eq!(trace::TraceLogger::parse_frames("20-10"), None);
+        std::fs::remove_file(filename).ok();
+    }
+
+    #[test]
+    fn test_trace_ring() {
+        let filename = std::env::temp_dir().join("rustsega_test_trace_ring.txt");
+        let mut logger = trace::TraceLogger::create(filename.to_str().unwrap(), Some(2)).unwrap();
+        logger.set_fields(trace::TraceFields::from_names("pc").unwrap());
+        let memory = flat::FlatMemory::new();
+        let mut pc_state = pc_state::PcState::new();
+        for pc in 0..4 {
+            pc_state.set_pc(pc);
+            logger.log(0, &pc_state, &memory);
+        }
+        logger.dump().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&filename).unwrap(),
+            "--:0002\n--:0003\n"
+        );
+        std::fs::remove_file(&filename).ok();
+
+        // The panic hook dumps the latest ring.
+        let latest = trace::TraceLogger::create(filename.to_str().unwrap(), Some(2)).unwrap();
+        let current = trace::PANIC_RING
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .upgrade();
+        match (&latest.output, current) {
+            (trace::TraceOutput::Ring(ring), Some(current)) => {
+                assert!(std::sync::Arc::ptr_eq(ring, &current))
+            }
+            _ => panic!("Expected the latest ring"),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use super::super::clocks;
+use super::super::interruptor;
+use super::super::memory::flat;
+use super::super::memory::memory::MemoryRW;
+use super::super::ports;
+use super::core;
+use super::pc_state;
+
+// Runs the CP/M instruction exercisers (zexdoc/zexall, or anything else
+// that only prints through the BDOS) on a flat 64K memory.  The program is
+// loaded at 0x0100, the BDOS calls at 0x0005 are trapped (function 2 prints
+// E, function 9 prints the '$' terminated string at DE) and a jump to
+// 0x0000 (warm boot) ends the run.
+
+// Result of each test group, from the "<name>....  OK" / "ERROR" lines.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ZexGroup {
+    pub name: String,
+    pub passed: bool,
+}
+
+pub struct ZexRunner {
+    core: core::Core<flat::FlatMemory>,
+    line: String, // Output since the last newline.
+    pub groups: Vec<ZexGroup>,
+}
+
+impl ZexRunner {
+    const BOOT_ADDRESS: u16 = 0x0000;
+    const BDOS_ADDRESS: u16 = 0x0005;
+    const LOAD_ADDRESS: u16 = 0x0100;
+    const STACK_TOP: u16 = 0xF000; // Read from 0x0006 by the exercisers.
+
+    pub fn new(program: &[u8]) -> Self {
+        let mut memory = flat::FlatMemory::new();
+        memory.load(ZexRunner::BOOT_ADDRESS, &[0x76]); // HALT, never run.
+        let stack_top = ZexRunner::STACK_TOP.to_le_bytes();
+        memory.load(ZexRunner::BDOS_ADDRESS, &[0xC9, stack_top[0], stack_top[1]]); // RET
+        memory.load(ZexRunner::LOAD_ADDRESS, program);
+
+        // Return to the warm boot address at the end of the program.
+        let mut pc_state = pc_state::PcState::new();
+        pc_state.sp_reg.set(ZexRunner::STACK_TOP.wrapping_sub(2));
+        memory.load(pc_state.get_sp(), &ZexRunner::BOOT_ADDRESS.to_le_bytes());
+        pc_state.set_pc(ZexRunner::LOAD_ADDRESS);
+
+        Self {
+            core: core::Core::new(
+                clocks::Clock::new(),
+                memory,
+                pc_state,
+                ports::Ports::new(),
+                interruptor::Interruptor::new(),
+            ),
+            line: String::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    // Run until the program exits, passing the printed text to 'output' as
+    // it's produced.
+    pub fn run<F>(&mut self, mut output: F) -> Result<(), String>
+    where
+        F: FnMut(&str),
+    {
+        loop {
+            match self.core.get_pc_state().get_pc() {
+                ZexRunner::BOOT_ADDRESS => return Ok(()),
+                ZexRunner::BDOS_ADDRESS => {
+                    let text = self.bdos_call()?;
+                    self.add_output(&text);
+                    output(&text);
+                }
+                _ => {}
+            }
+            self.core.step(false, false).map_err(|e| e.to_string())?;
+        }
+    }
+
+    pub fn passed(&self) -> usize {
+        self.groups.iter().filter(|group| group.passed).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.groups.len() - self.passed()
+    }
+
+    fn bdos_call(&self) -> Result<String, String> {
+        let pc_state = self.core.get_pc_state();
+        match pc_state.get_c() {
+            2 => Ok((pc_state.get_e() as char).to_string()),
+            9 => {
+                let mut text = String::new();
+                let mut address = pc_state.get_de();
+                loop {
+                    match self.core.memory.read(address) {
+                        b'$' => return Ok(text),
+                        value => text.push(value as char),
+                    }
+                    address = address.wrapping_add(1);
+                }
+            }
+            function => Err(format!("Unsupported BDOS function {}", function)),
+        }
+    }
+
+    fn add_output(&mut self, text: &str) {
+        for c in text.chars() {
+            match c {
+                '\n' => {
+                    let line = std::mem::take(&mut self.line);
+                    if let Some(group) = ZexRunner::parse_group(&line) {
+                        self.groups.push(group);
+                    }
+                }
+                '\r' => {}
+                _ => self.line.push(c),
+            }
+        }
+    }
+
+    fn parse_group(line: &str) -> Option<ZexGroup> {
+        let (name, result) = line.split_once("..")?;
+        let result = result.trim_start_matches('.').trim_start();
+        let passed = if result.starts_with("OK") {
+            true
+        } else if result.starts_with("ERROR") {
+            false
+        } else {
+            return None;
+        };
+        Some(ZexGroup {
+            name: name.trim().to_string(),
+            passed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::cpu::zex;
+
+    #[test]
+    fn test_zex_bdos() {
+        // Prints a group result with each BDOS function, then returns to CP/M.
+        let program = [
+            0x0E, 0x09, // LD C,9
+            0x11, 0x0F, 0x01, // LD DE,$010F
+            0xCD, 0x05, 0x00, // CALL $0005
+            0x0E, 0x02, // LD C,2
+            0x1E, 0x0A, // LD E,$0A
+            0xC3, 0x05, 0x00, // JP $0005
+        ];
+        let text = b"add hl,<bc,de,hl,sp>....  OK\r\nalu a,n.... ERROR\r$";
+        let mut runner = zex::ZexRunner::new(&[&program[..], &text[..]].concat());
+        let mut output = String::new();
+        runner.run(|text| output.push_str(text)).unwrap();
+
+        assert_eq!(
+            output,
+            "add hl,<bc,de,hl,sp>....  OK\r\nalu a,n.... ERROR\r\n"
+        );
+        assert_eq!(
+            runner.groups,
+            vec![
+                zex::ZexGroup {
+                    name: "add hl,<bc,de,hl,sp>".to_string(),
+                    passed: true
+                },
+                zex::ZexGroup {
+                    name: "alu a,n".to_string(),
+                    passed: false
+                },
+            ]
+        );
+        assert_eq!((runner.passed(), runner.failed()), (1, 1));
+    }
+
+    // Run with the path to zexdoc.com or zexall.com, eg:
+    //   RUSTSEGA_ZEX=zexdoc.com cargo test -- --ignored test_zex_program
+    #[test]
+    #[ignore]
+    fn test_zex_program() {
+        let filename = std::env::var("RUSTSEGA_ZEX").expect("RUSTSEGA_ZEX not set");
+        let program = std::fs::read(&filename).unwrap();
+        let mut runner = zex::ZexRunner::new(&program);
+        runner.run(|text| print!("{}", text)).unwrap();
+
+        assert!(!runner.groups.is_empty());
+        let failed: Vec<_> = runner
+            .groups
+            .iter()
+            .filter(|group| !group.passed)
+            .map(|group| group.name.as_str())
+            .collect();
+        assert!(failed.is_empty(), "Failed groups: {:?}", failed);
+    }
+}
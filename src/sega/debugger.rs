@@ -0,0 +1,689 @@
+// Interactive (terminal) debugger.  The emulation checks 'should_break'
+// before each instruction, on a break 'prompt' reads commands from stdin
+// until the emulation is resumed.  Memory and port watches stop after the
+// instruction that made the access.
+use super::cpu::core;
+use super::cpu::disassembler;
+use super::cpu::pc_state;
+use super::memory::memory;
+use super::watch;
+use std::collections::BTreeSet;
+use std::io;
+use std::io::Write;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resume {
+    Run,
+    Quit,
+}
+
+// What to do on an unimplemented opcode (see 'cpu::error::CpuError').
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CpuErrorAction {
+    Ask,   // Ask on the terminal.
+    Debug, // Stop in the debugger.
+    Nop,   // Skip it, as if it were a NOP.
+    Stop,  // Stop the emulation.
+}
+
+impl CpuErrorAction {
+    pub fn from_name(name: &str) -> Option<CpuErrorAction> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "a" | "ask" => Some(CpuErrorAction::Ask),
+            "d" | "debug" => Some(CpuErrorAction::Debug),
+            "n" | "nop" => Some(CpuErrorAction::Nop),
+            "s" | "stop" => Some(CpuErrorAction::Stop),
+            _ => None,
+        }
+    }
+
+    // Ask on the terminal (stopping if there's no input).
+    pub fn ask() -> CpuErrorAction {
+        loop {
+            print!("[d]ebug, [n]op or [s]top? ");
+            io::stdout().flush().ok();
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => return CpuErrorAction::Stop,
+                Ok(_) => {}
+            }
+            match CpuErrorAction::from_name(&line) {
+                Some(CpuErrorAction::Ask) | None => {}
+                Some(action) => return action,
+            }
+        }
+    }
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    break_requested: bool,              // Break before the next instruction.
+    run_to: Option<u16>,                // Run to cursor.
+    step_over: Option<(u16, u16)>,      // Return address and stack pointer of the call.
+    step_out_sp: Option<u16>,           // Break once the stack is above this.
+    last_command: String,               // Repeated by an empty line.
+    watch_hit: Option<watch::WatchHit>, // Watch that caused the last break.
+}
+
+impl Debugger {
+    const DEFAULT_DUMP_LENGTH: u16 = 0x40;
+    const DUMP_BYTES_PER_LINE: u16 = 16;
+    const DEFAULT_LIST_COUNT: u16 = 10;
+
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            break_requested: false,
+            run_to: None,
+            step_over: None,
+            step_out_sp: None,
+            last_command: String::new(),
+            watch_hit: None,
+        }
+    }
+
+    // Parse a (hex) address or value, with an optional '0x' or '$' prefix.
+    pub fn parse_value(text: &str) -> Option<u16> {
+        let text = text.trim();
+        let digits = text
+            .strip_prefix("0x")
+            .or_else(|| text.strip_prefix("0X"))
+            .or_else(|| text.strip_prefix('$'))
+            .unwrap_or(text);
+        u16::from_str_radix(digits, 16).ok()
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn get_breakpoints(&self) -> Vec<u16> {
+        self.breakpoints.iter().copied().collect()
+    }
+
+    // Break before the next instruction (the debugger hotkey).
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
+    }
+
+    // Check whether to stop before the instruction at the current PC.
+    pub fn should_break<M: memory::MemoryRW>(&mut self, core: &mut core::Core<M>) -> bool {
+        self.watch_hit = core.take_watch_hit();
+        let pc_state = core.get_pc_state();
+        let pc = pc_state.get_pc();
+        let sp = pc_state.get_sp();
+        let stop = self.watch_hit.is_some()
+            || self.break_requested
+            || self.breakpoints.contains(&pc)
+            || self.run_to == Some(pc)
+            || self
+                .step_over
+                .is_some_and(|(address, call_sp)| address == pc && sp >= call_sp)
+            || self.step_out_sp.is_some_and(|out_sp| sp > out_sp);
+        if stop {
+            self.break_requested = false;
+            self.run_to = None;
+            self.step_over = None;
+            self.step_out_sp = None;
+        }
+        stop
+    }
+
+    // Read and run commands until the emulation is resumed (or quit).
+    pub fn prompt<M: memory::MemoryRW>(&mut self, core: &mut core::Core<M>) -> Resume {
+        if let Some(watch_hit) = self.watch_hit {
+            println!("Watch: {}", watch_hit);
+        }
+        Debugger::print_registers(core.get_pc_state());
+        Debugger::print_next(core);
+        loop {
+            print!("debug> ");
+            io::stdout().flush().ok();
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    // No more input, so carry on without the debugger.
+                    println!("Debugger input closed, clearing the breakpoints.");
+                    self.breakpoints.clear();
+                    return Resume::Run;
+                }
+                Ok(_) => {}
+            }
+            if let Some(resume) = self.execute(&line, core) {
+                return resume;
+            }
+        }
+    }
+
+    // Run a single command, returns how to resume (or 'None' to keep prompting).
+    pub fn execute<M: memory::MemoryRW>(
+        &mut self,
+        line: &str,
+        core: &mut core::Core<M>,
+    ) -> Option<Resume> {
+        let resume = self.run_command(line, core);
+        // The debugger's own reads don't trigger the watches.
+        core.take_watch_hit();
+        resume
+    }
+
+    fn run_command<M: memory::MemoryRW>(
+        &mut self,
+        line: &str,
+        core: &mut core::Core<M>,
+    ) -> Option<Resume> {
+        let line = if line.trim().is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = line.trim().to_string();
+            line.trim().to_string()
+        };
+        let arguments: Vec<&str> = line.split_whitespace().collect();
+        let (command, arguments) = arguments.split_first()?;
+
+        match *command {
+            "c" | "continue" => return Some(Resume::Run),
+            "s" | "step" => {
+                self.break_requested = true;
+                return Some(Resume::Run);
+            }
+            "n" | "next" => {
+                self.set_step_over(core);
+                return Some(Resume::Run);
+            }
+            "o" | "out" => {
+                self.step_out_sp = Some(core.get_pc_state().get_sp());
+                return Some(Resume::Run);
+            }
+            "u" | "until" => match arguments.first().and_then(|a| Debugger::parse_value(a)) {
+                Some(address) => {
+                    self.run_to = Some(address);
+                    return Some(Resume::Run);
+                }
+                None => println!("Usage: until <address>"),
+            },
+            "b" | "break" => match arguments.first().and_then(|a| Debugger::parse_value(a)) {
+                Some(address) => {
+                    self.add_breakpoint(address);
+                    println!("Breakpoint at {:04X}", address);
+                }
+                None => self.print_breakpoints(),
+            },
+            "d" | "delete" => match arguments.first().and_then(|a| Debugger::parse_value(a)) {
+                Some(address) => {
+                    if !self.remove_breakpoint(address) {
+                        println!("No breakpoint at {:04X}", address);
+                    }
+                }
+                None => println!("Usage: delete <address>"),
+            },
+            "w" | "watch" => {
+                let watch = match arguments {
+                    ["mapper", value @ ..] => Debugger::parse_watch(&["w", "FFFC-FFFF"], value),
+                    [access, range, value @ ..] => Debugger::parse_watch(&[access, range], value),
+                    _ => Err("Usage: watch <r|w|rw> <addr>[-<end>] [value]".to_string()),
+                };
+                match (watch, core.memory.get_watches_mut()) {
+                    (Ok(watch), Some(watches)) => watches.add(watch),
+                    (Ok(_), None) => println!("Memory watches aren't supported."),
+                    (Err(e), _) => println!("{}", e),
+                }
+            }
+            "p" | "pwatch" => match arguments {
+                [access, range, value @ ..] => match Debugger::parse_watch(&[access, range], value)
+                {
+                    Ok(watch) => core.ports.watches.add(watch),
+                    Err(e) => println!("{}", e),
+                },
+                _ => println!("Usage: pwatch <r|w|rw> <port>[-<end>] [value]"),
+            },
+            "watches" => {
+                if let Some(watches) = core.memory.get_watches_mut() {
+                    Debugger::print_watches("Memory", watches);
+                }
+                Debugger::print_watches("Port", &core.ports.watches);
+            }
+            "unwatch" => {
+                if let Some(watches) = core.memory.get_watches_mut() {
+                    watches.clear();
+                }
+                core.ports.watches.clear();
+            }
+            "r" | "regs" => Debugger::print_registers(core.get_pc_state()),
+            "set" => match arguments {
+                [register, value] => match Debugger::parse_value(value) {
+                    Some(value) => {
+                        if let Err(e) =
+                            Debugger::set_register(core.get_pc_state_mut(), register, value)
+                        {
+                            println!("{}", e);
+                        }
+                    }
+                    None => println!("Invalid value: {}", value),
+                },
+                _ => println!("Usage: set <register> <value>"),
+            },
+            "flag" => match arguments {
+                [flag, value] => {
+                    if let Err(e) = Debugger::set_flag(core.get_pc_state_mut(), flag, value) {
+                        println!("{}", e);
+                    }
+                }
+                _ => println!("Usage: flag <c|n|pv|h|z|s> <0|1>"),
+            },
+            "l" | "list" => {
+                let address = arguments
+                    .first()
+                    .and_then(|a| Debugger::parse_value(a))
+                    .unwrap_or(core.get_pc_state().get_pc());
+                let count = arguments
+                    .get(1)
+                    .and_then(|a| Debugger::parse_value(a))
+                    .unwrap_or(Debugger::DEFAULT_LIST_COUNT);
+                print!("{}", Debugger::list(&core.memory, address, count));
+            }
+            "m" | "mem" => {
+                let address = arguments.first().and_then(|a| Debugger::parse_value(a));
+                let length = arguments.get(1).and_then(|a| Debugger::parse_value(a));
+                match address {
+                    Some(address) => print!(
+                        "{}",
+                        Debugger::dump_memory(
+                            &core.memory,
+                            address,
+                            length.unwrap_or(Debugger::DEFAULT_DUMP_LENGTH)
+                        )
+                    ),
+                    None => println!("Usage: mem <address> [length]"),
+                }
+            }
+            "q" | "quit" => return Some(Resume::Quit),
+            "h" | "help" => Debugger::print_help(),
+            _ => println!("Unknown command '{}' (try 'help').", command),
+        }
+        None
+    }
+
+    // Break after the current instruction, running calls (and repeated
+    // block instructions) through to the end.
+    fn set_step_over<M: memory::MemoryRW>(&mut self, core: &core::Core<M>) {
+        let pc = core.get_pc_state().get_pc();
+        let op_code = core.memory.peek(pc);
+        let length = match op_code {
+            0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC | 0xE4 | 0xEC | 0xF4 | 0xFC => 3, // CALL
+            _ if op_code & 0xC7 == 0xC7 => 1,                                  // RST
+            0xED => match core.memory.peek(pc.wrapping_add(1)) {
+                0xB0..=0xB3 | 0xB8..=0xBB => 2, // LDIR, CPIR, INIR, OTIR (and decrementing)
+                _ => 0,
+            },
+            _ => 0,
+        };
+        if length > 0 {
+            self.step_over = Some((pc.wrapping_add(length), core.get_pc_state().get_sp()));
+        } else {
+            self.break_requested = true;
+        }
+    }
+
+    // Parse '<access> <start>[-<end>]' and an optional value.
+    fn parse_watch(arguments: &[&str], value: &[&str]) -> Result<watch::Watch, String> {
+        let access = watch::Access::from_name(arguments[0])
+            .ok_or_else(|| format!("Invalid access (r, w or rw): {}", arguments[0]))?;
+        let (start, end) = arguments[1]
+            .split_once('-')
+            .unwrap_or((arguments[1], arguments[1]));
+        let start = Debugger::parse_value(start)
+            .ok_or_else(|| format!("Invalid address: {}", arguments[1]))?;
+        let end = Debugger::parse_value(end)
+            .ok_or_else(|| format!("Invalid address: {}", arguments[1]))?;
+        let value = match value.first() {
+            Some(value) => Some(
+                Debugger::parse_value(value)
+                    .filter(|value| *value <= 0xFF)
+                    .ok_or_else(|| format!("Invalid value: {}", value))? as u8,
+            ),
+            None => None,
+        };
+        Ok(watch::Watch::new(access, start, end.max(start), value))
+    }
+
+    fn print_watches(name: &str, watches: &watch::WatchList) {
+        for watch in watches.get_watches() {
+            println!("{} watch: {}", name, watch);
+        }
+    }
+
+    fn set_register(
+        pc_state: &mut pc_state::PcState,
+        register: &str,
+        value: u16,
+    ) -> Result<(), String> {
+        let byte = value as u8;
+        match register.to_ascii_lowercase().as_str() {
+            "a" => pc_state.set_a(byte),
+            "f" => pc_state.set_f(pc_state::PcStatusFlagFields(byte)),
+            "b" => pc_state.set_b(byte),
+            "c" => pc_state.set_c(byte),
+            "d" => pc_state.set_d(byte),
+            "e" => pc_state.set_e(byte),
+            "h" => pc_state.set_h(byte),
+            "l" => pc_state.set_l(byte),
+            "i" => pc_state.set_i(byte),
+            "r" => pc_state.set_r(byte),
+            "af" => pc_state.set_af(value),
+            "bc" => pc_state.set_bc(value),
+            "de" => pc_state.set_de(value),
+            "hl" => pc_state.set_hl(value),
+            "ix" => pc_state.index_registers.ix_reg.set(value),
+            "iy" => pc_state.index_registers.iy_reg.set(value),
+            "sp" => pc_state.sp_reg.set(value),
+            "pc" => pc_state.set_pc(value),
+            _ => return Err(format!("Unknown register: {}", register)),
+        }
+        Ok(())
+    }
+
+    fn set_flag(pc_state: &mut pc_state::PcState, flag: &str, value: &str) -> Result<(), String> {
+        let value = match value {
+            "0" => 0,
+            "1" => 1,
+            _ => return Err(format!("Invalid flag value: {}", value)),
+        };
+        let mut flags = pc_state.get_f();
+        match flag.to_ascii_lowercase().as_str() {
+            "c" => flags.set_c(value),
+            "n" => flags.set_n(value),
+            "pv" => flags.set_pv(value),
+            "h" => flags.set_h(value),
+            "z" => flags.set_z(value),
+            "s" => flags.set_s(value),
+            _ => return Err(format!("Unknown flag: {}", flag)),
+        }
+        pc_state.set_f(flags);
+        Ok(())
+    }
+
+    // Hex dump, 16 bytes a line.
+    pub fn dump_memory<M: memory::MemoryRW>(memory: &M, address: u16, length: u16) -> String {
+        let mut dump = String::new();
+        let mut line_start = 0;
+        while line_start < length {
+            let count = (length - line_start).min(Debugger::DUMP_BYTES_PER_LINE);
+            let start = address.wrapping_add(line_start);
+            let bytes: Vec<u8> = (0..count)
+                .map(|offset| memory.peek(start.wrapping_add(offset)))
+                .collect();
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let text: String = bytes
+                .iter()
+                .map(|byte| {
+                    if byte.is_ascii_graphic() || *byte == b' ' {
+                        *byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            dump.push_str(&format!("{:04X}: {:<47}  {}\n", start, hex.join(" "), text));
+            line_start += count;
+        }
+        dump
+    }
+
+    fn print_registers(pc_state: &pc_state::PcState) {
+        println!(
+            "PC:{:04X} SP:{:04X} AF:{:04X} BC:{:04X} DE:{:04X} HL:{:04X} IX:{:04X} IY:{:04X}",
+            pc_state.get_pc(),
+            pc_state.get_sp(),
+            pc_state.get_af(),
+            pc_state.get_bc(),
+            pc_state.get_de(),
+            pc_state.get_hl(),
+            pc_state.index_registers.ix_reg.get(),
+            pc_state.index_registers.iy_reg.get(),
+        );
+        println!(
+            "I:{:02X} R:{:02X} IFF1:{} IM:{} {}",
+            pc_state.get_i(),
+            pc_state.get_r(),
+            pc_state.get_iff1(),
+            pc_state.get_im(),
+            pc_state.get_f()
+        );
+    }
+
+    // The bytes at the PC.
+    fn print_next<M: memory::MemoryRW>(core: &core::Core<M>) {
+        print!(
+            "{}",
+            Debugger::list(&core.memory, core.get_pc_state().get_pc(), 1)
+        );
+    }
+
+    // Disassemble 'count' instructions.
+    pub fn list<M: memory::MemoryRW>(memory: &M, address: u16, count: u16) -> String {
+        let read = |address: u16| memory.peek(address);
+        let mut listing = String::new();
+        let mut address = address;
+        for _ in 0..count {
+            let length = disassembler::Disassembler::disassemble(&read, address).length;
+            listing.push_str(&disassembler::Disassembler::listing(
+                &read,
+                address,
+                length as u32,
+            ));
+            address = address.wrapping_add(length);
+        }
+        listing
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() {
+            println!("No breakpoints.");
+        } else {
+            let addresses: Vec<String> = self
+                .breakpoints
+                .iter()
+                .map(|address| format!("{:04X}", address))
+                .collect();
+            println!("Breakpoints: {}", addresses.join(", "));
+        }
+    }
+
+    fn print_help() {
+        println!("Commands (addresses and values are hex):");
+        println!("  c, continue        run until a breakpoint");
+        println!("  s, step            run one instruction");
+        println!("  n, next            step, running over calls");
+        println!("  o, out             run until the current function returns");
+        println!("  u, until <addr>    run to the address (run to cursor)");
+        println!("  b, break [addr]    add a breakpoint (or list them)");
+        println!("  d, delete <addr>   remove a breakpoint");
+        println!("  w, watch <r|w|rw> <addr>[-<end>] [value]");
+        println!("                     stop after a memory access (of the value)");
+        println!("  watch mapper [value]  stop after a mapper (paging) register write");
+        println!("  p, pwatch <r|w|rw> <port>[-<end>] [value]");
+        println!("                     stop after a port access (of the value)");
+        println!("  watches            list the watches");
+        println!("  unwatch            remove all of the watches");
+        println!("  r, regs            show the registers and flags");
+        println!("  set <reg> <value>  set a register (a-l, af-hl, ix, iy, sp, pc, i, r)");
+        println!("  flag <flag> <0|1>  set a flag (c, n, pv, h, z, s)");
+        println!("  l, list [addr] [n] disassemble (from the PC)");
+        println!("  m, mem <addr> [n]  dump memory");
+        println!("  q, quit            quit the emulator");
+        println!("An empty line repeats the last command.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::clocks;
+    use crate::sega::cpu::core;
+    use crate::sega::cpu::pc_state;
+    use crate::sega::debugger;
+    use crate::sega::interruptor;
+    use crate::sega::memory::flat;
+    use crate::sega::memory::watched;
+    use crate::sega::ports;
+
+    const PROGRAM: [u8; 8] = [
+        0xCD, 0x06, 0x00, // CALL 0x0006
+        0x00, // NOP
+        0x18, 0xFE, // JR -2
+        0x00, // NOP
+        0xC9, // RET
+    ];
+    const OUT_ADDRESS: u16 = 0x0010;
+    const OUT_PROGRAM: [u8; 2] = [0xD3, 0x3F]; // OUT (0x3F),A
+
+    type TestCore = core::Core<watched::WatchedMemory<flat::FlatMemory>>;
+
+    fn build_core() -> TestCore {
+        let mut memory = flat::FlatMemory::new();
+        memory.load(0, &PROGRAM);
+        memory.load(OUT_ADDRESS, &OUT_PROGRAM);
+        memory.load(0x8000, b"Hello\x00");
+        let mut pc_state = pc_state::PcState::new();
+        pc_state.sp_reg.set(0xF000);
+        core::Core::new(
+            clocks::Clock::new(),
+            watched::WatchedMemory::new(memory),
+            pc_state,
+            ports::Ports::new(),
+            interruptor::Interruptor::new(),
+        )
+    }
+
+    // Step until the debugger breaks, returns the PC.
+    fn run(debugger: &mut debugger::Debugger, core: &mut TestCore) -> u16 {
+        for _ in 0..100 {
+            core.step(false, false).unwrap();
+            if debugger.should_break(core) {
+                return core.get_pc_state().get_pc();
+            }
+        }
+        panic!("Didn't break");
+    }
+
+    #[test]
+    fn test_stepping() {
+        let mut debugger = debugger::Debugger::new();
+        let mut core = build_core();
+
+        assert_eq!(
+            debugger.execute("step", &mut core),
+            Some(debugger::Resume::Run)
+        );
+        assert_eq!(run(&mut debugger, &mut core), 0x0006);
+        assert_eq!(
+            debugger.execute("out", &mut core),
+            Some(debugger::Resume::Run)
+        );
+        assert_eq!(run(&mut debugger, &mut core), 0x0003);
+
+        // Over the call.
+        core.get_pc_state_mut().set_pc(0);
+        debugger.execute("next", &mut core);
+        assert_eq!(run(&mut debugger, &mut core), 0x0003);
+
+        // Run to cursor, then a breakpoint.
+        core.get_pc_state_mut().set_pc(0);
+        debugger.execute("until 7", &mut core);
+        assert_eq!(run(&mut debugger, &mut core), 0x0007);
+        debugger.execute("break $4", &mut core);
+        assert_eq!(debugger.get_breakpoints(), vec![0x0004]);
+        assert_eq!(run(&mut debugger, &mut core), 0x0004);
+        // An empty line repeats the command.
+        debugger.execute("", &mut core);
+        assert_eq!(debugger.get_breakpoints(), vec![0x0004]);
+        debugger.execute("delete 4", &mut core);
+        assert!(debugger.get_breakpoints().is_empty());
+
+        assert_eq!(
+            debugger.execute("quit", &mut core),
+            Some(debugger::Resume::Quit)
+        );
+    }
+
+    #[test]
+    fn test_edit_registers() {
+        let mut debugger = debugger::Debugger::new();
+        let mut core = build_core();
+
+        assert_eq!(debugger.execute("set hl 0x1234", &mut core), None);
+        debugger.execute("set a 56", &mut core);
+        debugger.execute("set ix ABCD", &mut core);
+        debugger.execute("flag z 1", &mut core);
+        debugger.execute("flag c 1", &mut core);
+        debugger.execute("flag c 0", &mut core);
+        let pc_state = core.get_pc_state();
+        assert_eq!(pc_state.get_hl(), 0x1234);
+        assert_eq!(pc_state.get_a(), 0x56);
+        assert_eq!(pc_state.index_registers.ix_reg.get(), 0xABCD);
+        assert_eq!(pc_state.get_f().get_z(), 1);
+        assert_eq!(pc_state.get_f().get_c(), 0);
+
+        assert_eq!(debugger::Debugger::parse_value("0x10"), Some(0x10));
+        assert_eq!(debugger::Debugger::parse_value("zz"), None);
+    }
+
+    #[test]
+    fn test_watches() {
+        let mut debugger = debugger::Debugger::new();
+        let mut core = build_core();
+
+        // The call writes the return address (0x0003) to the stack.
+        debugger.execute("watch w EFFE 04", &mut core);
+        debugger.execute("watch rw EFFE-EFFF 03", &mut core);
+        debugger.execute("watch mapper", &mut core);
+        assert_eq!(core.memory.watches.get_watches().len(), 3);
+        assert_eq!(run(&mut debugger, &mut core), 0x0006);
+        assert_eq!(
+            debugger.watch_hit.unwrap().to_string(),
+            "memory write of 03 to EFFE"
+        );
+        // The return reads it back.
+        debugger.execute("continue", &mut core);
+        assert_eq!(run(&mut debugger, &mut core), 0x0003);
+
+        debugger.execute("unwatch", &mut core);
+        debugger.execute("pwatch w 3F", &mut core);
+        assert!(core.memory.watches.is_empty());
+        core.get_pc_state_mut().set_pc(OUT_ADDRESS);
+        assert_eq!(run(&mut debugger, &mut core), OUT_ADDRESS + 2);
+        assert_eq!(
+            debugger.watch_hit.unwrap().to_string(),
+            "port write of 00 to 003F"
+        );
+    }
+
+    #[test]
+    fn test_dump_memory() {
+        let core = build_core();
+        assert_eq!(
+            debugger::Debugger::dump_memory(&core.memory, 0x8000, 6),
+            format!("8000: {:<47}  Hello.\n", "48 65 6C 6C 6F 00")
+        );
+        assert_eq!(
+            debugger::Debugger::dump_memory(&core.memory, 0x8000, 0x20)
+                .lines()
+                .count(),
+            2
+        );
+        let listing = debugger::Debugger::list(&core.memory, 0, 3);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "0000: CD 06 00     CALL $0006",
+                "0003: 00           NOP",
+                "0004: 18 FE        JR $0004",
+            ]
+        );
+    }
+}
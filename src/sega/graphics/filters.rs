@@ -0,0 +1,513 @@
+// CPU side filters, applied to the exported display before it's copied to
+// the texture.  Pixels are handled as 'u32' (0x00RRGGBB, the RGB888 display
+// pixel format read as little endian).
+use super::ntsc;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    Nearest(u32), // Integer scale factor.
+    Scale2x,      // EPX/AdvMAME2x
+    Scale3x,      // AdvMAME3x
+    Hq2x,         // HQ2x style, edges blended by colour similarity.
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "none" | "nearest" => Some(Filter::Nearest(1)),
+            "nearest2x" => Some(Filter::Nearest(2)),
+            "nearest3x" => Some(Filter::Nearest(3)),
+            "nearest4x" => Some(Filter::Nearest(4)),
+            "scale2x" => Some(Filter::Scale2x),
+            "scale3x" => Some(Filter::Scale3x),
+            "hq2x" => Some(Filter::Hq2x),
+            _ => None,
+        }
+    }
+
+    pub fn scale(&self) -> u32 {
+        match self {
+            Filter::Nearest(scale) => *scale,
+            Filter::Scale2x | Filter::Hq2x => 2,
+            Filter::Scale3x => 3,
+        }
+    }
+}
+
+// Pixel aspect ratio used to size the displayed image.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AspectRatio {
+    Square,     // 1:1 pixels
+    Display4x3, // Whole image stretched to 4:3
+    Par8x7,     // 8:7 pixels (NTSC TV)
+}
+
+impl AspectRatio {
+    pub fn from_name(name: &str) -> Option<AspectRatio> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "square" | "1:1" => Some(AspectRatio::Square),
+            "4:3" => Some(AspectRatio::Display4x3),
+            "8:7" => Some(AspectRatio::Par8x7),
+            _ => None,
+        }
+    }
+
+    // Displayed size of a 'width' x 'height' image.
+    pub fn display_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            AspectRatio::Square => (width, height),
+            AspectRatio::Display4x3 => (height * 4 / 3, height),
+            AspectRatio::Par8x7 => (width * 8 / 7, height),
+        }
+    }
+}
+
+pub struct DisplayFilter {
+    pub filter: Filter,
+    pub scanlines: u8, // Percentage the scanlines are darkened by (0 for none).
+    pub aspect_ratio: AspectRatio,
+    pub integer_scale: bool, // Only scale the window contents by whole numbers.
+    pub ntsc: ntsc::NtscFilter, // Applied before scaling.
+    pub overscan: bool,      // Show the border around the display.
+
+    input: Vec<u32>,
+    output: Vec<u32>,
+}
+
+impl DisplayFilter {
+    // Visible overscan border (in console pixels) on a typical TV.
+    pub const OVERSCAN_WIDTH: u32 = 16;
+    pub const OVERSCAN_HEIGHT: u32 = 24;
+
+    // Colour differences (in YUV) treated as the same colour by 'Hq2x'.
+    const HQ_Y_THRESHOLD: i32 = 48;
+    const HQ_U_THRESHOLD: i32 = 7;
+    const HQ_V_THRESHOLD: i32 = 6;
+
+    pub fn new() -> Self {
+        Self {
+            filter: Filter::Nearest(1),
+            scanlines: 0,
+            aspect_ratio: AspectRatio::Square,
+            integer_scale: false,
+            ntsc: ntsc::NtscFilter::new(),
+            overscan: false,
+            input: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    // Scale of the filtered image (scanlines need at least 2 lines per line).
+    pub fn scale(&self) -> u32 {
+        let scale = self.filter.scale();
+        if self.scanlines > 0 {
+            scale.max(2)
+        } else {
+            scale
+        }
+    }
+
+    // Displayed size of the 'width' x 'height' console display, including
+    // the overscan border if shown, after the aspect ratio is applied.
+    pub fn display_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let (border_width, border_height) = self.border_size();
+        self.aspect_ratio
+            .display_size(width + 2 * border_width, height + 2 * border_height)
+    }
+
+    // Position and size of the console display within 'display_size'.
+    pub fn display_rect(&self, width: u32, height: u32) -> (i32, i32, u32, u32) {
+        let (border_width, border_height) = self.border_size();
+        let (total_width, total_height) = (width + 2 * border_width, height + 2 * border_height);
+        let (display_width, display_height) = self.display_size(width, height);
+        (
+            (display_width * border_width / total_width) as i32,
+            (display_height * border_height / total_height) as i32,
+            display_width * width / total_width,
+            display_height * height / total_height,
+        )
+    }
+
+    fn border_size(&self) -> (u32, u32) {
+        if self.overscan {
+            (
+                DisplayFilter::OVERSCAN_WIDTH,
+                DisplayFilter::OVERSCAN_HEIGHT,
+            )
+        } else {
+            (0, 0)
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.scale() == 1 && !self.ntsc.is_enabled()
+    }
+
+    // Filter 'raw_display' ('size' is width x height, 'bytes_per_pixel' bytes
+    // each) into 'buffer', which has 'pitch' bytes per row.
+    pub fn apply(
+        &mut self,
+        raw_display: &[u8],
+        size: (u32, u32),
+        bytes_per_pixel: usize,
+        buffer: &mut [u8],
+        pitch: usize,
+        frame_count: u64,
+    ) {
+        let (width, height) = size;
+        self.input.clear();
+        self.input.extend(
+            raw_display
+                .chunks(bytes_per_pixel)
+                .map(|pixel| u32::from_le_bytes([pixel[0], pixel[1], pixel[2], 0])),
+        );
+        self.ntsc.apply(
+            &mut self.input,
+            width as usize,
+            height as usize,
+            frame_count,
+        );
+        self.filter_frame(width, height);
+
+        let output_width = (width * self.scale()) as usize;
+        for (row, pixels) in buffer
+            .chunks_mut(pitch)
+            .zip(self.output.chunks(output_width))
+        {
+            for (destination, pixel) in row.chunks_mut(bytes_per_pixel).zip(pixels) {
+                destination[0..3].copy_from_slice(&pixel.to_le_bytes()[0..3]);
+            }
+        }
+    }
+
+    fn filter_frame(&mut self, width: u32, height: u32) {
+        let filter = match self.filter {
+            // Scanlines need at least two lines.
+            Filter::Nearest(1) if self.scanlines > 0 => Filter::Nearest(2),
+            filter => filter,
+        };
+        let scale = filter.scale();
+        let (width, height) = (width as usize, height as usize);
+        self.output.clear();
+        self.output
+            .resize(width * height * (scale * scale) as usize, 0);
+
+        let input = &self.input;
+        let pixel = |x: isize, y: isize| -> u32 {
+            // Edges are repeated.
+            let x = x.clamp(0, width as isize - 1) as usize;
+            let y = y.clamp(0, height as isize - 1) as usize;
+            input[y * width + x]
+        };
+
+        let scale = scale as usize;
+        let output_width = width * scale;
+        for y in 0..height {
+            for x in 0..width {
+                let (xi, yi) = (x as isize, y as isize);
+                let neighbour = |dx, dy| pixel(xi + dx, yi + dy);
+                let start = y * scale * output_width + x * scale;
+                let output = &mut self.output[start..];
+                match filter {
+                    Filter::Nearest(_) => {
+                        let value = pixel(xi, yi);
+                        for row in output.chunks_mut(output_width).take(scale) {
+                            row[..scale].fill(value);
+                        }
+                    }
+                    Filter::Scale2x => DisplayFilter::write_block(
+                        output,
+                        output_width,
+                        &DisplayFilter::scale2x(&neighbour),
+                    ),
+                    Filter::Scale3x => DisplayFilter::write_block(
+                        output,
+                        output_width,
+                        &DisplayFilter::scale3x(&neighbour),
+                    ),
+                    Filter::Hq2x => DisplayFilter::write_block(
+                        output,
+                        output_width,
+                        &DisplayFilter::hq2x(&neighbour),
+                    ),
+                }
+            }
+        }
+
+        if self.scanlines > 0 {
+            // Darken the last line of each scaled line.
+            let keep = 100 - self.scanlines.min(100) as u32;
+            for y in (scale - 1..height * scale).step_by(scale) {
+                for value in &mut self.output[y * output_width..(y + 1) * output_width] {
+                    *value = DisplayFilter::darken(*value, keep);
+                }
+            }
+        }
+    }
+
+    // Copy a square block into 'output', which starts at its top left corner.
+    fn write_block<const N: usize>(output: &mut [u32], output_width: usize, block: &[u32; N]) {
+        let scale = N.isqrt();
+        for (row, values) in output.chunks_mut(output_width).zip(block.chunks(scale)) {
+            row[..scale].copy_from_slice(values);
+        }
+    }
+
+    //   A        E0 E1
+    // C P B  ->  E2 E3
+    //   D
+    fn scale2x(pixel: &dyn Fn(isize, isize) -> u32) -> [u32; 4] {
+        let p = pixel(0, 0);
+        let (a, b, c, d) = (pixel(0, -1), pixel(1, 0), pixel(-1, 0), pixel(0, 1));
+        if a != d && c != b {
+            [
+                if c == a { c } else { p },
+                if a == b { b } else { p },
+                if c == d { c } else { p },
+                if d == b { b } else { p },
+            ]
+        } else {
+            [p; 4]
+        }
+    }
+
+    // A B C      E0 E1 E2
+    // D E F  ->  E3 E4 E5
+    // G H I      E6 E7 E8
+    fn scale3x(pixel: &dyn Fn(isize, isize) -> u32) -> [u32; 9] {
+        let (a, b, c) = (pixel(-1, -1), pixel(0, -1), pixel(1, -1));
+        let (d, e, f) = (pixel(-1, 0), pixel(0, 0), pixel(1, 0));
+        let (g, h, i) = (pixel(-1, 1), pixel(0, 1), pixel(1, 1));
+        if b != h && d != f {
+            [
+                if d == b { d } else { e },
+                if (d == b && e != c) || (b == f && e != a) {
+                    b
+                } else {
+                    e
+                },
+                if b == f { f } else { e },
+                if (d == b && e != g) || (d == h && e != a) {
+                    d
+                } else {
+                    e
+                },
+                e,
+                if (b == f && e != i) || (h == f && e != c) {
+                    f
+                } else {
+                    e
+                },
+                if d == h { d } else { e },
+                if (d == h && e != i) || (h == f && e != g) {
+                    h
+                } else {
+                    e
+                },
+                if h == f { f } else { e },
+            ]
+        } else {
+            [e; 9]
+        }
+    }
+
+    // Each corner of the output looks at its two neighbouring (edge) pixels,
+    // if they're alike but different to the centre the corner is blended
+    // towards them (smoothing diagonal edges), a lone corner gets a lighter
+    // blend.  Colours are compared in YUV, as HQ2x does.
+    fn hq2x(pixel: &dyn Fn(isize, isize) -> u32) -> [u32; 4] {
+        let e = pixel(0, 0);
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)].map(|(dx, dy)| {
+            let horizontal = pixel(dx, 0);
+            let vertical = pixel(0, dy);
+            let diagonal = pixel(dx, dy);
+            let like_horizontal = DisplayFilter::is_similar(e, horizontal);
+            let like_vertical = DisplayFilter::is_similar(e, vertical);
+            if !like_horizontal && !like_vertical {
+                if DisplayFilter::is_similar(horizontal, vertical) {
+                    if DisplayFilter::is_similar(diagonal, horizontal) {
+                        DisplayFilter::blend(&[(e, 2), (horizontal, 1), (vertical, 1)])
+                    } else {
+                        DisplayFilter::blend(&[(e, 6), (horizontal, 1), (vertical, 1)])
+                    }
+                } else {
+                    DisplayFilter::blend(&[(e, 3), (diagonal, 1)])
+                }
+            } else {
+                e
+            }
+        })
+    }
+
+    fn to_yuv(value: u32) -> (i32, i32, i32) {
+        let r = ((value >> 16) & 0xFF) as i32;
+        let g = ((value >> 8) & 0xFF) as i32;
+        let b = (value & 0xFF) as i32;
+        (
+            (r + g + b) / 3,
+            (r - b) / 4 + 128,
+            (2 * g - r - b) / 8 + 128,
+        )
+    }
+
+    fn is_similar(a: u32, b: u32) -> bool {
+        if a == b {
+            return true;
+        }
+        let (ya, ua, va) = DisplayFilter::to_yuv(a);
+        let (yb, ub, vb) = DisplayFilter::to_yuv(b);
+        (ya - yb).abs() <= DisplayFilter::HQ_Y_THRESHOLD
+            && (ua - ub).abs() <= DisplayFilter::HQ_U_THRESHOLD
+            && (va - vb).abs() <= DisplayFilter::HQ_V_THRESHOLD
+    }
+
+    // Weighted average of the colours.
+    fn blend(colours: &[(u32, u32)]) -> u32 {
+        let total: u32 = colours.iter().map(|(_, weight)| weight).sum();
+        let channel = |shift: u32| {
+            colours
+                .iter()
+                .map(|(value, weight)| ((value >> shift) & 0xFF) * weight)
+                .sum::<u32>()
+                / total
+        };
+        (channel(16) << 16) | (channel(8) << 8) | channel(0)
+    }
+
+    fn darken(value: u32, keep: u32) -> u32 {
+        let channel = |shift: u32| ((value >> shift) & 0xFF) * keep / 100;
+        (channel(16) << 16) | (channel(8) << 8) | channel(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::graphics::filters;
+    use crate::sega::graphics::ntsc;
+
+    const BLACK: u32 = 0x000000;
+    const WHITE: u32 = 0xFFFFFF;
+
+    // Filter a 'u32' image (4 bytes per pixel, no row padding).
+    fn run(filter: &mut filters::DisplayFilter, image: &[u32], width: u32) -> Vec<u32> {
+        let height = image.len() as u32 / width;
+        let raw: Vec<u8> = image.iter().flat_map(|pixel| pixel.to_le_bytes()).collect();
+        let scale = filter.scale();
+        let pitch = (width * scale * 4) as usize;
+        let mut buffer = vec![0; pitch * (height * scale) as usize];
+        filter.apply(&raw, (width, height), 4, &mut buffer, pitch, 0);
+        buffer
+            .chunks(4)
+            .map(|pixel| u32::from_le_bytes(pixel.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_nearest() {
+        let mut filter = filters::DisplayFilter::new();
+        assert!(filter.is_identity());
+        filter.filter = filters::Filter::from_name("nearest2x").unwrap();
+        assert_eq!(
+            run(&mut filter, &[BLACK, WHITE], 2),
+            [BLACK, BLACK, WHITE, WHITE, BLACK, BLACK, WHITE, WHITE]
+        );
+    }
+
+    #[test]
+    fn test_scale2x() {
+        // A diagonal edge gets smoothed, a flat area doesn't change.
+        let mut filter = filters::DisplayFilter::new();
+        filter.filter = filters::Filter::Scale2x;
+        #[rustfmt::skip]
+        let image = [
+            WHITE, BLACK,
+            BLACK, BLACK,
+        ];
+        let output = run(&mut filter, &image, 2);
+        // Top left pixel, bottom right quarter is filled in from its black neighbours.
+        assert_eq!(output[0..2], [WHITE, WHITE]);
+        assert_eq!(output[4..6], [WHITE, BLACK]);
+        assert!(output[8..16].iter().all(|pixel| *pixel == BLACK));
+    }
+
+    #[test]
+    fn test_scale3x() {
+        let mut filter = filters::DisplayFilter::new();
+        filter.filter = filters::Filter::Scale3x;
+        let output = run(&mut filter, &[WHITE; 4], 2);
+        assert_eq!(output.len(), 36);
+        assert!(output.iter().all(|pixel| *pixel == WHITE));
+
+        #[rustfmt::skip]
+        let image = [
+            WHITE, BLACK,
+            BLACK, BLACK,
+        ];
+        let output = run(&mut filter, &image, 2);
+        // Bottom right corner of the white pixel is black, its centre stays white.
+        assert_eq!(output[6 + 1], WHITE);
+        assert_eq!(output[2 * 6 + 2], BLACK);
+    }
+
+    #[test]
+    fn test_hq2x() {
+        let mut filter = filters::DisplayFilter::new();
+        filter.filter = filters::Filter::Hq2x;
+        #[rustfmt::skip]
+        let image = [
+            WHITE, BLACK,
+            BLACK, BLACK,
+        ];
+        let output = run(&mut filter, &image, 2);
+        // The white pixel's corner towards the black area is blended.
+        assert_eq!(output[0], WHITE);
+        let corner = output[4 + 1];
+        assert!(corner != WHITE && corner != BLACK);
+        assert_eq!(output[3 * 4 + 3], BLACK);
+    }
+
+    #[test]
+    fn test_scanlines() {
+        let mut filter = filters::DisplayFilter::new();
+        filter.scanlines = 50;
+        assert_eq!(filter.scale(), 2);
+        let output = run(&mut filter, &[WHITE], 1);
+        assert_eq!(output, [WHITE, WHITE, 0x7F7F7F, 0x7F7F7F]);
+    }
+
+    #[test]
+    fn test_ntsc_stage() {
+        // The NTSC filter runs (at 1x) before any scaling.
+        let mut filter = filters::DisplayFilter::new();
+        filter.ntsc.mode = ntsc::NtscMode::Composite;
+        assert!(!filter.is_identity());
+        let image: Vec<u32> = (0..64)
+            .map(|i| if i % 2 == 0 { WHITE } else { BLACK })
+            .collect();
+        let output = run(&mut filter, &image, 16);
+        assert_eq!(output.len(), 64);
+        assert!(output[16 + 8] != WHITE && output[16 + 8] != BLACK);
+    }
+
+    #[test]
+    fn test_overscan() {
+        let mut filter = filters::DisplayFilter::new();
+        assert_eq!(filter.display_size(256, 192), (256, 192));
+        assert_eq!(filter.display_rect(256, 192), (0, 0, 256, 192));
+        filter.overscan = true;
+        assert_eq!(filter.display_size(256, 192), (288, 240));
+        assert_eq!(filter.display_rect(256, 192), (16, 24, 256, 192));
+        filter.aspect_ratio = filters::AspectRatio::Par8x7;
+        assert_eq!(filter.display_size(256, 192), (329, 240));
+        assert_eq!(filter.display_rect(256, 192), (18, 24, 292, 192));
+    }
+
+    #[test]
+    fn test_aspect_ratio() {
+        let aspect = |name| filters::AspectRatio::from_name(name).unwrap();
+        assert_eq!(aspect("square").display_size(256, 192), (256, 192));
+        assert_eq!(aspect("4:3").display_size(256, 224), (298, 224));
+        assert_eq!(aspect("8:7").display_size(256, 192), (292, 192));
+        assert!(filters::AspectRatio::from_name("16:9").is_none());
+    }
+}
@@ -1,2 +1,7 @@
 pub mod display;
+pub mod filters;
+pub mod ntsc;
+pub mod png;
+pub mod screenshot;
 pub mod vdp;
+pub mod y4m;
@@ -0,0 +1,305 @@
+// NTSC video signal filter, applied to the display before it's scaled.
+// Each line is encoded as the TV signal would be (luma plus chroma modulated
+// on the colour subcarrier), sampled 3 times per pixel, then decoded again:
+//   - Luma is separated from composite with a low pass filter, leaving some
+//     chroma in the luma (dot patterns on colour edges), and taking some
+//     luma detail as chroma (colour fringes on fine patterns, which is what
+//     blends dithering).
+//   - Chroma is band limited, so colours bleed along the line.
+//   - The subcarrier phase changes by half a cycle each line and each frame,
+//     so the dot patterns move (dot crawl).
+// Pixels are 'u32' (0x00RRGGBB), as in 'filters'.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NtscMode {
+    Rgb,       // Passthrough.
+    SVideo,    // Separate luma and chroma, only the chroma bleeds.
+    Composite, // Luma and chroma combined.
+    Rf,        // Composite, with less bandwidth.
+}
+
+impl NtscMode {
+    pub fn from_name(name: &str) -> Option<NtscMode> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "rgb" | "none" => Some(NtscMode::Rgb),
+            "svideo" | "s-video" => Some(NtscMode::SVideo),
+            "composite" => Some(NtscMode::Composite),
+            "rf" => Some(NtscMode::Rf),
+            _ => None,
+        }
+    }
+}
+
+// Filter widths, in samples.
+struct NtscPreset {
+    luma_taps: usize,
+    chroma_taps: usize,
+    combined: bool, // Luma and chroma share the signal.
+}
+
+pub struct NtscFilter {
+    pub mode: NtscMode,
+
+    signal: Vec<f32>,
+    luma: Vec<f32>,
+    i: Vec<f32>,
+    q: Vec<f32>,
+    filtered: Vec<f32>,
+}
+
+impl NtscFilter {
+    const SAMPLES_PER_PIXEL: usize = 3;
+    // The subcarrier is 2/3 of the pixel clock, so a cycle is 4.5 samples (80 degrees a sample).
+    const PHASE_STEP: usize = 80;
+    const PHASE_ANGLES: usize = 9; // Multiples of 'PHASE_STEP' are all multiples of 40 degrees.
+
+    const SVIDEO: NtscPreset = NtscPreset {
+        luma_taps: 1,
+        chroma_taps: 7,
+        combined: false,
+    };
+    const COMPOSITE: NtscPreset = NtscPreset {
+        luma_taps: 5,
+        chroma_taps: 9,
+        combined: true,
+    };
+    const RF: NtscPreset = NtscPreset {
+        luma_taps: 7,
+        chroma_taps: 13,
+        combined: true,
+    };
+
+    pub fn new() -> Self {
+        Self {
+            mode: NtscMode::Rgb,
+            signal: Vec::new(),
+            luma: Vec::new(),
+            i: Vec::new(),
+            q: Vec::new(),
+            filtered: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mode != NtscMode::Rgb
+    }
+
+    // Filter the 'width' x 'height' 'pixels' in place, 'frame_count' sets the
+    // subcarrier phase (for dot crawl).
+    pub fn apply(&mut self, pixels: &mut [u32], width: usize, height: usize, frame_count: u64) {
+        let preset = match self.mode {
+            NtscMode::Rgb => return,
+            NtscMode::SVideo => NtscFilter::SVIDEO,
+            NtscMode::Composite => NtscFilter::COMPOSITE,
+            NtscMode::Rf => NtscFilter::RF,
+        };
+
+        // cos/sin for each phase angle (multiples of 40 degrees).
+        let angles: Vec<(f32, f32)> = (0..NtscFilter::PHASE_ANGLES)
+            .map(|angle| {
+                let radians = (angle as f32 * 40.0).to_radians();
+                (radians.cos(), radians.sin())
+            })
+            .collect();
+
+        for (y, line) in pixels.chunks_mut(width).take(height).enumerate() {
+            // Half a cycle (180 degrees, inverting the subcarrier) each line and frame.
+            let sign = if (y as u64 + frame_count).is_multiple_of(2) {
+                1.0
+            } else {
+                -1.0
+            };
+            let phase = |sample: usize| {
+                let (cos, sin) = angles[((sample * NtscFilter::PHASE_STEP) % 360) / 40];
+                (sign * cos, sign * sin)
+            };
+            self.filter_line(line, &preset, &phase);
+        }
+    }
+
+    fn filter_line(
+        &mut self,
+        line: &mut [u32],
+        preset: &NtscPreset,
+        phase: &dyn Fn(usize) -> (f32, f32),
+    ) {
+        let samples = line.len() * NtscFilter::SAMPLES_PER_PIXEL;
+
+        // Encode.
+        self.signal.clear();
+        self.luma.clear();
+        for sample in 0..samples {
+            let (y, i, q) = NtscFilter::rgb_to_yiq(line[sample / NtscFilter::SAMPLES_PER_PIXEL]);
+            let (cos, sin) = phase(sample);
+            let chroma = i * cos + q * sin;
+            if preset.combined {
+                self.signal.push(y + chroma);
+            } else {
+                self.signal.push(chroma);
+                self.luma.push(y);
+            }
+        }
+
+        // Separate luma (low pass), the rest of the signal is chroma.
+        if preset.combined {
+            NtscFilter::box_filter(&self.signal, preset.luma_taps, &mut self.luma);
+            for (signal, luma) in self.signal.iter_mut().zip(&self.luma) {
+                *signal -= luma;
+            }
+        }
+
+        // Demodulate the chroma, then band limit it.
+        self.filtered.clear();
+        self.filtered
+            .extend((0..samples).map(|sample| 2.0 * self.signal[sample] * phase(sample).0));
+        NtscFilter::box_filter(&self.filtered, preset.chroma_taps, &mut self.i);
+        self.filtered.clear();
+        self.filtered
+            .extend((0..samples).map(|sample| 2.0 * self.signal[sample] * phase(sample).1));
+        NtscFilter::box_filter(&self.filtered, preset.chroma_taps, &mut self.q);
+
+        // Decode, each pixel is the average of its samples.
+        for (index, pixel) in line.iter_mut().enumerate() {
+            let start = index * NtscFilter::SAMPLES_PER_PIXEL;
+            let range = start..start + NtscFilter::SAMPLES_PER_PIXEL;
+            let average = |values: &[f32]| {
+                values[range.clone()].iter().sum::<f32>() / NtscFilter::SAMPLES_PER_PIXEL as f32
+            };
+            *pixel =
+                NtscFilter::yiq_to_rgb(average(&self.luma), average(&self.i), average(&self.q));
+        }
+    }
+
+    // Moving average over 'taps' samples (centred, the ends are repeated).
+    fn box_filter(input: &[f32], taps: usize, output: &mut Vec<f32>) {
+        output.clear();
+        let half = (taps / 2) as isize;
+        let last = input.len() as isize - 1;
+        let mut sum: f32 = (-half..=half)
+            .map(|offset| input[offset.clamp(0, last) as usize])
+            .sum();
+        for index in 0..input.len() as isize {
+            output.push(sum / (2 * half + 1) as f32);
+            sum += input[(index + half + 1).clamp(0, last) as usize];
+            sum -= input[(index - half).clamp(0, last) as usize];
+        }
+    }
+
+    fn rgb_to_yiq(value: u32) -> (f32, f32, f32) {
+        let r = ((value >> 16) & 0xFF) as f32 / 255.0;
+        let g = ((value >> 8) & 0xFF) as f32 / 255.0;
+        let b = (value & 0xFF) as f32 / 255.0;
+        (
+            0.299 * r + 0.587 * g + 0.114 * b,
+            0.596 * r - 0.274 * g - 0.322 * b,
+            0.211 * r - 0.523 * g + 0.312 * b,
+        )
+    }
+
+    fn yiq_to_rgb(y: f32, i: f32, q: f32) -> u32 {
+        let channel = |value: f32| (value * 255.0).round().clamp(0.0, 255.0) as u32;
+        let r = channel(y + 0.956 * i + 0.621 * q);
+        let g = channel(y - 0.272 * i - 0.647 * q);
+        let b = channel(y - 1.106 * i + 1.703 * q);
+        (r << 16) | (g << 8) | b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::graphics::ntsc;
+
+    const WIDTH: usize = 32;
+    const HEIGHT: usize = 4;
+
+    fn filter(mode: ntsc::NtscMode, pixels: &[u32], frame_count: u64) -> Vec<u32> {
+        let mut filter = ntsc::NtscFilter::new();
+        filter.mode = mode;
+        let mut pixels = pixels.to_vec();
+        filter.apply(&mut pixels, WIDTH, HEIGHT, frame_count);
+        pixels
+    }
+
+    fn channels(value: u32) -> [i32; 3] {
+        [
+            ((value >> 16) & 0xFF) as i32,
+            ((value >> 8) & 0xFF) as i32,
+            (value & 0xFF) as i32,
+        ]
+    }
+
+    fn is_close(a: u32, b: u32, tolerance: i32) -> bool {
+        channels(a)
+            .iter()
+            .zip(channels(b).iter())
+            .all(|(a, b)| (a - b).abs() <= tolerance)
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let pixels: Vec<u32> = (0..(WIDTH * HEIGHT) as u32).map(|i| i * 0x010203).collect();
+        assert_eq!(filter(ntsc::NtscMode::Rgb, &pixels, 0), pixels);
+        assert_eq!(
+            ntsc::NtscMode::from_name("S-Video"),
+            Some(ntsc::NtscMode::SVideo)
+        );
+        assert!(ntsc::NtscMode::from_name("vga").is_none());
+    }
+
+    #[test]
+    fn test_flat_colour() {
+        // A flat area keeps (close to) its colour in every mode.
+        let pixels = vec![0x55AA55; WIDTH * HEIGHT];
+        for mode in [
+            ntsc::NtscMode::SVideo,
+            ntsc::NtscMode::Composite,
+            ntsc::NtscMode::Rf,
+        ] {
+            let output = filter(mode, &pixels, 0);
+            let middle = output[WIDTH + WIDTH / 2];
+            assert!(is_close(middle, 0x55AA55, 16), "{:?} {:06X}", mode, middle);
+        }
+    }
+
+    #[test]
+    fn test_dither_blends() {
+        // Alternating columns (dithering) blend towards the average.
+        let pixels: Vec<u32> = (0..WIDTH * HEIGHT)
+            .map(|i| if i % 2 == 0 { 0xFF0000 } else { 0x0000FF })
+            .collect();
+        let output = filter(ntsc::NtscMode::Composite, &pixels, 0);
+        let (left, right) = (output[WIDTH + 16], output[WIDTH + 17]);
+        let difference = |a: u32, b: u32| {
+            channels(a)
+                .iter()
+                .zip(channels(b).iter())
+                .map(|(a, b)| (a - b).abs())
+                .sum::<i32>()
+        };
+        assert!(difference(left, right) < difference(0xFF0000, 0x0000FF) / 2);
+    }
+
+    #[test]
+    fn test_dot_crawl() {
+        // A sharp colour edge leaves a pattern that changes with the frame.
+        let pixels: Vec<u32> = (0..WIDTH * HEIGHT)
+            .map(|i| {
+                if i % WIDTH < WIDTH / 2 {
+                    0xFF00FF
+                } else {
+                    0x00FF00
+                }
+            })
+            .collect();
+        let even = filter(ntsc::NtscMode::Composite, &pixels, 0);
+        let odd = filter(ntsc::NtscMode::Composite, &pixels, 1);
+        assert_ne!(even, odd);
+        // Lines alternate, so the next frame's pattern is the next line's.
+        assert_eq!(&even[WIDTH..2 * WIDTH], &odd[0..WIDTH]);
+        // S-Video doesn't mix chroma into luma, so there's no crawl.
+        assert_eq!(
+            filter(ntsc::NtscMode::SVideo, &pixels, 0),
+            filter(ntsc::NtscMode::SVideo, &pixels, 1)
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use super::super::checksum;
+use std::fs;
+use std::io;
+use std::io::Write;
+
+// Minimal PNG encoder for 8-bit RGB images (screenshots).
+// The image data is held in 'stored' (uncompressed) deflate blocks, so no
+// compression library is needed.  The files are larger than they could be,
+// but the output only depends on the pixels (useful for comparing images).
+pub struct Png {}
+
+impl Png {
+    const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    const BIT_DEPTH: u8 = 8;
+    const COLOUR_TYPE_RGB: u8 = 2;
+    const FILTER_NONE: u8 = 0;
+    const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+    // Encode 'rgb' (3 bytes per pixel, rows top to bottom) as a PNG file.
+    pub fn encode(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+        assert_eq!(rgb.len(), (width * height * 3) as usize);
+
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[Png::BIT_DEPTH, Png::COLOUR_TYPE_RGB, 0, 0, 0]);
+
+        // Each row is preceded by its filter type.
+        let mut image = Vec::with_capacity(rgb.len() + height as usize);
+        if width > 0 {
+            for row in rgb.chunks((width * 3) as usize) {
+                image.push(Png::FILTER_NONE);
+                image.extend_from_slice(row);
+            }
+        }
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&Png::SIGNATURE);
+        Png::write_chunk(&mut data, b"IHDR", &ihdr);
+        Png::write_chunk(&mut data, b"IDAT", &Png::zlib_stored(&image));
+        Png::write_chunk(&mut data, b"IEND", &[]);
+        data
+    }
+
+    pub fn save(filename: &str, width: u32, height: u32, rgb: &[u8]) -> io::Result<()> {
+        let mut file = fs::File::create(filename)?;
+        file.write_all(&Png::encode(width, height, rgb))
+    }
+
+    // Chunk: length, type, data, CRC-32 (of the type and data).
+    fn write_chunk(output: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+        output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        output.extend_from_slice(chunk_type);
+        output.extend_from_slice(data);
+        let crc = checksum::crc32_update(checksum::crc32(chunk_type), data);
+        output.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    // zlib stream of uncompressed deflate blocks.
+    fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        let mut output = vec![0x78, 0x01]; // Deflate, 32K window, no preset dictionary.
+        let mut blocks = data.chunks(Png::MAX_STORED_BLOCK).peekable();
+        if blocks.peek().is_none() {
+            output.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+        }
+        while let Some(block) = blocks.next() {
+            let last = blocks.peek().is_none();
+            output.push(last as u8); // BFINAL, BTYPE = 0 (stored).
+            output.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            output.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            output.extend_from_slice(block);
+        }
+        output.extend_from_slice(&checksum::adler32(data).to_be_bytes());
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::checksum;
+    use crate::sega::graphics::png;
+
+    #[test]
+    fn test_encode() {
+        let rgb = [
+            0xFF, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0xFF, 0x10, 0x20, 0x30,
+        ];
+        let data = png::Png::encode(2, 2, &rgb);
+        assert_eq!(&data[0..8], &png::Png::SIGNATURE);
+
+        // IHDR
+        assert_eq!(&data[8..16], &[0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(&data[16..29], &[0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+        assert_eq!(
+            u32::from_be_bytes(data[29..33].try_into().unwrap()),
+            checksum::crc32(&data[12..29])
+        );
+
+        // IDAT, a single stored block of the filtered rows.
+        let length = u32::from_be_bytes(data[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&data[37..41], b"IDAT");
+        let zlib = &data[41..41 + length];
+        let image = [&[0][..], &rgb[0..6], &[0][..], &rgb[6..12]].concat();
+        assert_eq!(&zlib[0..3], &[0x78, 0x01, 0x01]);
+        assert_eq!(&zlib[3..7], &[14, 0, !14, 0xFF]);
+        assert_eq!(&zlib[7..21], &image[..]);
+        assert_eq!(&zlib[21..], &checksum::adler32(&image).to_be_bytes());
+
+        // IEND
+        assert_eq!(
+            &data[45 + length..],
+            &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+    }
+
+    #[test]
+    fn test_large_image() {
+        // More than one stored block (each is up to 65535 bytes).
+        let (width, height) = (256, 192);
+        let rgb: Vec<u8> = (0..width * height * 3).map(|i| (i % 251) as u8).collect();
+        let data = png::Png::encode(width, height, &rgb);
+        let length = u32::from_be_bytes(data[33..37].try_into().unwrap()) as usize;
+        let image_length = (width * 3 + 1) * height;
+        let blocks = (image_length as usize).div_ceil(0xFFFF);
+        assert_eq!(blocks, 3);
+        assert_eq!(length, 2 + image_length as usize + blocks * 5 + 4);
+    }
+}
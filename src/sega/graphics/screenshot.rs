@@ -0,0 +1,117 @@
+use super::display;
+use super::png;
+use super::vdp;
+use std::io;
+
+// Copy of the console display (as exported to 'Core::raw_display'), saved as
+// a PNG at the native resolution, optionally with a scaled copy.
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<u8>, // 3 bytes per pixel.
+}
+
+impl Screenshot {
+    // 'raw_display' is in the display pixel format (see 'display::SDLUtility').
+    pub fn from_display(raw_display: &[u8]) -> Self {
+        let bytes_per_pixel = display::SDLUtility::bytes_per_pixel() as usize;
+        let mut rgb = Vec::with_capacity(raw_display.len() / bytes_per_pixel * 3);
+        for pixel in raw_display.chunks(bytes_per_pixel) {
+            // Stored as b, g, r (see 'Colour::convert_rgb888').
+            rgb.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        }
+        Self {
+            width: vdp::Constants::SMS_WIDTH as u32,
+            height: vdp::Constants::SMS_HEIGHT as u32,
+            rgb,
+        }
+    }
+
+    // Nearest neighbour scaled copy.
+    pub fn scaled(&self, scale: u32) -> Self {
+        let scale = scale.max(1);
+        let mut rgb = Vec::with_capacity(self.rgb.len() * (scale * scale) as usize);
+        for row in self.rgb.chunks((self.width * 3) as usize) {
+            let mut scaled_row = Vec::with_capacity(row.len() * scale as usize);
+            for pixel in row.chunks(3) {
+                for _ in 0..scale {
+                    scaled_row.extend_from_slice(pixel);
+                }
+            }
+            for _ in 0..scale {
+                rgb.extend_from_slice(&scaled_row);
+            }
+        }
+        Self {
+            width: self.width * scale,
+            height: self.height * scale,
+            rgb,
+        }
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        png::Png::save(filename, self.width, self.height, &self.rgb)
+    }
+
+    // eg, 'rustsega_<game>_<seconds since 1970>_<frame>.png'
+    pub fn filename(game_name: &str, frame_count: u64, suffix: &str) -> String {
+        let game_name = if game_name.is_empty() {
+            String::new()
+        } else {
+            format!("{}_", game_name)
+        };
+        format!(
+            "rustsega_{}{}_{}{}.png",
+            game_name,
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+            frame_count,
+            suffix
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::graphics::display;
+    use crate::sega::graphics::screenshot;
+    use crate::sega::graphics::vdp;
+
+    #[test]
+    fn test_screenshot() {
+        let width = vdp::Constants::SMS_WIDTH as usize;
+        let height = vdp::Constants::SMS_HEIGHT as usize;
+        let bytes_per_pixel = display::SDLUtility::bytes_per_pixel() as usize;
+        let mut raw_display = vec![0; width * height * bytes_per_pixel];
+        display::Colour::new(0x10, 0x20, 0x30).convert_rgb888(&mut raw_display[0..3]);
+        let last = (width * height - 1) * bytes_per_pixel;
+        display::Colour::new(0xFF, 0x80, 0x01).convert_rgb888(&mut raw_display[last..last + 3]);
+
+        let screenshot = screenshot::Screenshot::from_display(&raw_display);
+        assert_eq!((screenshot.width, screenshot.height), (256, 192));
+        assert_eq!(screenshot.rgb.len(), width * height * 3);
+        assert_eq!(&screenshot.rgb[0..3], &[0x10, 0x20, 0x30]);
+        assert_eq!(
+            &screenshot.rgb[screenshot.rgb.len() - 3..],
+            &[0xFF, 0x80, 0x01]
+        );
+
+        let scaled = screenshot.scaled(2);
+        assert_eq!((scaled.width, scaled.height), (512, 384));
+        let row = (scaled.width * 3) as usize;
+        for offset in [0, 3, row, row + 3] {
+            assert_eq!(&scaled.rgb[offset..offset + 3], &[0x10, 0x20, 0x30]);
+        }
+        assert_eq!(&scaled.rgb[6..9], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn test_filename() {
+        let filename = screenshot::Screenshot::filename("Alex Kidd", 120, "_x3");
+        assert!(filename.starts_with("rustsega_Alex Kidd_"));
+        assert!(filename.ends_with("_120_x3.png"));
+        assert!(screenshot::Screenshot::filename("", 0, "").starts_with("rustsega_1"));
+    }
+}
@@ -1,5 +1,7 @@
 use super::super::clocks;
 use super::super::ports;
+use super::super::snapshot;
+use super::super::snapshot::Snapshot;
 use super::display;
 
 #[derive(Clone, Copy, Default)]
@@ -175,7 +177,7 @@ impl Constants {
     const RAMSIZE: u16 = 0x4000;
     const CRAMSIZE: u8 = 0x20;
     // 3Mhz CPU, 50Hz refresh ~= 60000 ticks
-    const VSYNCCYCLETIME: u16 = 65232;
+    pub const VSYNCCYCLETIME: u16 = 65232;
     const BLANKTIME: u16 = ((Constants::VSYNCCYCLETIME as u32 * 72) / 262) as u16;
     const VFRAMETIME: u16 =
         ((Constants::VSYNCCYCLETIME as u32 * Constants::SMS_HEIGHT as u32) / 262) as u16;
@@ -416,6 +418,7 @@ pub struct VDPInterrupts {
     h_sync_interrupt_enabled: bool,
 
     frame_updated: bool,
+    frame_count: u64,
 }
 
 impl Vdp {
@@ -785,42 +788,32 @@ impl Vdp {
             // Write to video ram
             self.set_palette(self.current_address, data);
         } else {
-            if ((self.current_address & Constants::TILEATTRIBUTESADDRESSMASK)
-                == self.tile_attributes_address)
-                && ((self.current_address & Constants::TILEATTRIBUTEMASK)
-                    < Constants::NUMTILEATTRIBUTES)
-            {
-                self.update_tile_attributes(
-                    self.current_address,
-                    self.ram[self.current_address as usize],
-                    data,
-                );
-            } else if ((self.current_address & Constants::SPRITEATTRIBUTESADDRESSMASK)
-                == self.sprite_attributes_address)
-                && ((self.current_address & Constants::SPRITEATTRIBUTESMASK)
-                    < Constants::NUMSPRITEATTRIBUTES)
-            {
-                self.update_sprite_attributes(
-                    self.current_address,
-                    self.ram[self.current_address as usize],
-                    data,
-                );
-            }
-            if self.current_address < Constants::PATTERNADDRESSLIMIT {
-                self.update_pattern(
-                    self.current_address,
-                    self.ram[self.current_address as usize],
-                    data,
-                );
-            }
-
-            self.ram[self.current_address as usize] = data; // Update after function call
+            self.write_ram(self.current_address, data);
             self.read_be_latch = data;
         }
 
         self.current_address = (self.current_address + 1) & 0x3FFF; // Should be ok without this
     }
 
+    // Write to video ram, updating the tile/sprite attributes and patterns.
+    fn write_ram(&mut self, address: u16, data: u8) {
+        if ((address & Constants::TILEATTRIBUTESADDRESSMASK) == self.tile_attributes_address)
+            && ((address & Constants::TILEATTRIBUTEMASK) < Constants::NUMTILEATTRIBUTES)
+        {
+            self.update_tile_attributes(address, self.ram[address as usize], data);
+        } else if ((address & Constants::SPRITEATTRIBUTESADDRESSMASK)
+            == self.sprite_attributes_address)
+            && ((address & Constants::SPRITEATTRIBUTESMASK) < Constants::NUMSPRITEATTRIBUTES)
+        {
+            self.update_sprite_attributes(address, self.ram[address as usize], data);
+        }
+        if address < Constants::PATTERNADDRESSLIMIT {
+            self.update_pattern(address, self.ram[address as usize], data);
+        }
+
+        self.ram[address as usize] = data; // Update after function call
+    }
+
     pub fn write_register(&mut self, register_number: u8, data: u8) {
         self.vdp_register[register_number as usize] = data; // Update register data
 
@@ -938,9 +931,15 @@ impl Vdp {
         }
     }
 
+    // Backdrop (and border) colour, from the sprite palette.
+    fn backdrop_colour(&self) -> display::Colour {
+        self.screen_palette[(0x10 | self.border_colour) as usize]
+    }
+
     fn single_scan(&mut self, y: u16) {
         let mut fine_scroll = 0;
         let mut x_offset = 0;
+        let backdrop = self.backdrop_colour();
 
         let sprite_scan_y = &self.display_buffers.sprite_scan_lines[y as usize];
         let vertical_offset = self.vertical_scroll_info[y as usize];
@@ -1028,10 +1027,11 @@ impl Vdp {
                     }
                 }
             }
+        }
 
-            for i in 0..self.mode_1_control.start_x {
-                scan_y_lines[i as usize] = display::Colour::new(0, 0, 0);
-            }
+        // The masked (left) column shows the backdrop colour, over any sprites.
+        for i in 0..self.mode_1_control.start_x {
+            scan_y_lines[i as usize] = backdrop;
         }
     }
 
@@ -1359,6 +1359,94 @@ impl ports::Device for Vdp {
         self.interrupt_handler.poll_interrupts()
     }
 
+    fn frame_count(&self) -> u64 {
+        self.interrupt_handler.frame_count
+    }
+
+    fn border_colour(&self) -> Option<display::Colour> {
+        Some(self.backdrop_colour())
+    }
+
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bytes(&self.c_ram);
+        writer.write_bytes(&self.vdp_register);
+        writer.write_u16(self.current_address);
+        writer.write_u8(self.code_register);
+        writer.write_u8(self.read_be_latch);
+        writer.write_bool(self.address_latch);
+        writer.write_u8(self.write_bf_low_address);
+        for y in 0..Constants::SMS_HEIGHT as usize {
+            let horizontal_scroll = &self.horizontal_scroll_info[y];
+            writer.write_u8(horizontal_scroll.column_offset);
+            writer.write_u8(horizontal_scroll.fine_scroll);
+            writer.write_u16(horizontal_scroll.x_offset);
+            writer.write_u8(self.vertical_scroll_info[y]);
+        }
+        self.interrupt_handler.save_state(writer);
+    }
+
+    // The pattern, attribute and palette caches are rebuilt by replaying the
+    // registers and video/colour ram into a new vdp, then the display is
+    // redrawn so the restored frame can be shown.
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        let ram = reader.read_bytes(self.ram.len())?;
+        let c_ram = reader.read_bytes(self.c_ram.len())?;
+        let vdp_register = reader.read_bytes(self.vdp_register.len())?;
+        let current_address = reader.read_u16()?;
+        let code_register = reader.read_u8()?;
+        let read_be_latch = reader.read_u8()?;
+        let address_latch = reader.read_bool()?;
+        let write_bf_low_address = reader.read_u8()?;
+        let mut horizontal_scroll_info = Vec::with_capacity(Constants::SMS_HEIGHT as usize);
+        let mut vertical_scroll_info = Vec::with_capacity(Constants::SMS_HEIGHT as usize);
+        for _ in 0..Constants::SMS_HEIGHT {
+            horizontal_scroll_info.push(HorizontalScroll {
+                column_offset: reader.read_u8()?,
+                fine_scroll: reader.read_u8()?,
+                x_offset: reader.read_u16()?,
+            });
+            vertical_scroll_info.push(reader.read_u8()?);
+        }
+        let mut interrupt_handler = VDPInterrupts::new();
+        interrupt_handler.load_state(reader)?;
+
+        *self = Vdp::new();
+        // Registers that set up the display mode and table addresses.
+        for register_number in [
+            Constants::MODE_CONTROL_NO_1,
+            Constants::MODE_CONTROL_NO_2,
+            2,
+            5,
+            6,
+        ] {
+            self.write_register(register_number, vdp_register[register_number as usize]);
+        }
+        self.vdp_register.copy_from_slice(vdp_register);
+        self.border_colour = self.vdp_register[7] & 0xf;
+        self.horizontal_scroll = self.vdp_register[8];
+        self.vertical_scroll = self.vdp_register[9];
+
+        for (address, data) in ram.iter().enumerate() {
+            self.write_ram(address as u16, *data);
+        }
+        for (address, data) in c_ram.iter().enumerate() {
+            self.set_palette(address as u16, *data);
+        }
+
+        self.current_address = current_address;
+        self.code_register = code_register;
+        self.read_be_latch = read_be_latch;
+        self.address_latch = address_latch;
+        self.write_bf_low_address = write_bf_low_address;
+        self.horizontal_scroll_info = horizontal_scroll_info;
+        self.vertical_scroll_info = vertical_scroll_info;
+        self.interrupt_handler = interrupt_handler;
+
+        self.draw_buffer();
+        Ok(())
+    }
+
     fn export(&mut self, raw_display: &mut Vec<u8>) -> bool {
         if self.screen_buffer_pending {
             self.driver_update_display(raw_display);
@@ -1390,10 +1478,48 @@ impl VDPInterrupts {
             h_sync_interrupt_enabled: true,
 
             frame_updated: false,
+            frame_count: 0,
         }
     }
 }
 
+impl snapshot::Snapshot for VDPInterrupts {
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        writer.write_u8(self.vdp_status_register);
+        writer.write_u16(self.v_sync);
+        writer.write_u16(self.y_end);
+        writer.write_u16(self.current_y_pos);
+        writer.write_u64(self.last_v_sync_clock.cycles);
+        writer.write_u32(self.line_int_time);
+        writer.write_u16(self.line_interrupt);
+        writer.write_u16(self.line_interrupt_latch);
+        writer.write_bool(self.h_int_pending);
+        writer.write_bool(self.v_int_pending);
+        writer.write_bool(self.v_sync_interrupt_enabled);
+        writer.write_bool(self.h_sync_interrupt_enabled);
+        writer.write_bool(self.frame_updated);
+        writer.write_u64(self.frame_count);
+    }
+
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        self.vdp_status_register = reader.read_u8()?;
+        self.v_sync = reader.read_u16()?;
+        self.y_end = reader.read_u16()?;
+        self.current_y_pos = reader.read_u16()?;
+        self.last_v_sync_clock.cycles = reader.read_u64()?;
+        self.line_int_time = reader.read_u32()?;
+        self.line_interrupt = reader.read_u16()?;
+        self.line_interrupt_latch = reader.read_u16()?;
+        self.h_int_pending = reader.read_bool()?;
+        self.v_int_pending = reader.read_bool()?;
+        self.v_sync_interrupt_enabled = reader.read_bool()?;
+        self.h_sync_interrupt_enabled = reader.read_bool()?;
+        self.frame_updated = reader.read_bool()?;
+        self.frame_count = reader.read_u64()?;
+        Ok(())
+    }
+}
+
 impl VDPInterrupts {
     fn update_in_frame_timing(&mut self, clock: &clocks::Clock) {
         self.v_sync = (clock.cycles - self.last_v_sync_clock.cycles) as u16;
@@ -1425,6 +1551,7 @@ impl VDPInterrupts {
     fn update_vsync_timing(&mut self, clock: &clocks::Clock) {
         if self.v_sync >= Constants::VSYNCCYCLETIME {
             self.frame_updated = false;
+            self.frame_count += 1;
             self.last_v_sync_clock.cycles = clock.cycles;
             self.v_sync = 0;
             self.current_y_pos = 0;
@@ -1442,7 +1569,9 @@ impl VDPInterrupts {
 
 #[cfg(test)]
 mod tests {
+    use crate::sega::graphics::display;
     use crate::sega::graphics::vdp;
+    use crate::sega::ports;
     use sdl2::event;
     use sdl2::keyboard; // Keycode
     use sdl2::pixels;
@@ -1522,6 +1651,28 @@ mod tests {
         assert_eq!(vdp::Constants::BLANKTIME, 17926);
         assert_eq!(vdp::Constants::VFRAMETIME, 47803);
     }
+
+    #[test]
+    fn test_masked_column_backdrop() {
+        let mut vdp = vdp::Vdp::new();
+        vdp.set_palette(0x13, 0x3F); // White
+        vdp.write_register(7, 0x3);
+        vdp.write_register(0, vdp::Constants::VDP0COL0OVERSCAN | vdp::Constants::VDP0M4);
+        vdp.write_register(1, vdp::Constants::VDP1ENABLEDISPLAY);
+        vdp.draw_buffer();
+
+        let rgb = |colour: &display::Colour| {
+            let mut rgb = [0; 3];
+            colour.convert_rgb24(&mut rgb);
+            rgb
+        };
+        let scan_line = &vdp.display_buffers.scan_lines[10].scan_line;
+        assert!(scan_line[0..8]
+            .iter()
+            .all(|colour| rgb(colour) == [0xFF; 3]));
+        assert_eq!(rgb(&scan_line[8]), [0; 3]);
+        assert_eq!(rgb(&ports::Device::border_colour(&vdp).unwrap()), [0xFF; 3]);
+    }
 }
 
 // set_colour
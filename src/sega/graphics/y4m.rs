@@ -0,0 +1,119 @@
+use super::display;
+use std::fs;
+use std::io;
+use std::io::Write;
+
+pub type Y4mFileWriter = Y4mWriter<io::BufWriter<fs::File>>;
+
+// Writes frames to a YUV4MPEG2 stream (raw video, readable by ffmpeg/mpv).
+// Frames are converted to full resolution YCbCr (4:4:4, BT.601), so there's
+// no chroma subsampling of the console's single pixel detail.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: u32,
+    height: u32,
+    frames: u64,
+}
+
+impl Y4mFileWriter {
+    pub fn create(filename: &str, width: u32, height: u32, rate: (u32, u32)) -> io::Result<Self> {
+        let file = fs::File::create(filename)?;
+        Y4mWriter::new(io::BufWriter::new(file), width, height, rate)
+    }
+}
+
+impl<W: Write> Y4mWriter<W> {
+    // 'rate' is the frame rate as a fraction (numerator, denominator).
+    pub fn new(mut writer: W, width: u32, height: u32, rate: (u32, u32)) -> io::Result<Self> {
+        let divisor = Self::gcd(rate.0, rate.1).max(1);
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+            width,
+            height,
+            rate.0 / divisor,
+            rate.1 / divisor
+        )?;
+        Ok(Self {
+            writer,
+            width,
+            height,
+            frames: 0,
+        })
+    }
+
+    // 'raw_display' is in the display pixel format (see 'display::SDLUtility').
+    pub fn write_frame(&mut self, raw_display: &[u8]) -> io::Result<()> {
+        let pixels = (self.width * self.height) as usize;
+        let bytes_per_pixel = display::SDLUtility::bytes_per_pixel() as usize;
+        let mut planes = vec![0; pixels * 3];
+        for (index, pixel) in raw_display.chunks(bytes_per_pixel).take(pixels).enumerate() {
+            // Stored as b, g, r (see 'Colour::convert_rgb888').
+            let (y, u, v) = Self::rgb_to_yuv(pixel[2], pixel[1], pixel[0]);
+            planes[index] = y;
+            planes[pixels + index] = u;
+            planes[2 * pixels + index] = v;
+        }
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&planes)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    // BT.601 'studio swing' (16-235 luma), as expected by most players.
+    fn rgb_to_yuv(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+        let (r, g, b) = (r as i32, g as i32, b as i32);
+        let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+        let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+        let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+        (y as u8, u as u8, v as u8)
+    }
+
+    fn gcd(a: u32, b: u32) -> u32 {
+        if b == 0 {
+            a
+        } else {
+            Self::gcd(b, a % b)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::graphics::display;
+    use crate::sega::graphics::y4m;
+    use std::io;
+
+    #[test]
+    fn test_y4m_frames() {
+        let mut writer =
+            y4m::Y4mWriter::new(io::Cursor::new(Vec::new()), 2, 1, (3590000, 65232)).unwrap();
+        let bytes_per_pixel = display::SDLUtility::bytes_per_pixel() as usize;
+        let mut raw_display = vec![0; 2 * bytes_per_pixel];
+        display::Colour::new(0xFF, 0xFF, 0xFF)
+            .convert_rgb888(&mut raw_display[bytes_per_pixel..bytes_per_pixel + 3]);
+        writer.write_frame(&raw_display).unwrap();
+        writer.write_frame(&raw_display).unwrap();
+        assert_eq!(writer.frames(), 2);
+
+        let data = writer.into_inner().into_inner();
+        let header = b"YUV4MPEG2 W2 H1 F224375:4077 Ip A1:1 C444\n";
+        assert_eq!(&data[..header.len()], header);
+
+        // Black then white, with neutral chroma.
+        let frame = [b"FRAME\n".as_slice(), &[16, 235, 128, 128, 128, 128]].concat();
+        assert_eq!(&data[header.len()..], [frame.clone(), frame].concat());
+    }
+}
@@ -1,6 +1,170 @@
 use super::clocks;
+use super::config;
 use sdl2::event; // Keycode
 use sdl2::keyboard; // Keycode
+use sdl2::mouse;
+use std::collections::HashMap;
+use std::fmt;
+
+// How to handle opposing directions (up+down, left+right) held at the same time,
+// which isn't possible on a real joypad and confuses some games.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OpposingDirections {
+    Allow,       // Pass both directions through.
+    LastPressed, // Only the most recently pressed direction.
+    Neutral,     // Neither direction.
+}
+
+impl OpposingDirections {
+    pub fn from_name(name: &str) -> Option<OpposingDirections> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "allow" => Some(OpposingDirections::Allow),
+            "last" => Some(OpposingDirections::LastPressed),
+            "neutral" => Some(OpposingDirections::Neutral),
+            _ => None,
+        }
+    }
+}
+
+// Peripheral connected to a controller port.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PeripheralType {
+    Joypad,
+    Paddle,    // HPD-200 Paddle Control
+    SportsPad, // Sports Pad trackball
+}
+
+impl PeripheralType {
+    pub fn from_name(name: &str) -> Option<PeripheralType> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "joypad" => Some(PeripheralType::Joypad),
+            "paddle" => Some(PeripheralType::Paddle),
+            "sportspad" => Some(PeripheralType::SportsPad),
+            _ => None,
+        }
+    }
+}
+
+// Analog peripheral state.  Both return position data 4 bits at a time, the
+// nibble is selected by TH when it is an output (export consoles), otherwise
+// by the peripheral's own clock (Japanese consoles), modelled as toggling on
+// each read.  The selected half is reported on TR.
+#[derive(Clone, Copy)]
+pub struct Peripheral {
+    peripheral_type: PeripheralType,
+    paddle_position: u8,
+    sports_pad_motion: (i32, i32), // Accumulated movement since the last latch.
+    sports_pad_latched: (u8, u8),
+    phase: u8, // Nibble being returned (paddle: 0/1, sports pad: 0-3)
+    last_th: bool,
+}
+
+impl Peripheral {
+    const PADDLE_SENSITIVITY: i32 = 1;
+    const SPORTS_PAD_MAX: i32 = 127;
+
+    fn new(peripheral_type: PeripheralType) -> Self {
+        Self {
+            peripheral_type,
+            paddle_position: 0x80,
+            sports_pad_motion: (0, 0),
+            sports_pad_latched: (0, 0),
+            // The sports pad starts at the end of a sequence, ready to latch.
+            phase: if peripheral_type == PeripheralType::SportsPad {
+                3
+            } else {
+                0
+            },
+            last_th: true,
+        }
+    }
+
+    fn mouse_motion(&mut self, x: i32, y: i32) {
+        match self.peripheral_type {
+            PeripheralType::Paddle => {
+                self.paddle_position = (self.paddle_position as i32
+                    + x * Peripheral::PADDLE_SENSITIVITY)
+                    .clamp(0, 0xFF) as u8;
+            }
+            PeripheralType::SportsPad => {
+                self.sports_pad_motion.0 += x;
+                self.sports_pad_motion.1 += y;
+            }
+            PeripheralType::Joypad => {}
+        }
+    }
+
+    // The sports pad returns the (inverted) movement since the last latch.
+    fn latch_sports_pad(&mut self) {
+        let clamp = |motion: i32| {
+            (-motion.clamp(-Peripheral::SPORTS_PAD_MAX, Peripheral::SPORTS_PAD_MAX)) as i8 as u8
+        };
+        self.sports_pad_latched = (
+            clamp(self.sports_pad_motion.0),
+            clamp(self.sports_pad_motion.1),
+        );
+        self.sports_pad_motion = (0, 0);
+    }
+
+    // TH output level changed (TH configured as an output via port 0x3F).
+    fn th_write(&mut self, th: bool) {
+        if th != self.last_th {
+            match self.peripheral_type {
+                PeripheralType::Paddle => self.phase = th as u8,
+                PeripheralType::SportsPad => {
+                    // TH low starts a new sequence (X high, X low, Y high, Y low).
+                    if !th && self.phase >= 3 {
+                        self.phase = 0;
+                        self.latch_sports_pad();
+                    } else {
+                        self.phase = (self.phase + 1).min(3);
+                    }
+                }
+                PeripheralType::Joypad => {}
+            }
+        }
+        self.last_th = th;
+    }
+
+    // Pins (up, down, left, right, TL, TR) for the current nibble, with
+    // 'buttons' holding the active low joypad button bits (TL = bit 4, TR = bit 5).
+    fn read(&mut self, th_is_output: bool, buttons: u8) -> u8 {
+        match self.peripheral_type {
+            PeripheralType::Joypad => buttons,
+            PeripheralType::Paddle => {
+                if !th_is_output {
+                    self.phase ^= 1;
+                }
+                let nibble = if self.phase == 1 {
+                    self.paddle_position >> 4
+                } else {
+                    self.paddle_position & 0xF
+                };
+                // TL is the paddle button, TR reports the nibble.
+                nibble | (buttons & 0x10) | (self.phase << 5)
+            }
+            PeripheralType::SportsPad => {
+                if !th_is_output {
+                    if self.phase >= 3 {
+                        self.phase = 0;
+                        self.latch_sports_pad();
+                    } else {
+                        self.phase += 1;
+                    }
+                }
+                let (x, y) = self.sports_pad_latched;
+                let nibble = match self.phase {
+                    0 => x >> 4,
+                    1 => x & 0xF,
+                    2 => y >> 4,
+                    _ => y & 0xF,
+                };
+                // TL and TR are the two buttons.
+                nibble | (buttons & 0x30)
+            }
+        }
+    }
+}
 
 #[derive(Clone, Copy)]
 pub struct Joystick {
@@ -12,6 +176,25 @@ pub struct Joystick {
     lg2x: u8,
     lg2y: u8,
     x: u8,
+    pause_requested: bool,
+
+    // Directions (up, down, left, right) held for each player, and the most
+    // recently pressed direction of each axis (vertical, horizontal).
+    held_directions: [[bool; 4]; 2],
+    last_pressed: [[usize; 2]; 2],
+    opposing_directions: OpposingDirections,
+
+    // Fire buttons (A, B) held for each player, and their autofire (turbo)
+    // variants.  Turbo buttons toggle every 'turbo_rate' frames.
+    held_buttons: [[bool; 2]; 2],
+    held_turbo: [[bool; 2]; 2],
+    turbo_rate: u64,
+    turbo_active: bool,
+
+    // Controller port A/B peripherals and the I/O control register (port 0x3F).
+    peripherals: [Peripheral; 2],
+    io_control: u8,
+    port_b_pins: u8, // Port B pins from the last 0xDC read (for the 0xDD read).
 }
 
 impl Joystick {
@@ -32,6 +215,22 @@ impl Joystick {
     const PORT2_LG1_BIT: u8 = (1 << 6);
     const PORT2_LG2_BIT: u8 = (1 << 7);
 
+    // I/O control (port 0x3F) bits, direction bits are 1 for input.
+    const IO_PORTA_TH_DIRECTION_BIT: u8 = 1 << 1;
+    const IO_PORTB_TH_DIRECTION_BIT: u8 = 1 << 3;
+    const IO_PORTA_TH_LEVEL_BIT: u8 = 1 << 5;
+    const IO_PORTB_TH_LEVEL_BIT: u8 = 1 << 7;
+
+    const UP: usize = 0;
+    const DOWN: usize = 1;
+    const LEFT: usize = 2;
+    const RIGHT: usize = 3;
+
+    const FIRE_A: usize = 0;
+    const FIRE_B: usize = 1;
+
+    pub const DEFAULT_TURBO_RATE: u64 = 2;
+
     pub fn new() -> Self {
         Self {
             port1_value: 0xFF,
@@ -42,6 +241,17 @@ impl Joystick {
             lg2x: 0,
             lg2y: 0,
             x: 0,
+            pause_requested: false,
+            held_directions: [[false; 4]; 2],
+            last_pressed: [[Joystick::UP, Joystick::LEFT]; 2],
+            opposing_directions: OpposingDirections::LastPressed,
+            held_buttons: [[false; 2]; 2],
+            held_turbo: [[false; 2]; 2],
+            turbo_rate: Joystick::DEFAULT_TURBO_RATE,
+            turbo_active: true,
+            peripherals: [Peripheral::new(PeripheralType::Joypad); 2],
+            io_control: 0xFF,
+            port_b_pins: 0x3F,
         }
     }
 
@@ -76,46 +286,175 @@ impl Joystick {
         self.port2_value
     }
 
+    pub fn set_opposing_directions(&mut self, opposing_directions: OpposingDirections) {
+        self.opposing_directions = opposing_directions;
+        self.update_directions(0);
+        self.update_directions(1);
+    }
+
+    // 'value' is the (active low) bit value, so 'false' is pressed.
+    fn set_direction(&mut self, player: usize, direction: usize, value: bool) {
+        let pressed = !value;
+        if pressed && !self.held_directions[player][direction] {
+            self.last_pressed[player][direction / 2] = direction;
+        }
+        self.held_directions[player][direction] = pressed;
+        self.update_directions(player);
+    }
+
+    // Set the port bits from the held directions, applying the opposing direction policy.
+    fn update_directions(&mut self, player: usize) {
+        for direction in [
+            Joystick::UP,
+            Joystick::DOWN,
+            Joystick::LEFT,
+            Joystick::RIGHT,
+        ] {
+            let opposite_held = self.held_directions[player][direction ^ 1];
+            let active = self.held_directions[player][direction]
+                && match self.opposing_directions {
+                    OpposingDirections::Allow => true,
+                    OpposingDirections::LastPressed => {
+                        !opposite_held || self.last_pressed[player][direction / 2] == direction
+                    }
+                    OpposingDirections::Neutral => !opposite_held,
+                };
+
+            match (player, direction) {
+                (0, Joystick::UP) => self.write_port1(Joystick::PORT1_J1UP_BIT, active),
+                (0, Joystick::DOWN) => self.write_port1(Joystick::PORT1_J1DOWN_BIT, active),
+                (0, Joystick::LEFT) => self.write_port1(Joystick::PORT1_J1LEFT_BIT, active),
+                (0, Joystick::RIGHT) => self.write_port1(Joystick::PORT1_J1RIGHT_BIT, active),
+                (_, Joystick::UP) => self.write_port1(Joystick::PORT1_J2UP_BIT, active),
+                (_, Joystick::DOWN) => self.write_port1(Joystick::PORT1_J2DOWN_BIT, active),
+                (_, Joystick::LEFT) => self.write_port2(Joystick::PORT2_J2LEFT_BIT, active),
+                (_, _) => self.write_port2(Joystick::PORT2_J2RIGHT_BIT, active),
+            }
+        }
+    }
+
+    // Number of frames the turbo buttons are pressed, then released, for.
+    pub fn set_turbo_rate(&mut self, frames: u64) {
+        self.turbo_rate = frames.max(1);
+    }
+
+    pub fn get_turbo_rate(&self) -> u64 {
+        self.turbo_rate
+    }
+
+    // Called at the start of each frame, the turbo buttons follow the frame
+    // count (not the wall clock) so recordings replay the same way.
+    pub fn update_turbo(&mut self, frame_count: u64) {
+        let turbo_active = (frame_count / self.turbo_rate).is_multiple_of(2);
+        if turbo_active != self.turbo_active {
+            self.turbo_active = turbo_active;
+            for player in 0..2 {
+                self.update_buttons(player);
+            }
+        }
+    }
+
+    fn set_button(&mut self, player: usize, button: usize, pressed: bool) {
+        self.held_buttons[player][button] = pressed;
+        self.update_buttons(player);
+    }
+
+    fn set_turbo(&mut self, player: usize, button: usize, pressed: bool) {
+        self.held_turbo[player][button] = pressed;
+        self.update_buttons(player);
+    }
+
+    fn update_buttons(&mut self, player: usize) {
+        for button in [Joystick::FIRE_A, Joystick::FIRE_B] {
+            let active = self.held_buttons[player][button]
+                || (self.held_turbo[player][button] && self.turbo_active);
+            match (player, button) {
+                (0, Joystick::FIRE_A) => self.write_port1(Joystick::PORT1_J1FIREA_BIT, active),
+                (0, _) => self.write_port1(Joystick::PORT1_J1FIREB_BIT, active),
+                (_, Joystick::FIRE_A) => self.write_port2(Joystick::PORT2_J2FIREA_BIT, active),
+                (_, _) => self.write_port2(Joystick::PORT2_J2FIREB_BIT, active),
+            }
+        }
+    }
+
+    fn write_port1(&mut self, mask: u8, pressed: bool) {
+        self.port1_value = Joystick::set_bit(self.port1_value, mask, !pressed);
+    }
+
+    fn write_port2(&mut self, mask: u8, pressed: bool) {
+        self.port2_value = Joystick::set_bit(self.port2_value, mask, !pressed);
+    }
+
     pub fn j1_up(&mut self, value: bool) {
-        self.port1_value = Joystick::set_bit(self.port1_value, Joystick::PORT1_J1UP_BIT, value);
+        self.set_direction(0, Joystick::UP, value);
     }
     pub fn j1_down(&mut self, value: bool) {
-        self.port1_value = Joystick::set_bit(self.port1_value, Joystick::PORT1_J1DOWN_BIT, value);
+        self.set_direction(0, Joystick::DOWN, value);
     }
     pub fn j1_left(&mut self, value: bool) {
-        self.port1_value = Joystick::set_bit(self.port1_value, Joystick::PORT1_J1LEFT_BIT, value);
+        self.set_direction(0, Joystick::LEFT, value);
     }
     pub fn j1_right(&mut self, value: bool) {
-        self.port1_value = Joystick::set_bit(self.port1_value, Joystick::PORT1_J1RIGHT_BIT, value);
+        self.set_direction(0, Joystick::RIGHT, value);
     }
     pub fn j1_fire_a(&mut self, value: bool) {
-        self.port1_value = Joystick::set_bit(self.port1_value, Joystick::PORT1_J1FIREA_BIT, value);
+        self.set_button(0, Joystick::FIRE_A, !value);
     }
     pub fn j1_fire_b(&mut self, value: bool) {
-        self.port1_value = Joystick::set_bit(self.port1_value, Joystick::PORT1_J1FIREB_BIT, value);
+        self.set_button(0, Joystick::FIRE_B, !value);
+    }
+    pub fn j1_turbo_a(&mut self, value: bool) {
+        self.set_turbo(0, Joystick::FIRE_A, !value);
+    }
+    pub fn j1_turbo_b(&mut self, value: bool) {
+        self.set_turbo(0, Joystick::FIRE_B, !value);
     }
     pub fn j2_up(&mut self, value: bool) {
-        self.port1_value = Joystick::set_bit(self.port1_value, Joystick::PORT1_J2UP_BIT, value);
+        self.set_direction(1, Joystick::UP, value);
     }
     pub fn j2_down(&mut self, value: bool) {
-        self.port1_value = Joystick::set_bit(self.port1_value, Joystick::PORT1_J2DOWN_BIT, value);
+        self.set_direction(1, Joystick::DOWN, value);
     }
     pub fn j2_left(&mut self, value: bool) {
-        self.port2_value = Joystick::set_bit(self.port2_value, Joystick::PORT2_J2LEFT_BIT, value);
+        self.set_direction(1, Joystick::LEFT, value);
     }
     pub fn j2_right(&mut self, value: bool) {
-        self.port2_value = Joystick::set_bit(self.port2_value, Joystick::PORT2_J2RIGHT_BIT, value);
+        self.set_direction(1, Joystick::RIGHT, value);
     }
     pub fn j2_fire_a(&mut self, value: bool) {
-        self.port2_value = Joystick::set_bit(self.port2_value, Joystick::PORT2_J2FIREA_BIT, value);
+        self.set_button(1, Joystick::FIRE_A, !value);
     }
     pub fn j2_fire_b(&mut self, value: bool) {
-        self.port2_value = Joystick::set_bit(self.port2_value, Joystick::PORT2_J2FIREB_BIT, value);
+        self.set_button(1, Joystick::FIRE_B, !value);
+    }
+    pub fn j2_turbo_a(&mut self, value: bool) {
+        self.set_turbo(1, Joystick::FIRE_A, !value);
+    }
+    pub fn j2_turbo_b(&mut self, value: bool) {
+        self.set_turbo(1, Joystick::FIRE_B, !value);
     }
     pub fn reset(&mut self, value: bool) {
         self.port2_value = Joystick::set_bit(self.port2_value, Joystick::PORT2_RESET_BIT, value);
     }
 
+    // The pause button isn't read from a port, pressing it raises an NMI.
+    pub fn pause(&mut self, value: bool) {
+        if !value {
+            self.pause_requested = true;
+        }
+    }
+
+    // Overwrite the console visible input state (used for movie playback).
+    pub fn set_input_state(&mut self, port1_value: u8, port2_value: u8, pause_requested: bool) {
+        self.port1_value = port1_value;
+        self.port2_value = port2_value;
+        self.pause_requested = pause_requested;
+    }
+
+    pub fn take_pause_request(&mut self) -> bool {
+        std::mem::replace(&mut self.pause_requested, false)
+    }
+
     pub fn lg1(&mut self, value: bool) {
         if !value {
             self.x = self.lg1x;
@@ -141,10 +480,125 @@ impl Joystick {
         self.lg2y = y
     }
 
+    // Select the peripheral for controller port 0 (A) or 1 (B).
+    pub fn set_peripheral(&mut self, port: usize, peripheral_type: PeripheralType) {
+        self.peripherals[port] = Peripheral::new(peripheral_type);
+    }
+
+    pub fn get_peripheral(&self, port: usize) -> PeripheralType {
+        self.peripherals[port].peripheral_type
+    }
+
+    pub fn uses_mouse(&self) -> bool {
+        self.peripherals
+            .iter()
+            .any(|peripheral| peripheral.peripheral_type != PeripheralType::Joypad)
+    }
+
+    // Mouse movement drives all of the analog peripherals.
+    pub fn mouse_motion(&mut self, x: i32, y: i32) {
+        for peripheral in self.peripherals.iter_mut() {
+            peripheral.mouse_motion(x, y);
+        }
+    }
+
+    // Mouse buttons are the peripheral buttons (left: TL/fire A, right: TR/fire B).
+    pub fn mouse_button(&mut self, left: bool, pressed: bool) {
+        for port in 0..self.peripherals.len() {
+            if self.peripherals[port].peripheral_type == PeripheralType::Joypad {
+                continue;
+            }
+            match (port, left) {
+                (0, true) => self.j1_fire_a(!pressed),
+                (0, false) => self.j1_fire_b(!pressed),
+                (_, true) => self.j2_fire_a(!pressed),
+                (_, false) => self.j2_fire_b(!pressed),
+            }
+        }
+    }
+
+    // Port 0x3F, sets the TR/TH pin directions and output levels.
+    pub fn write_io_control(&mut self, value: u8) {
+        self.io_control = value;
+        for (port, direction_bit, level_bit) in [
+            (
+                0,
+                Joystick::IO_PORTA_TH_DIRECTION_BIT,
+                Joystick::IO_PORTA_TH_LEVEL_BIT,
+            ),
+            (
+                1,
+                Joystick::IO_PORTB_TH_DIRECTION_BIT,
+                Joystick::IO_PORTB_TH_LEVEL_BIT,
+            ),
+        ] {
+            if value & direction_bit == 0 {
+                self.peripherals[port].th_write(value & level_bit != 0);
+            }
+        }
+    }
+
+    fn th_is_output(&self, port: usize) -> bool {
+        let direction_bit = if port == 0 {
+            Joystick::IO_PORTA_TH_DIRECTION_BIT
+        } else {
+            Joystick::IO_PORTB_TH_DIRECTION_BIT
+        };
+        self.io_control & direction_bit == 0
+    }
+
+    // Port A pins are 0xDC bits 0-5, port B are 0xDC bits 6-7 and 0xDD bits 0-3.
+    fn read_port_pins(&mut self, port: usize) -> u8 {
+        let buttons = if port == 0 {
+            self.port1_value & 0x3F
+        } else {
+            (self.port1_value >> 6) | ((self.port2_value & 0xF) << 2)
+        };
+        let th_is_output = self.th_is_output(port);
+        self.peripherals[port].read(th_is_output, buttons)
+    }
+
     pub fn port_read(&mut self, _clock: &clocks::Clock, port_address: u8) -> Option<u8> {
         match port_address {
-            0xDC => Some(self.read_port1()),
-            0xDD => Some(self.read_port2()),
+            0xDC => {
+                let port_a = self.read_port_pins(0);
+                let port_b = if self.peripherals[1].peripheral_type == PeripheralType::Joypad {
+                    self.port1_value >> 6
+                } else {
+                    self.port_b_pins = self.read_port_pins(1);
+                    self.port_b_pins & 0x3
+                };
+                Some(port_a | (port_b << 6))
+            }
+            0xDD => {
+                let mut value = self.port2_value;
+                if self.peripherals[1].peripheral_type != PeripheralType::Joypad {
+                    // Reading the pins moves the peripheral on to its next
+                    // nibble (with TH as an input), so reuse the 0xDC read.
+                    let pins = if self.th_is_output(1) {
+                        self.read_port_pins(1)
+                    } else {
+                        self.port_b_pins
+                    };
+                    value = (value & 0xF0) | (pins >> 2);
+                }
+                // TH pins set as outputs read back the output level.
+                if self.th_is_output(0) {
+                    value = Joystick::set_bit(
+                        value,
+                        Joystick::PORT2_LG1_BIT,
+                        self.io_control & Joystick::IO_PORTA_TH_LEVEL_BIT != 0,
+                    );
+                }
+                if self.th_is_output(1) {
+                    value = Joystick::set_bit(
+                        value,
+                        Joystick::PORT2_LG2_BIT,
+                        self.io_control & Joystick::IO_PORTB_TH_LEVEL_BIT != 0,
+                    );
+                }
+                Some(value)
+            }
 
             _ => {
                 None /* Unhandled, just return 0 for now */
@@ -153,135 +607,786 @@ impl Joystick {
     }
 }
 
-pub struct Input {}
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    P1Up,
+    P1Down,
+    P1Left,
+    P1Right,
+    P1FireA,
+    P1FireB,
+    P1TurboA,
+    P1TurboB,
+    P2Up,
+    P2Down,
+    P2Left,
+    P2Right,
+    P2FireA,
+    P2FireB,
+    P2TurboA,
+    P2TurboB,
+    Pause,
+    Reset,
+    Quit,
+    MuteChannel(usize),
+    SoloChannel(usize),
+    RecordAudio,
+    FastForward, // Held
+    SlowMotion,
+    PauseEmulation,
+    FrameAdvance,
+    Rewind, // Held
+    Screenshot,
+    RecordVideo,
+    Debug,
+}
+
+impl Action {
+    const ALL: [Action; 36] = [
+        Action::P1Up,
+        Action::P1Down,
+        Action::P1Left,
+        Action::P1Right,
+        Action::P1FireA,
+        Action::P1FireB,
+        Action::P1TurboA,
+        Action::P1TurboB,
+        Action::P2Up,
+        Action::P2Down,
+        Action::P2Left,
+        Action::P2Right,
+        Action::P2FireA,
+        Action::P2FireB,
+        Action::P2TurboA,
+        Action::P2TurboB,
+        Action::Pause,
+        Action::Reset,
+        Action::Quit,
+        Action::MuteChannel(0),
+        Action::MuteChannel(1),
+        Action::MuteChannel(2),
+        Action::MuteChannel(3),
+        Action::SoloChannel(0),
+        Action::SoloChannel(1),
+        Action::SoloChannel(2),
+        Action::SoloChannel(3),
+        Action::RecordAudio,
+        Action::FastForward,
+        Action::SlowMotion,
+        Action::PauseEmulation,
+        Action::FrameAdvance,
+        Action::Rewind,
+        Action::Screenshot,
+        Action::RecordVideo,
+        Action::Debug,
+    ];
+
+    // Name used in the config file.
+    pub fn name(&self) -> String {
+        match self {
+            Action::P1Up => "p1_up".to_string(),
+            Action::P1Down => "p1_down".to_string(),
+            Action::P1Left => "p1_left".to_string(),
+            Action::P1Right => "p1_right".to_string(),
+            Action::P1FireA => "p1_fire_a".to_string(),
+            Action::P1FireB => "p1_fire_b".to_string(),
+            Action::P1TurboA => "p1_turbo_a".to_string(),
+            Action::P1TurboB => "p1_turbo_b".to_string(),
+            Action::P2Up => "p2_up".to_string(),
+            Action::P2Down => "p2_down".to_string(),
+            Action::P2Left => "p2_left".to_string(),
+            Action::P2Right => "p2_right".to_string(),
+            Action::P2FireA => "p2_fire_a".to_string(),
+            Action::P2FireB => "p2_fire_b".to_string(),
+            Action::P2TurboA => "p2_turbo_a".to_string(),
+            Action::P2TurboB => "p2_turbo_b".to_string(),
+            Action::Pause => "pause".to_string(),
+            Action::Reset => "reset".to_string(),
+            Action::Quit => "quit".to_string(),
+            Action::MuteChannel(channel) => format!("mute_channel_{}", channel),
+            Action::SoloChannel(channel) => format!("solo_channel_{}", channel),
+            Action::RecordAudio => "record_audio".to_string(),
+            Action::FastForward => "fast_forward".to_string(),
+            Action::SlowMotion => "slow_motion".to_string(),
+            Action::PauseEmulation => "pause_emulation".to_string(),
+            Action::FrameAdvance => "frame_advance".to_string(),
+            Action::Rewind => "rewind".to_string(),
+            Action::Screenshot => "screenshot".to_string(),
+            Action::RecordVideo => "record_video".to_string(),
+            Action::Debug => "debug".to_string(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .find(|action| action.name() == name)
+            .copied()
+    }
+
+    // The matching player 2 action, for player 1 actions.
+    pub fn for_player2(&self) -> Action {
+        match self {
+            Action::P1Up => Action::P2Up,
+            Action::P1Down => Action::P2Down,
+            Action::P1Left => Action::P2Left,
+            Action::P1Right => Action::P2Right,
+            Action::P1FireA => Action::P2FireA,
+            Action::P1FireB => Action::P2FireB,
+            Action::P1TurboA => Action::P2TurboA,
+            Action::P1TurboB => Action::P2TurboB,
+            _ => *self,
+        }
+    }
+
+    // Apply console (joystick/button) actions, returns 'false' for emulator actions.
+    pub fn apply(&self, joystick: &mut Joystick, pressed: bool) -> bool {
+        // Port bits are active low.
+        let value = !pressed;
+        match self {
+            Action::P1Up => joystick.j1_up(value),
+            Action::P1Down => joystick.j1_down(value),
+            Action::P1Left => joystick.j1_left(value),
+            Action::P1Right => joystick.j1_right(value),
+            Action::P1FireA => joystick.j1_fire_a(value),
+            Action::P1FireB => joystick.j1_fire_b(value),
+            Action::P1TurboA => joystick.j1_turbo_a(value),
+            Action::P1TurboB => joystick.j1_turbo_b(value),
+            Action::P2Up => joystick.j2_up(value),
+            Action::P2Down => joystick.j2_down(value),
+            Action::P2Left => joystick.j2_left(value),
+            Action::P2Right => joystick.j2_right(value),
+            Action::P2FireA => joystick.j2_fire_a(value),
+            Action::P2FireB => joystick.j2_fire_b(value),
+            Action::P2TurboA => joystick.j2_turbo_a(value),
+            Action::P2TurboB => joystick.j2_turbo_b(value),
+            Action::Pause => joystick.pause(value),
+            Action::Reset => joystick.reset(value),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct KeyBinding {
+    pub keycode: keyboard::Keycode,
+    pub shift: bool,
+}
+
+impl KeyBinding {
+    pub fn new(keycode: keyboard::Keycode) -> Self {
+        Self {
+            keycode,
+            shift: false,
+        }
+    }
+
+    pub fn with_shift(keycode: keyboard::Keycode) -> Self {
+        Self {
+            keycode,
+            shift: true,
+        }
+    }
+
+    // Parse an SDL key name, optionally prefixed with 'Shift+', eg "Shift+F1".
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim();
+        match name.split_once('+') {
+            Some((modifier, key)) if modifier.trim().eq_ignore_ascii_case("shift") => {
+                keyboard::Keycode::from_name(key.trim()).map(KeyBinding::with_shift)
+            }
+            _ => keyboard::Keycode::from_name(name).map(KeyBinding::new),
+        }
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.shift {
+            write!(f, "Shift+{}", self.keycode)
+        } else {
+            write!(f, "{}", self.keycode)
+        }
+    }
+}
+
+pub struct Input {
+    bindings: HashMap<KeyBinding, Action>,
+    pressed_keys: HashMap<keyboard::Keycode, Action>, // Action each held key pressed.
+}
 
 impl Input {
-    const KEY_UP: keyboard::Keycode = keyboard::Keycode::Up;
-    const KEY_DOWN: keyboard::Keycode = keyboard::Keycode::Down;
-    const KEY_LEFT: keyboard::Keycode = keyboard::Keycode::Left;
-    const KEY_RIGHT: keyboard::Keycode = keyboard::Keycode::Right;
-    const KEY_FIRE_A: keyboard::Keycode = keyboard::Keycode::Z;
-    const KEY_FIRE_B: keyboard::Keycode = keyboard::Keycode::X;
-    const KEY_RESET: keyboard::Keycode = keyboard::Keycode::R;
-    const KEY_QUIT: keyboard::Keycode = keyboard::Keycode::Escape;
-
-    pub fn print_keys() {
-        println!("Key mappings (Joystick 1):");
+    pub const CONFIG_SECTION: &'static str = "keys";
+
+    pub fn new() -> Self {
+        let mut input = Self {
+            bindings: HashMap::new(),
+            pressed_keys: HashMap::new(),
+        };
+        input.bind(Action::P1Up, &[KeyBinding::new(keyboard::Keycode::Up)]);
+        input.bind(Action::P1Down, &[KeyBinding::new(keyboard::Keycode::Down)]);
+        input.bind(Action::P1Left, &[KeyBinding::new(keyboard::Keycode::Left)]);
+        input.bind(
+            Action::P1Right,
+            &[KeyBinding::new(keyboard::Keycode::Right)],
+        );
+        input.bind(Action::P1FireA, &[KeyBinding::new(keyboard::Keycode::Z)]);
+        input.bind(Action::P1FireB, &[KeyBinding::new(keyboard::Keycode::X)]);
+        input.bind(Action::P1TurboA, &[KeyBinding::new(keyboard::Keycode::C)]);
+        input.bind(Action::P1TurboB, &[KeyBinding::new(keyboard::Keycode::V)]);
+        input.bind(Action::P2Up, &[KeyBinding::new(keyboard::Keycode::W)]);
+        input.bind(Action::P2Down, &[KeyBinding::new(keyboard::Keycode::S)]);
+        input.bind(Action::P2Left, &[KeyBinding::new(keyboard::Keycode::A)]);
+        input.bind(Action::P2Right, &[KeyBinding::new(keyboard::Keycode::D)]);
+        input.bind(Action::P2FireA, &[KeyBinding::new(keyboard::Keycode::F)]);
+        input.bind(Action::P2FireB, &[KeyBinding::new(keyboard::Keycode::G)]);
+        input.bind(Action::P2TurboA, &[KeyBinding::new(keyboard::Keycode::H)]);
+        input.bind(Action::P2TurboB, &[KeyBinding::new(keyboard::Keycode::J)]);
+        input.bind(Action::Pause, &[KeyBinding::new(keyboard::Keycode::P)]);
+        input.bind(Action::Reset, &[KeyBinding::new(keyboard::Keycode::R)]);
+        input.bind(Action::Quit, &[KeyBinding::new(keyboard::Keycode::Escape)]);
+
+        let channel_keys = [
+            keyboard::Keycode::F1,
+            keyboard::Keycode::F2,
+            keyboard::Keycode::F3,
+            keyboard::Keycode::F4,
+        ];
+        for (channel, keycode) in channel_keys.into_iter().enumerate() {
+            input.bind(Action::MuteChannel(channel), &[KeyBinding::new(keycode)]);
+            input.bind(
+                Action::SoloChannel(channel),
+                &[KeyBinding::with_shift(keycode)],
+            );
+        }
+        input.bind(
+            Action::RecordAudio,
+            &[KeyBinding::new(keyboard::Keycode::F9)],
+        );
+        input.bind(
+            Action::FastForward,
+            &[KeyBinding::new(keyboard::Keycode::Tab)],
+        );
+        input.bind(
+            Action::PauseEmulation,
+            &[KeyBinding::new(keyboard::Keycode::F5)],
+        );
+        input.bind(
+            Action::FrameAdvance,
+            &[KeyBinding::new(keyboard::Keycode::F6)],
+        );
+        input.bind(
+            Action::SlowMotion,
+            &[KeyBinding::new(keyboard::Keycode::F7)],
+        );
+        input.bind(
+            Action::Rewind,
+            &[KeyBinding::new(keyboard::Keycode::Backspace)],
+        );
+        input.bind(
+            Action::Screenshot,
+            &[KeyBinding::new(keyboard::Keycode::F12)],
+        );
+        input.bind(
+            Action::RecordVideo,
+            &[KeyBinding::new(keyboard::Keycode::F10)],
+        );
+        input.bind(Action::Debug, &[KeyBinding::new(keyboard::Keycode::F8)]);
+        input
+    }
+
+    // Replace the keys bound to 'action'.
+    pub fn bind(&mut self, action: Action, keys: &[KeyBinding]) {
+        self.bindings
+            .retain(|_, bound_action| *bound_action != action);
+        for key in keys {
+            self.bindings.insert(*key, action);
+        }
+    }
+
+    pub fn keys_for(&self, action: Action) -> Vec<KeyBinding> {
+        let mut keys: Vec<KeyBinding> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound_action)| **bound_action == action)
+            .map(|(key, _)| *key)
+            .collect();
+        keys.sort_by_key(|key| (key.shift, key.keycode as i32));
+        keys
+    }
+
+    // Override the default bindings with those in the '[keys]' section of the config.
+    // Each entry is a comma separated list of keys (empty to unbind), eg:
+    //    p1_fire_a = "Z, Space"
+    pub fn load_config(&mut self, config: &config::Config) {
+        for (name, value) in config.section(Input::CONFIG_SECTION) {
+            let action = match Action::from_name(name) {
+                Some(action) => action,
+                None => {
+                    println!("Unknown key binding action in config: {}", name);
+                    continue;
+                }
+            };
+            let mut keys = Vec::new();
+            for key_name in value.split(',').filter(|key| !key.trim().is_empty()) {
+                match KeyBinding::parse(key_name) {
+                    Some(key) => keys.push(key),
+                    None => println!("Unknown key '{}' for {} in config.", key_name.trim(), name),
+                }
+            }
+            self.bind(action, &keys);
+        }
+    }
+
+    fn describe_keys(&self, action: Action) -> String {
+        let keys = self.keys_for(action);
+        if keys.is_empty() {
+            "(unbound)".to_string()
+        } else {
+            keys.iter()
+                .map(|key| key.to_string())
+                .collect::<Vec<String>>()
+                .join("/")
+        }
+    }
+
+    pub fn print_keys(&self) {
+        for (player, actions) in [
+            (
+                1,
+                [
+                    Action::P1Up,
+                    Action::P1Down,
+                    Action::P1Left,
+                    Action::P1Right,
+                    Action::P1FireA,
+                    Action::P1FireB,
+                    Action::P1TurboA,
+                    Action::P1TurboB,
+                ],
+            ),
+            (
+                2,
+                [
+                    Action::P2Up,
+                    Action::P2Down,
+                    Action::P2Left,
+                    Action::P2Right,
+                    Action::P2FireA,
+                    Action::P2FireB,
+                    Action::P2TurboA,
+                    Action::P2TurboB,
+                ],
+            ),
+        ] {
+            println!("Key mappings (Joystick {}):", player);
+            println!(
+                "Up: {}, Down: {}, Left: {}, Right: {}",
+                self.describe_keys(actions[0]),
+                self.describe_keys(actions[1]),
+                self.describe_keys(actions[2]),
+                self.describe_keys(actions[3])
+            );
+            println!(
+                "Fire A: {}, Fire B: {}, Turbo A: {}, Turbo B: {}",
+                self.describe_keys(actions[4]),
+                self.describe_keys(actions[5]),
+                self.describe_keys(actions[6]),
+                self.describe_keys(actions[7])
+            );
+        }
+        println!(
+            "Pause: {}, Reset: {}",
+            self.describe_keys(Action::Pause),
+            self.describe_keys(Action::Reset)
+        );
+        println!(
+            "Mute sound channel 0-3: {}",
+            (0..4)
+                .map(|channel| self.describe_keys(Action::MuteChannel(channel)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        println!(
+            "Solo sound channel 0-3: {}",
+            (0..4)
+                .map(|channel| self.describe_keys(Action::SoloChannel(channel)))
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        println!(
+            "Start/stop audio recording: {}",
+            self.describe_keys(Action::RecordAudio)
+        );
+        println!(
+            "Fast forward (hold): {}, Slow motion (1x/0.5x/0.25x): {}",
+            self.describe_keys(Action::FastForward),
+            self.describe_keys(Action::SlowMotion)
+        );
         println!(
-            "Up: {}, Down: {}, Left: {}, Right: {}",
-            Input::KEY_UP,
-            Input::KEY_DOWN,
-            Input::KEY_LEFT,
-            Input::KEY_RIGHT
+            "Pause emulation: {}, Frame advance: {}",
+            self.describe_keys(Action::PauseEmulation),
+            self.describe_keys(Action::FrameAdvance)
         );
+        println!("Rewind (hold): {}", self.describe_keys(Action::Rewind));
         println!(
-            "Fire A: {}, Fire B: {}",
-            Input::KEY_FIRE_A,
-            Input::KEY_FIRE_B
+            "Screenshot: {}, Start/stop video recording: {}",
+            self.describe_keys(Action::Screenshot),
+            self.describe_keys(Action::RecordVideo)
         );
-        println!("Reset: {}", Input::KEY_RESET);
+        println!("Debugger: {}", self.describe_keys(Action::Debug));
         println!();
-        println!("Quit: {}", Input::KEY_QUIT);
+        println!("Quit: {}", self.describe_keys(Action::Quit));
     }
 
-    // Return 'true' if handled, otherwise 'false' (ie quit)
-    pub fn handle_events(event: event::Event, joystick: &mut Joystick) -> bool {
-        match event {
-            event::Event::Quit { .. }
-            | event::Event::KeyDown {
-                keycode: Some(Input::KEY_QUIT),
-                ..
-            } => return false,
+    fn lookup(&self, keycode: keyboard::Keycode, keymod: keyboard::Mod) -> Option<Action> {
+        let shift = keymod.intersects(keyboard::Mod::LSHIFTMOD | keyboard::Mod::RSHIFTMOD);
+        self.bindings
+            .get(&KeyBinding { keycode, shift })
+            .or_else(|| self.bindings.get(&KeyBinding::new(keycode)))
+            .copied()
+    }
 
+    // Update the joystick from the key events, returns emulator actions
+    // (quit, hotkeys) for the caller to handle, with whether the key was
+    // pressed or released.
+    pub fn handle_events(
+        &mut self,
+        event: &event::Event,
+        joystick: &mut Joystick,
+    ) -> Option<(Action, bool)> {
+        match event {
+            event::Event::Quit { .. } => Some((Action::Quit, true)),
             event::Event::KeyDown {
-                keycode: Some(Input::KEY_UP),
-                ..
-            } => {
-                joystick.j1_up(false);
-            }
-            event::Event::KeyDown {
-                keycode: Some(Input::KEY_DOWN),
-                ..
-            } => {
-                joystick.j1_down(false);
-            }
-            event::Event::KeyDown {
-                keycode: Some(Input::KEY_LEFT),
-                ..
-            } => {
-                joystick.j1_left(false);
-            }
-            event::Event::KeyDown {
-                keycode: Some(Input::KEY_RIGHT),
-                ..
-            } => {
-                joystick.j1_right(false);
-            }
-            event::Event::KeyDown {
-                keycode: Some(Input::KEY_FIRE_A),
-                ..
-            } => {
-                joystick.j1_fire_a(false);
-            }
-            event::Event::KeyDown {
-                keycode: Some(Input::KEY_FIRE_B),
-                ..
-            } => {
-                joystick.j1_fire_b(false);
-            }
-            event::Event::KeyDown {
-                keycode: Some(Input::KEY_RESET),
-                ..
-            } => {
-                joystick.reset(false);
-            }
-
-            event::Event::KeyUp {
-                keycode: Some(Input::KEY_UP),
-                ..
-            } => {
-                joystick.j1_up(true);
-            }
-            event::Event::KeyUp {
-                keycode: Some(Input::KEY_DOWN),
+                keycode: Some(keycode),
+                keymod,
+                repeat,
                 ..
-            } => {
-                joystick.j1_down(true);
-            }
+            } => match self.lookup(*keycode, *keymod) {
+                // Key repeats are ignored (the key's still held), otherwise a
+                // held pause would keep toggling.
+                Some(_) if *repeat => None,
+                Some(action) => {
+                    self.pressed_keys.insert(*keycode, action);
+                    if !action.apply(joystick, true) {
+                        Some((action, true))
+                    } else {
+                        None
+                    }
+                }
+                None => None,
+            },
             event::Event::KeyUp {
-                keycode: Some(Input::KEY_LEFT),
+                keycode: Some(keycode),
+                keymod,
                 ..
             } => {
-                joystick.j1_left(true);
+                // Release whatever the key pressed, the modifiers may have changed since.
+                let action = self
+                    .pressed_keys
+                    .remove(keycode)
+                    .or_else(|| self.lookup(*keycode, *keymod));
+                match action {
+                    Some(action) if !action.apply(joystick, false) => Some((action, false)),
+                    _ => None,
+                }
             }
-            event::Event::KeyUp {
-                keycode: Some(Input::KEY_RIGHT),
-                ..
-            } => {
-                joystick.j1_right(true);
+            event::Event::MouseMotion { xrel, yrel, .. } => {
+                joystick.mouse_motion(*xrel, *yrel);
+                None
             }
-            event::Event::KeyUp {
-                keycode: Some(Input::KEY_FIRE_A),
-                ..
-            } => {
-                joystick.j1_fire_a(true);
+            event::Event::MouseButtonDown { mouse_btn, .. }
+            | event::Event::MouseButtonUp { mouse_btn, .. } => {
+                let pressed = matches!(event, event::Event::MouseButtonDown { .. });
+                match mouse_btn {
+                    mouse::MouseButton::Left => joystick.mouse_button(true, pressed),
+                    mouse::MouseButton::Right => joystick.mouse_button(false, pressed),
+                    _ => {}
+                }
+                None
             }
-            event::Event::KeyUp {
-                keycode: Some(Input::KEY_FIRE_B),
-                ..
-            } => {
-                joystick.j1_fire_b(true);
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::clocks;
+    use crate::sega::config;
+    use crate::sega::inputs;
+    use sdl2::event;
+    use sdl2::keyboard;
+
+    fn key_event(keycode: keyboard::Keycode, keymod: keyboard::Mod, down: bool) -> event::Event {
+        if down {
+            event::Event::KeyDown {
+                timestamp: 0,
+                window_id: 0,
+                keycode: Some(keycode),
+                scancode: None,
+                keymod,
+                repeat: false,
             }
+        } else {
             event::Event::KeyUp {
-                keycode: Some(Input::KEY_RESET),
-                ..
-            } => {
-                joystick.reset(true);
+                timestamp: 0,
+                window_id: 0,
+                keycode: Some(keycode),
+                scancode: None,
+                keymod,
+                repeat: false,
             }
+        }
+    }
+
+    #[test]
+    fn test_default_bindings() {
+        let mut input = inputs::Input::new();
+        let mut joystick = inputs::Joystick::new();
+
+        let no_mod = keyboard::Mod::NOMOD;
+        let action = input.handle_events(
+            &key_event(keyboard::Keycode::Z, no_mod, true),
+            &mut joystick,
+        );
+        assert_eq!(action, None);
+        assert_eq!(joystick.read_port1(), 0xEF); // Fire A bit is active low
+        input.handle_events(
+            &key_event(keyboard::Keycode::Z, no_mod, false),
+            &mut joystick,
+        );
+        assert_eq!(joystick.read_port1(), 0xFF);
 
-            _ => return true,
+        assert_eq!(
+            input.handle_events(
+                &key_event(keyboard::Keycode::F2, no_mod, true),
+                &mut joystick
+            ),
+            Some((inputs::Action::MuteChannel(1), true))
+        );
+        assert_eq!(
+            input.handle_events(
+                &key_event(keyboard::Keycode::F2, keyboard::Mod::LSHIFTMOD, true),
+                &mut joystick
+            ),
+            Some((inputs::Action::SoloChannel(1), true))
+        );
+        // Unshifted bindings still work with shift held.
+        assert_eq!(
+            input.handle_events(
+                &key_event(keyboard::Keycode::Escape, keyboard::Mod::LSHIFTMOD, true),
+                &mut joystick
+            ),
+            Some((inputs::Action::Quit, true))
+        );
+
+        // Releasing an emulator hotkey is reported too (for held actions).
+        assert_eq!(
+            input.handle_events(
+                &key_event(keyboard::Keycode::Tab, no_mod, false),
+                &mut joystick
+            ),
+            Some((inputs::Action::FastForward, false))
+        );
+
+        input.handle_events(
+            &key_event(keyboard::Keycode::P, no_mod, true),
+            &mut joystick,
+        );
+        assert!(joystick.take_pause_request());
+        assert!(!joystick.take_pause_request());
+
+        // Holding a key down doesn't press it again.
+        let mut repeat = key_event(keyboard::Keycode::P, no_mod, true);
+        if let event::Event::KeyDown { repeat, .. } = &mut repeat {
+            *repeat = true;
         }
+        assert_eq!(input.handle_events(&repeat, &mut joystick), None);
+        assert!(!joystick.take_pause_request());
+    }
 
-        true
+    #[test]
+    fn test_rebind() {
+        let mut input = inputs::Input::new();
+        let mut joystick = inputs::Joystick::new();
+
+        input.bind(
+            inputs::Action::P2FireA,
+            &[inputs::KeyBinding::new(keyboard::Keycode::Z)],
+        );
+        assert!(input.keys_for(inputs::Action::P1FireA).is_empty());
+        input.handle_events(
+            &key_event(keyboard::Keycode::Z, keyboard::Mod::NOMOD, true),
+            &mut joystick,
+        );
+        assert_eq!(joystick.read_port1(), 0xFF);
+        assert_eq!(joystick.read_port2(), 0xFB); // Joystick 2 Fire A
+
+        // An empty entry unbinds the action.
+        input.load_config(&config::Config::parse("[keys]\nquit = \"\"\n").unwrap());
+        assert!(input.keys_for(inputs::Action::Quit).is_empty());
+    }
+
+    #[test]
+    fn test_modifier_released_first() {
+        let mut input = inputs::Input::new();
+        let mut joystick = inputs::Joystick::new();
+        input.bind(
+            inputs::Action::P2FireA,
+            &[inputs::KeyBinding::with_shift(keyboard::Keycode::Z)],
+        );
+
+        // Shift+Z, then shift is released before Z.
+        input.handle_events(
+            &key_event(keyboard::Keycode::Z, keyboard::Mod::LSHIFTMOD, true),
+            &mut joystick,
+        );
+        assert_eq!(joystick.read_port2(), 0xFB); // Joystick 2 Fire A
+        input.handle_events(
+            &key_event(keyboard::Keycode::Z, keyboard::Mod::NOMOD, false),
+            &mut joystick,
+        );
+        assert_eq!(joystick.read_port1(), 0xFF);
+        assert_eq!(joystick.read_port2(), 0xFF);
+    }
+
+    #[test]
+    fn test_opposing_directions() {
+        let mut joystick = inputs::Joystick::new();
+
+        // Default, last pressed wins.
+        joystick.j1_up(false);
+        joystick.j1_down(false);
+        assert_eq!(joystick.read_port1(), 0xFD); // Down
+        joystick.j1_down(true);
+        assert_eq!(joystick.read_port1(), 0xFE); // Up
+        joystick.j1_down(false);
+        joystick.j1_up(false); // Repeated press doesn't change the order.
+        assert_eq!(joystick.read_port1(), 0xFD);
+
+        joystick.set_opposing_directions(inputs::OpposingDirections::Neutral);
+        assert_eq!(joystick.read_port1(), 0xFF);
+
+        joystick.set_opposing_directions(inputs::OpposingDirections::Allow);
+        assert_eq!(joystick.read_port1(), 0xFC);
+
+        // Player 2 left/right are split across the ports.
+        joystick.set_opposing_directions(inputs::OpposingDirections::Neutral);
+        joystick.j2_left(false);
+        assert_eq!(joystick.read_port2(), 0xFE);
+        joystick.j2_right(false);
+        assert_eq!(joystick.read_port2(), 0xFF);
+        joystick.j2_left(true);
+        assert_eq!(joystick.read_port2(), 0xFD);
+
+        assert_eq!(
+            inputs::OpposingDirections::from_name("Last"),
+            Some(inputs::OpposingDirections::LastPressed)
+        );
+        assert_eq!(inputs::OpposingDirections::from_name("other"), None);
+    }
+
+    #[test]
+    fn test_paddle() {
+        let clock = clocks::Clock::new();
+        let mut joystick = inputs::Joystick::new();
+        joystick.set_peripheral(0, inputs::PeripheralType::Paddle);
+        joystick.mouse_motion(0x34 - 0x80, 0);
+
+        // Japanese console, the nibble toggles on each read (high nibble has TR set).
+        assert_eq!(joystick.port_read(&clock, 0xDC), Some(0xC0 | 0x30 | 0x3));
+        assert_eq!(joystick.port_read(&clock, 0xDC), Some(0xC0 | 0x10 | 0x4));
+        assert_eq!(joystick.port_read(&clock, 0xDC), Some(0xC0 | 0x30 | 0x3));
+
+        // Export console, TH selects the nibble.
+        joystick.write_io_control(0xDD); // Port A TH output, low.
+        assert_eq!(joystick.port_read(&clock, 0xDC), Some(0xC0 | 0x10 | 0x4));
+        assert_eq!(joystick.port_read(&clock, 0xDC), Some(0xC0 | 0x10 | 0x4));
+        joystick.write_io_control(0xFD); // Port A TH output, high.
+        assert_eq!(joystick.port_read(&clock, 0xDC), Some(0xC0 | 0x30 | 0x3));
+        assert_eq!(joystick.port_read(&clock, 0xDD).unwrap() & 0x40, 0x40);
+
+        // The button is TL.
+        joystick.mouse_button(true, true);
+        assert_eq!(joystick.port_read(&clock, 0xDC), Some(0xC0 | 0x20 | 0x3));
+    }
+
+    #[test]
+    fn test_sports_pad() {
+        let clock = clocks::Clock::new();
+        let mut joystick = inputs::Joystick::new();
+        joystick.set_peripheral(1, inputs::PeripheralType::SportsPad);
+        joystick.mouse_motion(-0x12, 0x34);
+
+        // Export console, a TH low/high/low/high sequence reads X then Y.
+        let mut nibbles = Vec::new();
+        for io_control in [0x77, 0xF7, 0x77, 0xF7] {
+            joystick.write_io_control(io_control);
+            let port_b = (joystick.port_read(&clock, 0xDC).unwrap() >> 6)
+                | ((joystick.port_read(&clock, 0xDD).unwrap() & 0x3) << 2);
+            nibbles.push(port_b);
+        }
+        assert_eq!(nibbles, vec![0x1, 0x2, 0xC, 0xC]); // 0x12, -0x34
+
+        // Buttons are unaffected by the position.
+        assert_eq!(joystick.port_read(&clock, 0xDD).unwrap() & 0xC, 0xC);
+
+        // Japanese console, each 0xDC read moves on a nibble, with the rest
+        // of it read through 0xDD.
+        let mut joystick = inputs::Joystick::new();
+        joystick.set_peripheral(1, inputs::PeripheralType::SportsPad);
+        joystick.mouse_motion(-0x12, 0x34);
+        let mut nibbles = Vec::new();
+        for _ in 0..4 {
+            let port_b = (joystick.port_read(&clock, 0xDC).unwrap() >> 6)
+                | ((joystick.port_read(&clock, 0xDD).unwrap() & 0x3) << 2);
+            nibbles.push(port_b);
+        }
+        assert_eq!(nibbles, vec![0x1, 0x2, 0xC, 0xC]);
+    }
+
+    #[test]
+    fn test_turbo() {
+        let mut joystick = inputs::Joystick::new();
+        joystick.set_turbo_rate(3);
+        joystick.update_turbo(0);
+        inputs::Action::P1TurboA.apply(&mut joystick, true);
+
+        // Pressed for 3 frames, released for 3 frames.
+        let fire_a: Vec<bool> = (0..12)
+            .map(|frame| {
+                joystick.update_turbo(frame);
+                joystick.read_port1() & 0x10 == 0
+            })
+            .collect();
+        assert_eq!(
+            fire_a,
+            vec![true, true, true, false, false, false, true, true, true, false, false, false]
+        );
+
+        // Holding the normal button overrides the turbo, releasing both releases the button.
+        inputs::Action::P1FireA.apply(&mut joystick, true);
+        assert_eq!(joystick.read_port1() & 0x10, 0);
+        inputs::Action::P1FireA.apply(&mut joystick, false);
+        assert_eq!(joystick.read_port1() & 0x10, 0x10);
+        inputs::Action::P1TurboA.apply(&mut joystick, false);
+        joystick.update_turbo(12);
+        assert_eq!(joystick.read_port1(), 0xFF);
+
+        // Player 2 turbo B.
+        inputs::Action::P2TurboB.apply(&mut joystick, true);
+        assert_eq!(joystick.read_port2(), 0xF7);
+        joystick.update_turbo(15);
+        assert_eq!(joystick.read_port2(), 0xFF);
+    }
+
+    #[test]
+    fn test_action_names() {
+        for action in inputs::Action::ALL {
+            assert_eq!(inputs::Action::from_name(&action.name()), Some(action));
+        }
+        assert_eq!(inputs::Action::from_name("unknown"), None);
     }
 }
@@ -0,0 +1,62 @@
+use super::super::snapshot;
+use super::memory;
+use super::memory::impl_common_memoryrw;
+
+// A flat (unbanked) 64K RAM, for running Z80 code outside of the Master
+// System memory map (tests, CPU exercisers).
+pub struct FlatMemory {
+    memory: Vec<u8>,
+}
+
+impl FlatMemory {
+    const SIZE: usize = 0x10000;
+
+    pub fn new() -> Self {
+        Self {
+            memory: vec![0; FlatMemory::SIZE],
+        }
+    }
+
+    // Copy 'data' into memory starting at 'address' (wrapping at the end of memory).
+    pub fn load(&mut self, address: memory::AddressType, data: &[u8]) {
+        for (offset, value) in data.iter().enumerate() {
+            self.memory[(address as usize + offset) % FlatMemory::SIZE] = *value;
+        }
+    }
+
+    fn read(&self, address: memory::AddressType) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: memory::AddressType, data: u8) {
+        self.memory[address as usize] = data;
+    }
+}
+
+impl_common_memoryrw!(FlatMemory);
+
+impl snapshot::Snapshot for FlatMemory {
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        writer.write_bytes(&self.memory);
+    }
+
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        reader.read_into(&mut self.memory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::memory::flat;
+    use crate::sega::memory::memory::MemoryRW;
+
+    #[test]
+    fn test_flat_memory() {
+        let mut flat_memory = flat::FlatMemory::new();
+        flat_memory.load(0xFFFF, &[0x12, 0x34]);
+        assert_eq!(MemoryRW::read(&flat_memory, 0xFFFF), 0x12);
+        assert_eq!(MemoryRW::read(&flat_memory, 0x0000), 0x34);
+        MemoryRW::write(&mut flat_memory, 0x8000, 0x56);
+        assert_eq!(MemoryRW::read16(&flat_memory, 0x7FFF), 0x5600);
+    }
+}
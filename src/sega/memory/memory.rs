@@ -1,3 +1,5 @@
+use super::super::snapshot;
+use super::super::watch;
 use super::cartridge;
 
 ///  Map the current 'pc' address to an 'absolute' address.  The
@@ -144,6 +146,23 @@ impl MemoryAbsolute {
         self.upper_mappings[(address >> 13) as usize] | (address & 0x1FFF) as AbsoluteAddressType
     }
 
+    pub fn get_bank(&self, address: AddressType) -> Option<u8> {
+        let mapping = self.upper_mappings[(address >> 13) as usize];
+        if mapping >= MemoryAbsoluteConstants::ABSOLUTE_CART_RAM_OFFSET {
+            None
+        } else if address < MemoryBase::PAGE0 {
+            // The first 1K is always bank 0.
+            Some(0)
+        } else {
+            let rom_offset = if mapping >= MemoryAbsoluteConstants::ABSOLUTE_PAGE_X_ROM_OFFSET {
+                MemoryAbsoluteConstants::ABSOLUTE_PAGE_X_ROM_OFFSET
+            } else {
+                MemoryAbsoluteConstants::ABSOLUTE_PAGE_0_ROM_OFFSET
+            };
+            Some(((mapping - rom_offset) / MemoryBase::BANK_SIZE as AbsoluteAddressType) as u8)
+        }
+    }
+
     pub fn read(&self, address: AddressType) -> u8 {
         self.memory_map[(self.upper_mappings[(address >> 13) as usize]
             | (address & 0x1FFF) as AbsoluteAddressType) as usize]
@@ -267,6 +286,32 @@ impl MemoryAbsolute {
     }
 }
 
+// Only the paging registers and RAM (cartridge and system) are saved, the ROM
+// doesn't change.
+impl snapshot::Snapshot for MemoryAbsolute {
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        writer.write_u8(self.page_2);
+        writer.write_u8(self.ram_select);
+        for mapping in &self.upper_mappings {
+            writer.write_u32(*mapping);
+        }
+        writer.write_bytes(
+            &self.memory_map[MemoryAbsoluteConstants::ABSOLUTE_CART_RAM_OFFSET as usize..],
+        );
+    }
+
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        self.page_2 = reader.read_u8()?;
+        self.ram_select = reader.read_u8()?;
+        for mapping in self.upper_mappings.iter_mut() {
+            *mapping = reader.read_u32()?;
+        }
+        reader.read_into(
+            &mut self.memory_map[MemoryAbsoluteConstants::ABSOLUTE_CART_RAM_OFFSET as usize..],
+        )
+    }
+}
+
 // Common macro to help export the read/write rules
 #[macro_export]
 macro_rules! impl_common_memoryrw {
@@ -294,12 +339,49 @@ macro_rules! impl_common_memoryrw {
 
 pub(crate) use impl_common_memoryrw;
 
-impl_common_memoryrw!(MemoryAbsolute);
+// As 'impl_common_memoryrw', plus the ROM bank lookup.
+impl MemoryRW for MemoryAbsolute {
+    fn read(&self, address: AddressType) -> u8 {
+        self.read(address)
+    }
+
+    fn read16(&self, address: AddressType) -> u16 {
+        self.read(address) as u16 + ((self.read(address + 1) as u16) << 8)
+    }
+
+    fn write(&mut self, address: AddressType, data: u8) {
+        self.write(address, data);
+    }
+
+    fn get_bank(&self, address: AddressType) -> Option<u8> {
+        self.get_bank(address)
+    }
+}
 
 pub trait MemoryRW {
     fn read(&self, address: AddressType) -> u8;
     fn read16(&self, address: AddressType) -> u16;
     fn write(&mut self, address: AddressType, data: u8);
+
+    // Read without triggering the watches (for the debugger's disassembly).
+    fn peek(&self, address: AddressType) -> u8 {
+        self.read(address)
+    }
+
+    // Watch (see 'watched::WatchedMemory') triggered since the last call.
+    fn take_watch_hit(&self) -> Option<watch::WatchHit> {
+        None
+    }
+
+    // The watches, for memories that support them.
+    fn get_watches_mut(&mut self) -> Option<&mut watch::WatchList> {
+        None
+    }
+
+    // ROM bank paged in at 'address', 'None' for RAM (or unbanked memory).
+    fn get_bank(&self, _address: AddressType) -> Option<u8> {
+        None
+    }
 }
 
 #[cfg(test)]
@@ -318,4 +400,20 @@ mod tests {
             mem::size_of_val(&memory.upper_mappings)
         );
     }
+
+    #[test]
+    fn test_get_bank() {
+        let mut memory = MemoryAbsolute::new();
+        memory.write(0xFFFD, 3);
+        memory.write(0xFFFE, 4);
+        memory.write(0xFFFF, 5);
+        assert_eq!(memory.get_bank(0x0100), Some(0));
+        assert_eq!(memory.get_bank(0x0400), Some(3));
+        assert_eq!(memory.get_bank(0x7FFF), Some(4));
+        assert_eq!(memory.get_bank(0x8000), Some(5));
+        assert_eq!(memory.get_bank(0xC000), None);
+        // Cartridge RAM paged in.
+        memory.write(0xFFFC, 0x08);
+        assert_eq!(memory.get_bank(0x8000), None);
+    }
 }
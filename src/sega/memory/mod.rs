@@ -1,2 +1,4 @@
 pub mod cartridge;
+pub mod flat;
 pub mod memory;
+pub mod watched;
@@ -0,0 +1,122 @@
+use super::super::snapshot;
+use super::super::watch;
+use super::memory;
+use std::cell;
+
+// Wraps a memory, checking each access against the watches (for the
+// debugger).  Without any watches the accesses go straight through.
+pub struct WatchedMemory<M> {
+    pub inner: M,
+    pub watches: watch::WatchList,
+    hit: cell::Cell<Option<watch::WatchHit>>, // First hit since the last 'take_watch_hit'.
+}
+
+impl<M: memory::MemoryRW> WatchedMemory<M> {
+    pub fn new(inner: M) -> Self {
+        Self {
+            inner,
+            watches: watch::WatchList::new(watch::Target::Memory),
+            hit: cell::Cell::new(None),
+        }
+    }
+
+    fn check(&self, access: watch::Access, address: memory::AddressType, value: u8) {
+        if self.hit.get().is_none() {
+            self.hit.set(self.watches.check(access, address, value));
+        }
+    }
+}
+
+impl<M: memory::MemoryRW> memory::MemoryRW for WatchedMemory<M> {
+    fn read(&self, address: memory::AddressType) -> u8 {
+        let value = self.inner.read(address);
+        if !self.watches.is_empty() {
+            self.check(watch::Access::Read, address, value);
+        }
+        value
+    }
+
+    fn read16(&self, address: memory::AddressType) -> u16 {
+        if self.watches.is_empty() {
+            return self.inner.read16(address);
+        }
+        self.read(address) as u16 + ((self.read(address.wrapping_add(1)) as u16) << 8)
+    }
+
+    fn write(&mut self, address: memory::AddressType, data: u8) {
+        if !self.watches.is_empty() {
+            self.check(watch::Access::Write, address, data);
+        }
+        self.inner.write(address, data);
+    }
+
+    fn peek(&self, address: memory::AddressType) -> u8 {
+        self.inner.peek(address)
+    }
+
+    fn take_watch_hit(&self) -> Option<watch::WatchHit> {
+        self.hit.take()
+    }
+
+    fn get_watches_mut(&mut self) -> Option<&mut watch::WatchList> {
+        Some(&mut self.watches)
+    }
+
+    fn get_bank(&self, address: memory::AddressType) -> Option<u8> {
+        self.inner.get_bank(address)
+    }
+}
+
+impl<M: snapshot::Snapshot> snapshot::Snapshot for WatchedMemory<M> {
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        self.inner.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        self.inner.load_state(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::memory::flat;
+    use crate::sega::memory::memory::MemoryRW;
+    use crate::sega::memory::watched;
+    use crate::sega::watch;
+
+    #[test]
+    fn test_watched_memory() {
+        let mut memory = watched::WatchedMemory::new(flat::FlatMemory::new());
+        memory.write(0xFFFF, 0x05);
+        assert!(memory.take_watch_hit().is_none());
+
+        // Mapper register writes (of a given bank).
+        memory.watches.add(watch::Watch::new(
+            watch::Access::Write,
+            0xFFFC,
+            0xFFFF,
+            Some(0x05),
+        ));
+        memory.write(0xFFFE, 0x04);
+        assert_eq!(memory.read(0xFFFF), 0x05);
+        assert!(memory.take_watch_hit().is_none());
+        memory.write(0xFFFE, 0x05);
+        memory.write(0xFFFF, 0x05);
+        let hit = memory.take_watch_hit().unwrap();
+        assert_eq!(hit.address, 0xFFFE);
+        assert!(memory.take_watch_hit().is_none());
+
+        // 16-bit reads check both bytes.
+        memory.watches.clear();
+        memory
+            .watches
+            .add(watch::Watch::new(watch::Access::Read, 0x8001, 0x8001, None));
+        memory.write(0x8001, 0x12);
+        assert_eq!(memory.read16(0x8000), 0x1200);
+        assert_eq!(memory.take_watch_hit().unwrap().access, watch::Access::Read);
+
+        // Peeks (disassembly) don't trigger the watches.
+        assert_eq!(memory.peek(0x8001), 0x12);
+        assert!(memory.take_watch_hit().is_none());
+    }
+}
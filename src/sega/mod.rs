@@ -1,9 +1,18 @@
 pub mod audio;
+pub mod checksum;
 pub mod clocks;
+pub mod config;
+pub mod controllers;
 pub mod cpu;
+pub mod debugger;
 pub mod graphics;
 pub mod inputs;
 pub mod interruptor;
 pub mod memory;
+pub mod movie;
 pub mod ports;
+pub mod rewind;
 pub mod sega;
+pub mod snapshot;
+pub mod video_capture;
+pub mod watch;
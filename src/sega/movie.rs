@@ -0,0 +1,460 @@
+use super::inputs;
+use std::fs;
+
+// Input movies, the console input (joystick ports and pause) recorded for
+// each frame.  Replaying the movie from the same starting point (anchor) with
+// the same ROM reproduces the session, as stepping the core (not in
+// realtime) is deterministic.  The paddle and sports pad positions aren't
+// recorded, so movies can't be used with them.
+//
+// File layout (little endian):
+//    "RSMV", version (u16), anchor (u8), ROM CRC-32 (u32), frame count (u32),
+//    then per frame: port 0xDC value, port 0xDD value, flags.
+pub struct Constants {}
+
+impl Constants {
+    pub const MAGIC: &'static [u8; 4] = b"RSMV";
+    pub const VERSION: u16 = 1;
+    pub const HEADER_SIZE: usize = 15;
+    pub const FRAME_SIZE: usize = 3;
+
+    pub const FLAG_PAUSE: u8 = 0x01;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Anchor {
+    PowerOn, // Recording started with the console powered on (reset).
+}
+
+impl Anchor {
+    fn to_u8(self) -> u8 {
+        match self {
+            Anchor::PowerOn => 0,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Anchor> {
+        match value {
+            0 => Some(Anchor::PowerOn),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MovieFrame {
+    pub port1_value: u8,
+    pub port2_value: u8,
+    pub pause: bool,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct Movie {
+    pub rom_crc32: u32,
+    pub anchor: Anchor,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    pub fn new(rom_crc32: u32, anchor: Anchor) -> Self {
+        Self {
+            rom_crc32,
+            anchor,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data =
+            Vec::with_capacity(Constants::HEADER_SIZE + self.frames.len() * Constants::FRAME_SIZE);
+        data.extend_from_slice(Constants::MAGIC);
+        data.extend_from_slice(&Constants::VERSION.to_le_bytes());
+        data.push(self.anchor.to_u8());
+        data.extend_from_slice(&self.rom_crc32.to_le_bytes());
+        data.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            data.push(frame.port1_value);
+            data.push(frame.port2_value);
+            data.push(if frame.pause {
+                Constants::FLAG_PAUSE
+            } else {
+                0
+            });
+        }
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        if data.len() < Constants::HEADER_SIZE || &data[0..4] != Constants::MAGIC {
+            return Err("Not a movie file.".to_string());
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != Constants::VERSION {
+            return Err(format!("Unsupported movie version {}.", version));
+        }
+        let anchor =
+            Anchor::from_u8(data[6]).ok_or(format!("Unsupported movie anchor {}.", data[6]))?;
+        let rom_crc32 = u32::from_le_bytes(data[7..11].try_into().unwrap());
+        let frame_count = u32::from_le_bytes(data[11..15].try_into().unwrap()) as usize;
+
+        let frame_data = &data[Constants::HEADER_SIZE..];
+        if frame_data.len() != frame_count * Constants::FRAME_SIZE {
+            return Err("Movie file is truncated.".to_string());
+        }
+        let frames = frame_data
+            .chunks(Constants::FRAME_SIZE)
+            .map(|frame| MovieFrame {
+                port1_value: frame[0],
+                port2_value: frame[1],
+                pause: frame[2] & Constants::FLAG_PAUSE != 0,
+            })
+            .collect();
+
+        Ok(Self {
+            rom_crc32,
+            anchor,
+            frames,
+        })
+    }
+
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        fs::write(filename, self.to_bytes())
+    }
+
+    pub fn load(filename: &str) -> Result<Self, String> {
+        let data = fs::read(filename).map_err(|e| format!("Unable to read {}. {}", filename, e))?;
+        Movie::from_bytes(&data)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+}
+
+// Records or plays back a movie, 'update' is called before each core step.
+// Either way the console only sees new input at the start of a frame: while
+// recording, the live input goes to 'live_input' and is copied over (and
+// recorded) at the frame boundary, the same point that playback applies it.
+pub struct MovieSession {
+    movie: Movie,
+    mode: MovieMode,
+    last_frame_count: Option<u64>,
+    position: usize,
+    live_input: inputs::Joystick,
+}
+
+impl MovieSession {
+    // 'joystick' is the console's joystick, for its settings (turbo rate and
+    // opposing directions).
+    pub fn record(movie: Movie, joystick: &inputs::Joystick) -> Self {
+        Self {
+            movie,
+            mode: MovieMode::Recording,
+            last_frame_count: None,
+            position: 0,
+            live_input: *joystick,
+        }
+    }
+
+    pub fn play(movie: Movie) -> Self {
+        Self {
+            movie,
+            mode: MovieMode::Playing,
+            last_frame_count: None,
+            position: 0,
+            live_input: inputs::Joystick::new(),
+        }
+    }
+
+    // Where the live (keyboard, controller) input goes, 'None' while playing
+    // back, as the movie replaces it.
+    pub fn live_input(&mut self) -> Option<&mut inputs::Joystick> {
+        match self.mode {
+            MovieMode::Recording => Some(&mut self.live_input),
+            MovieMode::Playing => None,
+        }
+    }
+
+    pub fn mode(&self) -> MovieMode {
+        self.mode
+    }
+
+    pub fn into_movie(self) -> Movie {
+        self.movie
+    }
+
+    // At the start of each frame, record the input or replace it with the
+    // recorded input.  Returns 'false' once playback has run out of frames.
+    pub fn update(&mut self, frame_count: u64, joystick: &mut inputs::Joystick) -> bool {
+        if self.last_frame_count == Some(frame_count) {
+            return true;
+        }
+        self.last_frame_count = Some(frame_count);
+
+        match self.mode {
+            MovieMode::Recording => {
+                // A pause press is latched until it's recorded.
+                self.live_input.update_turbo(frame_count);
+                let frame = MovieFrame {
+                    port1_value: self.live_input.read_port1(),
+                    port2_value: self.live_input.read_port2(),
+                    pause: self.live_input.take_pause_request(),
+                };
+                joystick.set_input_state(frame.port1_value, frame.port2_value, frame.pause);
+                self.movie.frames.push(frame);
+                true
+            }
+            MovieMode::Playing => match self.movie.frames.get(self.position) {
+                Some(frame) => {
+                    joystick.set_input_state(frame.port1_value, frame.port2_value, frame.pause);
+                    self.position += 1;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::clocks;
+    use crate::sega::cpu::core;
+    use crate::sega::cpu::pc_state;
+    use crate::sega::graphics::vdp;
+    use crate::sega::inputs;
+    use crate::sega::interruptor;
+    use crate::sega::memory::flat;
+    use crate::sega::memory::memory::MemoryRW;
+    use crate::sega::movie;
+    use crate::sega::ports;
+    use crate::sega::snapshot;
+    use crate::sega::snapshot::Snapshot;
+
+    // Copies joystick 1 to 0xC000 in a loop, counting loops (0xC001),
+    // v-sync interrupts (0xC002), pauses (0xC003) and loops with fire A held
+    // (0xC004).
+    const PROGRAM: [(u16, &[u8]); 3] = [
+        (
+            0x0000,
+            &[
+                0xF3, // DI
+                0x31, 0x00, 0xE0, // LD SP, 0xE000
+                0xED, 0x56, // IM 1
+                0xFB, // EI
+                0xDB, 0xDC, // IN A, (0xDC)
+                0x32, 0x00, 0xC0, // LD (0xC000), A
+                0x21, 0x01, 0xC0, // LD HL, 0xC001
+                0x34, // INC (HL)
+                0xCB, 0x67, // BIT 4, A
+                0x20, 0x04, // JR NZ, 0x0018
+                0x21, 0x04, 0xC0, // LD HL, 0xC004
+                0x34, // INC (HL)
+                0xC3, 0x07, 0x00, // JP 0x0007
+            ],
+        ),
+        (
+            0x0038,
+            &[
+                0xF5, // PUSH AF
+                0xE5, // PUSH HL
+                0x21, 0x02, 0xC0, // LD HL, 0xC002
+                0x34, // INC (HL)
+                0xDB, 0xBF, // IN A, (0xBF)
+                0xE1, // POP HL
+                0xF1, // POP AF
+                0xFB, // EI
+                0xED, 0x4D, // RETI
+            ],
+        ),
+        (
+            0x0066,
+            &[
+                0xE5, // PUSH HL
+                0x21, 0x03, 0xC0, // LD HL, 0xC003
+                0x34, // INC (HL)
+                0xE1, // POP HL
+                0xED, 0x45, // RETN
+            ],
+        ),
+    ];
+    const FRAMES: u64 = 8;
+    const MID_FRAME_STEP: u32 = 1000;
+
+    fn build_core() -> core::Core<flat::FlatMemory> {
+        let mut memory = flat::FlatMemory::new();
+        for (address, code) in PROGRAM {
+            memory.load(address, code);
+        }
+        let mut ports = ports::Ports::new();
+        ports.add_device(Box::new(vdp::Vdp::new()));
+        core::Core::new(
+            clocks::Clock::new(),
+            memory,
+            pc_state::PcState::new(),
+            ports,
+            interruptor::Interruptor::new(),
+        )
+    }
+
+    // Run for 'FRAMES' frames, calling 'live_input' before each step with the
+    // frame and the step within it (while recording).
+    fn run<F>(session: &mut movie::MovieSession, mut live_input: F) -> (Vec<u8>, u64)
+    where
+        F: FnMut(u64, u32, &mut inputs::Joystick),
+    {
+        let mut core = build_core();
+        let mut last_frame = None;
+        let mut step = 0;
+        while core.frame_count() < FRAMES {
+            let frame = core.frame_count();
+            if last_frame != Some(frame) {
+                last_frame = Some(frame);
+                step = 0;
+            }
+            if let Some(joystick) = session.live_input() {
+                live_input(frame, step, joystick);
+            }
+            assert!(session.update(frame, &mut core.ports.joysticks));
+            core.step(false, false).unwrap();
+            step += 1;
+        }
+        let state = (0xC000..0xC005)
+            .map(|address| core.memory.read(address))
+            .collect();
+        (state, core.clock.cycles)
+    }
+
+    fn scripted_input(frame: u64, step: u32, joystick: &mut inputs::Joystick) {
+        match (frame, step) {
+            (1, 0) => joystick.j1_up(false),
+            (2, 0) => joystick.j1_fire_a(false),
+            (3, 0) => joystick.pause(false),
+            (5, 0) => joystick.j1_up(true),
+            _ => {}
+        }
+    }
+
+    fn mid_frame_input(frame: u64, step: u32, joystick: &mut inputs::Joystick) {
+        match (frame, step) {
+            (1, MID_FRAME_STEP) => joystick.j1_fire_a(false),
+            (3, MID_FRAME_STEP) => joystick.pause(false),
+            (5, MID_FRAME_STEP) => joystick.j1_fire_a(true),
+            _ => {}
+        }
+    }
+
+    fn new_recording() -> movie::MovieSession {
+        movie::MovieSession::record(
+            movie::Movie::new(0x1234, movie::Anchor::PowerOn),
+            &inputs::Joystick::new(),
+        )
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let mut recording = new_recording();
+        let recorded_state = run(&mut recording, scripted_input);
+        assert_eq!(recording.mode(), movie::MovieMode::Recording);
+
+        // Round trip through the file format.
+        let recorded_movie = recording.into_movie();
+        assert_eq!(recorded_movie.frames.len(), FRAMES as usize);
+        let movie = movie::Movie::from_bytes(&recorded_movie.to_bytes()).unwrap();
+        assert_eq!(movie, recorded_movie);
+
+        // Playback ignores the live input, and reproduces the session.
+        let mut playback = movie::MovieSession::play(movie);
+        let replayed_state = run(&mut playback, scripted_input);
+        assert_eq!(replayed_state, recorded_state);
+
+        // Last input was fire A held, the pause was taken once, and interrupts ran.
+        assert_eq!(replayed_state.0[0], 0xEF);
+        assert_eq!(replayed_state.0[3], 1);
+        assert!(replayed_state.0[2] > 0);
+
+        // Out of frames.
+        let mut joystick = inputs::Joystick::new();
+        assert!(!playback.update(FRAMES + 1, &mut joystick));
+    }
+
+    #[test]
+    fn test_mid_frame_input() {
+        let mut recording = new_recording();
+        let recorded_state = run(&mut recording, mid_frame_input);
+        let movie = recording.into_movie();
+
+        // Input during a frame is seen (and recorded) from the start of the next.
+        assert_eq!(movie.frames[1].port1_value, 0xFF);
+        assert_eq!(movie.frames[2].port1_value, 0xEF);
+        assert_eq!(movie.frames[6].port1_value, 0xFF);
+        assert_eq!(
+            movie
+                .frames
+                .iter()
+                .map(|frame| frame.pause)
+                .collect::<Vec<_>>(),
+            vec![false, false, false, false, true, false, false, false]
+        );
+
+        let mut playback = movie::MovieSession::play(movie);
+        let replayed_state = run(&mut playback, mid_frame_input);
+        assert_eq!(replayed_state, recorded_state);
+        assert_eq!(replayed_state.0[3], 1);
+        assert!(replayed_state.0[4] > 0);
+    }
+
+    #[test]
+    fn test_deterministic_stepping() {
+        let mut first = new_recording();
+        let mut second = new_recording();
+        assert_eq!(
+            run(&mut first, scripted_input),
+            run(&mut second, scripted_input)
+        );
+        assert_eq!(first.into_movie(), second.into_movie());
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        // Run to a frame, snapshot, continue, then restore and continue again.
+        let mut core = build_core();
+        core.ports.joysticks.j1_fire_a(false);
+        while core.frame_count() < 3 {
+            core.step(false, false).unwrap();
+        }
+        let mut writer = snapshot::StateWriter::new();
+        core.save_state(&mut writer);
+        let state = writer.into_bytes();
+
+        let run_on = |core: &mut core::Core<flat::FlatMemory>| {
+            while core.frame_count() < FRAMES {
+                core.step(false, false).unwrap();
+            }
+            let memory: Vec<u8> = (0xC000..0xC004)
+                .map(|address| core.memory.read(address))
+                .collect();
+            (memory, core.clock.cycles)
+        };
+        let expected = run_on(&mut core);
+
+        let mut reader = snapshot::StateReader::new(&state);
+        assert!(core.load_state(&mut reader).is_ok());
+        assert!(reader.is_finished());
+        assert_eq!(core.frame_count(), 3);
+        assert_eq!(run_on(&mut core), expected);
+    }
+
+    #[test]
+    fn test_invalid_movie() {
+        assert!(movie::Movie::from_bytes(b"RSMV").is_err());
+        let mut data = movie::Movie::new(0, movie::Anchor::PowerOn).to_bytes();
+        data[11] = 1; // One frame, but no frame data.
+        assert!(movie::Movie::from_bytes(&data).is_err());
+        data[11] = 0;
+        data[6] = 9; // Unknown anchor.
+        assert!(movie::Movie::from_bytes(&data).is_err());
+    }
+}
@@ -1,6 +1,9 @@
 use super::audio::sound;
 use super::clocks;
+use super::graphics::display;
 use super::inputs;
+use super::snapshot;
+use super::watch;
 
 struct NullPort {}
 
@@ -20,6 +23,22 @@ pub trait Device {
     fn port_write(&mut self, clock: &clocks::Clock, port_address: u8, value: u8);
     fn port_read(&mut self, clock: &clocks::Clock, port_address: u8) -> Option<u8>;
     fn export(&mut self, raw_display: &mut Vec<u8>) -> bool;
+
+    // Number of frames (v-syncs) generated, for devices that drive the display.
+    fn frame_count(&self) -> u64 {
+        0
+    }
+
+    // Colour shown around the display (in the overscan area), for display devices.
+    fn border_colour(&self) -> Option<display::Colour> {
+        None
+    }
+
+    // Device state for snapshots (see 'snapshot::Snapshot'), for devices with state.
+    fn save_state(&self, _writer: &mut snapshot::StateWriter) {}
+    fn load_state(&mut self, _reader: &mut snapshot::StateReader) -> Result<(), String> {
+        Ok(())
+    }
 }
 
 impl Port for NullPort {
@@ -37,6 +56,8 @@ pub struct Ports {
     devices: Vec<Box<dyn Device>>,
     pub joysticks: inputs::Joystick,
     pub audio: sound::Sound,
+    pub watches: watch::WatchList, // Port watches (for the debugger).
+    watch_hit: Option<watch::WatchHit>,
 }
 
 impl Ports {
@@ -52,6 +73,8 @@ impl Ports {
             devices: Vec::new(),
             joysticks: inputs::Joystick::new(),
             audio: sound::Sound::new(),
+            watches: watch::WatchList::new(watch::Target::Port),
+            watch_hit: None,
         }
     }
 
@@ -64,6 +87,14 @@ impl Ports {
     }
 
     pub fn port_read(&mut self, clock: &clocks::Clock, port_address: u8) -> u8 {
+        let value = self.device_read(clock, port_address);
+        if !self.watches.is_empty() {
+            self.check_watch(watch::Access::Read, port_address, value);
+        }
+        value
+    }
+
+    fn device_read(&mut self, clock: &clocks::Clock, port_address: u8) -> u8 {
         for i in 0..self.devices.len() {
             if let Some(value) = self.devices[i].port_read(clock, port_address) {
                 return value;
@@ -78,17 +109,36 @@ impl Ports {
     }
 
     pub fn port_write(&mut self, clock: &clocks::Clock, port_address: u8, value: u8) {
+        if !self.watches.is_empty() {
+            self.check_watch(watch::Access::Write, port_address, value);
+        }
         for i in 0..self.devices.len() {
             // TODO: Replace with something useful.
             self.devices[i].port_write(clock, port_address, value);
         }
 
+        if port_address & 0xC1 == 0x01 {
+            // 3F (I/O control) plus all of the mirror ports.
+            self.joysticks.write_io_control(value);
+        }
+
         if port_address & 0xC0 == 0x40 {
             // 7E + 7F plus all of the pirror ports.
-            self.audio.write_port(value);
+            self.audio.write_port(clock, value);
         }
     }
 
+    fn check_watch(&mut self, access: watch::Access, port_address: u8, value: u8) {
+        if self.watch_hit.is_none() {
+            self.watch_hit = self.watches.check(access, port_address as u16, value);
+        }
+    }
+
+    // Watch triggered since the last call.
+    pub fn take_watch_hit(&mut self) -> Option<watch::WatchHit> {
+        self.watch_hit.take()
+    }
+
     pub fn export(&mut self, raw_display: &mut Vec<u8>) -> bool {
         let mut result = false;
         for i in 0..self.devices.len() {
@@ -97,6 +147,20 @@ impl Ports {
         result
     }
 
+    pub fn border_colour(&self) -> Option<display::Colour> {
+        self.devices
+            .iter()
+            .find_map(|device| device.border_colour())
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.devices
+            .iter()
+            .map(|device| device.frame_count())
+            .max()
+            .unwrap_or(0)
+    }
+
     pub fn poll_interrupts(&mut self, raw_display: &mut Vec<u8>, clock: &clocks::Clock) -> bool {
         let mut interrupt = false;
         for i in 0..self.devices.len() {
@@ -106,3 +170,20 @@ impl Ports {
         interrupt
     }
 }
+
+// The joysticks are live input, so they aren't part of the saved state.
+impl snapshot::Snapshot for Ports {
+    fn save_state(&self, writer: &mut snapshot::StateWriter) {
+        for device in &self.devices {
+            device.save_state(writer);
+        }
+        self.audio.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut snapshot::StateReader) -> Result<(), String> {
+        for device in self.devices.iter_mut() {
+            device.load_state(reader)?;
+        }
+        self.audio.load_state(reader)
+    }
+}
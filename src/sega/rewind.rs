@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+
+// Ring buffer of machine snapshots for rewinding.
+// Only the newest snapshot is held in full, older snapshots are held as the
+// difference (XOR) to the snapshot that follows them, run length encoded.
+// Most of RAM/VRAM doesn't change between snapshots, so the differences are
+// mostly zeros and cheap to hold.  The oldest snapshots are dropped once the
+// buffer exceeds its size limit.
+pub struct RewindBuffer {
+    interval: u64, // Frames between snapshots.
+    max_bytes: usize,
+
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>, // Oldest first, each is 'previous ^ next'.
+    delta_bytes: usize,
+    last_frame: Option<u64>,
+}
+
+impl RewindBuffer {
+    pub const DEFAULT_INTERVAL: u64 = 4;
+    pub const DEFAULT_SIZE_MB: usize = 32;
+
+    pub fn new(interval: u64, max_bytes: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            max_bytes,
+            newest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+            last_frame: None,
+        }
+    }
+
+    pub fn get_interval(&self) -> u64 {
+        self.interval
+    }
+
+    pub fn get_max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    // True if a snapshot is due for 'frame_count' (once per 'interval' frames).
+    pub fn is_snapshot_due(&self, frame_count: u64) -> bool {
+        frame_count.is_multiple_of(self.interval) && self.last_frame != Some(frame_count)
+    }
+
+    pub fn push(&mut self, frame_count: u64, snapshot: Vec<u8>) {
+        self.last_frame = Some(frame_count);
+        if let Some(previous) = self.newest.take() {
+            let delta = RewindBuffer::compress(&RewindBuffer::xor(&previous, &snapshot));
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.newest = Some(snapshot);
+
+        while self.size() > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(delta) => self.delta_bytes -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    // Remove and return the newest snapshot, the one before it becomes the newest.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.delta_bytes -= delta.len();
+            match RewindBuffer::decompress(&delta) {
+                Ok(delta) => self.newest = Some(RewindBuffer::xor(&newest, &delta)),
+                Err(e) => {
+                    // The older deltas are relative to the lost snapshot.
+                    println!("Rewind buffer cleared. {}", e);
+                    self.clear();
+                }
+            }
+        }
+        // Take another snapshot when next due, even if it's the same frame.
+        self.last_frame = None;
+        Some(newest)
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    // Approximate memory used by the snapshots.
+    pub fn size(&self) -> usize {
+        self.delta_bytes + self.newest.as_ref().map_or(0, |newest| newest.len())
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+        self.last_frame = None;
+    }
+
+    // The delta starts with the length of 'a', so 'b ^ delta' gives 'a' back
+    // (snapshots are normally the same size, but don't rely on it).
+    fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+        let length = a.len().max(b.len());
+        let byte = |data: &[u8], index: usize| data.get(index).copied().unwrap_or(0);
+        let mut result: Vec<u8> = (0..length).map(|i| byte(a, i) ^ byte(b, i)).collect();
+        result.truncate(a.len());
+        result
+    }
+
+    fn write_varint(output: &mut Vec<u8>, mut value: usize) {
+        while value >= 0x80 {
+            output.push((value as u8 & 0x7F) | 0x80);
+            value >>= 7;
+        }
+        output.push(value as u8);
+    }
+
+    fn read_varint(input: &[u8], position: &mut usize) -> Result<usize, String> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = *input
+                .get(*position)
+                .ok_or("Rewind delta is truncated.".to_string())?;
+            *position += 1;
+            value |= ((byte & 0x7F) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    // Encoded as (zero run length, literal length, literal bytes) repeated.
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        RewindBuffer::write_varint(&mut output, data.len());
+        let mut position = 0;
+        while position < data.len() {
+            let zeros = data[position..]
+                .iter()
+                .take_while(|byte| **byte == 0)
+                .count();
+            position += zeros;
+            let literals = data[position..]
+                .iter()
+                .take_while(|byte| **byte != 0)
+                .count();
+            RewindBuffer::write_varint(&mut output, zeros);
+            RewindBuffer::write_varint(&mut output, literals);
+            output.extend_from_slice(&data[position..position + literals]);
+            position += literals;
+        }
+        output
+    }
+
+    fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut position = 0;
+        let length = RewindBuffer::read_varint(data, &mut position)?;
+        let mut output = Vec::with_capacity(length);
+        while output.len() < length {
+            let zeros = RewindBuffer::read_varint(data, &mut position)?;
+            let literals = RewindBuffer::read_varint(data, &mut position)?;
+            output.resize(output.len() + zeros, 0);
+            let bytes = data
+                .get(position..position + literals)
+                .ok_or("Rewind delta is truncated.".to_string())?;
+            output.extend_from_slice(bytes);
+            position += literals;
+        }
+        if output.len() != length {
+            return Err("Rewind delta has the wrong length.".to_string());
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::rewind;
+
+    fn snapshot(frame: u8) -> Vec<u8> {
+        let mut data = vec![0x55; 0x6000];
+        data[0] = frame;
+        data[0x1000 + frame as usize] = 0xAA;
+        data
+    }
+
+    #[test]
+    fn test_compress() {
+        for data in [
+            vec![],
+            vec![0; 100],
+            vec![1, 2, 3],
+            vec![0, 0, 1, 0, 2, 2, 0],
+            (0..1000).map(|i| (i % 7) as u8).collect(),
+        ] {
+            let compressed = rewind::RewindBuffer::compress(&data);
+            assert_eq!(rewind::RewindBuffer::decompress(&compressed), Ok(data));
+        }
+        assert!(rewind::RewindBuffer::compress(&[0; 0x4000]).len() < 8);
+        assert!(rewind::RewindBuffer::decompress(&[10, 0, 5, 1]).is_err());
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut buffer = rewind::RewindBuffer::new(2, 1 << 20);
+        for frame in 0..10 {
+            if buffer.is_snapshot_due(frame) {
+                buffer.push(frame, snapshot(frame as u8));
+            }
+        }
+        assert!(!buffer.is_snapshot_due(8));
+        assert_eq!(buffer.len(), 5);
+
+        // The differences are small.
+        assert!(buffer.size() < 0x6000 + 4 * 64);
+
+        // Newest first.
+        for frame in [8, 6, 4, 2, 0] {
+            assert_eq!(buffer.pop(), Some(snapshot(frame)));
+        }
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_delta() {
+        let mut buffer = rewind::RewindBuffer::new(1, 1 << 20);
+        for frame in 0..4 {
+            buffer.push(frame, snapshot(frame as u8));
+        }
+        // Truncate the newest difference, the older ones can't be used either.
+        buffer.deltas.back_mut().unwrap().truncate(2);
+        assert_eq!(buffer.pop(), Some(snapshot(3)));
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.size(), 0);
+
+        buffer.push(10, snapshot(10));
+        assert_eq!(buffer.pop(), Some(snapshot(10)));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_size_limit() {
+        // Room for the full snapshot and a few differences.
+        let mut buffer = rewind::RewindBuffer::new(1, 0x6000 + 100);
+        for frame in 0..50 {
+            buffer.push(frame, snapshot(frame as u8));
+        }
+        assert!(buffer.size() <= 0x6000 + 100);
+        assert!(buffer.len() > 1 && buffer.len() < 50);
+
+        let kept = buffer.len() as u8;
+        let mut frame = 49;
+        while let Some(data) = buffer.pop() {
+            assert_eq!(data, snapshot(frame));
+            frame -= 1;
+        }
+        assert_eq!(frame, 49 - kept);
+    }
+}
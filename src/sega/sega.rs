@@ -4,20 +4,38 @@ use sdl2::render;
 use sdl2::video;
 
 use super::audio::sound;
+use super::audio::soundchannel;
+use super::audio::vgm;
+use super::audio::wav;
+use super::checksum;
 use super::clocks;
+use super::config;
+use super::controllers;
 use super::cpu;
+use super::debugger;
 use super::graphics;
 use super::inputs;
 use super::interruptor;
 use super::memory;
+use super::movie;
 use super::ports;
+use super::rewind;
+use super::snapshot;
+use super::snapshot::Snapshot;
+use super::video_capture;
+
+// The console memory, with the debugger's watches.
+type SegaMemory = memory::watched::WatchedMemory<memory::memory::MemoryAbsolute>;
 
 pub struct Sega {
-    core: cpu::core::Core<memory::memory::MemoryAbsolute>,
+    core: cpu::core::Core<SegaMemory>,
+    cartridge_name: String,
     debug: bool,
     realtime: bool,
     stop_clock: clocks::ClockType,
     fullscreen: bool,
+    input: inputs::Input,
+    controllers: controllers::Controllers,
 
     pub powered: bool,
 
@@ -25,13 +43,45 @@ pub struct Sega {
     sdl_context: Option<sdl2::Sdl>,
     canvas: Option<render::Canvas<video::Window>>,
     audio_queue: Option<Box<sound::SoundQueueType>>,
+
+    audio_recorder: Option<wav::WavFileWriter>,
+    record_audio_filename: String,
+
+    movie: Option<movie::MovieSession>,
+    movie_filename: String,
+
+    // Runtime speed controls (the speed only applies when running in realtime).
+    emulation_paused: bool,
+    fast_forward: bool,
+    fast_forward_speed: f64,
+    slow_motion_speed: f64,
+
+    rewind: Option<rewind::RewindBuffer>, // 'None' if disabled.
+    rewinding: bool,
+
+    screenshot_scale: u32, // Scale of the extra screenshot copy (0 for none).
+    screenshot_at_frame: Option<u64>,
+
+    video_recorder: Option<video_capture::VideoRecorder>,
+    record_video_filename: String,
+
+    display_filter: graphics::filters::DisplayFilter,
+    window_scale: u16,
+
+    debugger: debugger::Debugger,
+    on_cpu_error: debugger::CpuErrorAction,
 }
 
 impl Sega {
     const DISPLAY_UPDATES_PER_KEY_EVENT: u32 = 1; // Number of display updates per key press event. (reduces texture creation overhead).
     const CPU_STEPS_PER_AUDIO_UPDATE: u32 = 50; // Number of times to step the CPU before updating the audio.
+    const PAUSED_SLEEP_MS: u64 = 10; // Time to wait between checking for events while paused.
+    const SLOW_MOTION_SPEEDS: [f64; 3] = [1.0, 0.5, 0.25];
+    pub const DEFAULT_FAST_FORWARD_SPEED: f64 = 4.0;
+    const REWIND_FRAME_MS: u64 = 17; // Time each snapshot is shown for while rewinding.
+    const DEFAULT_WINDOW_SCALE: u16 = 3;
 
-    pub fn build_sega(cartridge_name: &str) -> cpu::core::Core<memory::memory::MemoryAbsolute> {
+    pub fn build_sega(cartridge_name: &str) -> cpu::core::Core<SegaMemory> {
         let clock = clocks::Clock::new();
         let mut memory = memory::memory::MemoryAbsolute::new();
         let pc_state = cpu::pc_state::PcState::new();
@@ -45,7 +95,13 @@ impl Sega {
 
         memory.reset(cartridge_name);
 
-        cpu::core::Core::new(clock, memory, pc_state, ports, interruptor)
+        cpu::core::Core::new(
+            clock,
+            memory::watched::WatchedMemory::new(memory),
+            pc_state,
+            ports,
+            interruptor,
+        )
     }
 
     pub fn get_console_size() -> graphics::display::ConsoleSize {
@@ -56,8 +112,12 @@ impl Sega {
     }
 
     pub fn reset(&mut self, cartridge_name: &str) {
-        self.core.memory.reset(cartridge_name);
+        self.cartridge_name = cartridge_name.to_string();
+        self.core.memory.inner.reset(cartridge_name);
         self.core.reset();
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
     }
 
     pub fn run_sega(me: &mut Sega) -> bool {
@@ -74,9 +134,31 @@ impl Sega {
         for event in event_pump.poll_iter() {
             graphics::display::SDLUtility::handle_events(&event);
 
-            if !inputs::Input::handle_events(event, &mut me.core.ports.joysticks) {
-                return false;
+            // With a movie, the console input is only changed at the start of
+            // a frame (see 'MovieSession'), the live input is ignored while
+            // playing back.
+            let mut ignored_input = inputs::Joystick::new();
+            let joystick = match me.movie.as_mut() {
+                Some(session) => session.live_input().unwrap_or(&mut ignored_input),
+                None => &mut me.core.ports.joysticks,
             };
+            me.controllers.handle_events(&event, joystick);
+
+            if let Some((action, pressed)) = me.input.handle_events(&event, joystick) {
+                if !me.handle_action(action, pressed) {
+                    return false;
+                }
+            }
+        }
+
+        if me.rewinding {
+            me.rewind_step();
+            return true;
+        }
+
+        if me.emulation_paused {
+            std::thread::sleep(std::time::Duration::from_millis(Sega::PAUSED_SLEEP_MS));
+            return true;
         }
 
         // First loop, draw FRAMES_PER_KEY_EVENT frames at a time.
@@ -87,20 +169,29 @@ impl Sega {
         ) {
             return false;
         }
+
+        if let Some(frame) = me.screenshot_at_frame {
+            if me.core.frame_count() >= frame {
+                me.screenshot_at_frame = None;
+                me.save_screenshot();
+            }
+        }
         true
     }
 
     pub fn power_sega(&mut self) {
-        let mut frame_width = graphics::vdp::Constants::SMS_WIDTH;
+        let (display_width, display_height) = self.get_display_size();
+        let mut frame_width = display_width as u16;
+        let mut frame_height = display_height as u16;
         // If not in full screen, default to using a bigger window.
         if !self.fullscreen {
-            frame_width *= 3;
+            frame_width *= self.window_scale;
+            frame_height *= self.window_scale;
         }
-        let frame_height = ((frame_width as u32) * (graphics::vdp::Constants::SMS_HEIGHT as u32)
-            / (graphics::vdp::Constants::SMS_WIDTH as u32)) as u16;
 
         println!("powering on Sega Emulator.");
-        inputs::Input::print_keys();
+        self.input.print_keys();
+        self.controllers.print_buttons();
 
         let console_size = Self::get_console_size();
         let window_size = graphics::display::WindowSize::new(
@@ -124,14 +215,1009 @@ impl Sega {
         let core = Self::build_sega(cartridge_name);
         Self {
             core,
+            cartridge_name: cartridge_name.to_string(),
             debug,
             realtime,
             stop_clock,
             fullscreen,
+            input: inputs::Input::new(),
+            controllers: controllers::Controllers::new(),
             powered: false,
             sdl_context: None,
             canvas: None,
             audio_queue: None,
+            audio_recorder: None,
+            record_audio_filename: String::new(),
+            movie: None,
+            movie_filename: String::new(),
+            emulation_paused: false,
+            fast_forward: false,
+            fast_forward_speed: Sega::DEFAULT_FAST_FORWARD_SPEED,
+            slow_motion_speed: 1.0,
+            rewind: Some(rewind::RewindBuffer::new(
+                rewind::RewindBuffer::DEFAULT_INTERVAL,
+                rewind::RewindBuffer::DEFAULT_SIZE_MB << 20,
+            )),
+            rewinding: false,
+            screenshot_scale: 0,
+            screenshot_at_frame: None,
+            video_recorder: None,
+            record_video_filename: String::new(),
+            display_filter: graphics::filters::DisplayFilter::new(),
+            window_scale: Sega::DEFAULT_WINDOW_SCALE,
+            debugger: debugger::Debugger::new(),
+            on_cpu_error: debugger::CpuErrorAction::Ask,
+        }
+    }
+
+    // Load the key bindings from the config file ('None' for the default file, if present).
+    pub fn load_config(&mut self, config_filename: Option<&str>) {
+        let config = match config_filename {
+            Some(filename) => config::Config::load(std::path::Path::new(filename)),
+            None => config::Config::load_default(),
+        };
+        match config {
+            Ok(config) => {
+                self.input.load_config(&config);
+                self.controllers.load_config(&config);
+                if let Some(name) = config.get("input", "opposing_directions") {
+                    self.set_opposing_directions(name);
+                }
+                if let Some(speed) = config.get("emulation", "fast_forward_speed") {
+                    match speed.parse() {
+                        Ok(speed) => self.set_fast_forward_speed(speed),
+                        Err(_) => println!("Invalid fast forward speed in config: {}", speed),
+                    }
+                }
+                if let Some(name) = config.get("display", "filter") {
+                    self.set_filter(name);
+                }
+                if let Some(percent) = config.get("display", "scanlines") {
+                    match percent.parse() {
+                        Ok(percent) => self.set_scanlines(percent),
+                        Err(_) => println!("Invalid scanlines percentage in config: {}", percent),
+                    }
+                }
+                if let Some(name) = config.get("display", "ntsc") {
+                    self.set_ntsc_mode(name);
+                }
+                if let Some(name) = config.get("display", "aspect_ratio") {
+                    self.set_aspect_ratio(name);
+                }
+                if let Some(overscan) = config.get("display", "overscan") {
+                    match overscan.parse() {
+                        Ok(overscan) => self.set_overscan(overscan),
+                        Err(_) => println!("Invalid overscan setting in config: {}", overscan),
+                    }
+                }
+                if let Some(integer_scale) = config.get("display", "integer_scale") {
+                    match integer_scale.parse() {
+                        Ok(integer_scale) => self.set_integer_scale(integer_scale),
+                        Err(_) => println!("Invalid integer scale in config: {}", integer_scale),
+                    }
+                }
+                if let Some(scale) = config.get("display", "window_scale") {
+                    match scale.parse() {
+                        Ok(scale) => self.set_window_scale(scale),
+                        Err(_) => println!("Invalid window scale in config: {}", scale),
+                    }
+                }
+                if let Some(scale) = config.get("screenshot", "scale") {
+                    match scale.parse() {
+                        Ok(scale) => self.set_screenshot_scale(scale),
+                        Err(_) => println!("Invalid screenshot scale in config: {}", scale),
+                    }
+                }
+                let interval = config.get("rewind", "interval");
+                let buffer_mb = config.get("rewind", "buffer_mb");
+                if interval.is_some() || buffer_mb.is_some() {
+                    match (
+                        interval.map_or(Ok(rewind::RewindBuffer::DEFAULT_INTERVAL), str::parse),
+                        buffer_mb.map_or(Ok(rewind::RewindBuffer::DEFAULT_SIZE_MB), str::parse),
+                    ) {
+                        (Ok(interval), Ok(buffer_mb)) => self.set_rewind(interval, buffer_mb),
+                        _ => println!("Invalid rewind settings in config."),
+                    }
+                }
+                if let Some(name) = config.get("debugger", "on_cpu_error") {
+                    self.set_on_cpu_error(name);
+                }
+                if let Some(rate) = config.get("input", "turbo_rate") {
+                    match rate.parse() {
+                        Ok(rate) => self.set_turbo_rate(rate),
+                        Err(_) => println!("Invalid turbo rate in config: {}", rate),
+                    }
+                }
+            }
+            Err(e) => println!("Error while loading config, using defaults. {}", e),
+        }
+    }
+
+    // How opposing directions are handled: "allow", "last" (pressed) or "neutral".
+    pub fn set_opposing_directions(&mut self, name: &str) {
+        match inputs::OpposingDirections::from_name(name) {
+            Some(opposing_directions) => self
+                .core
+                .ports
+                .joysticks
+                .set_opposing_directions(opposing_directions),
+            None => println!(
+                "Unknown opposing directions option '{}', expected allow, last or neutral.",
+                name
+            ),
+        }
+    }
+
+    // Number of frames the turbo buttons are pressed (then released) for.
+    pub fn set_turbo_rate(&mut self, frames: u64) {
+        self.core.ports.joysticks.set_turbo_rate(frames);
+    }
+
+    // Peripheral on controller port 0 (A) or 1 (B): "joypad", "paddle" or "sportspad".
+    pub fn set_peripheral(&mut self, port: usize, name: &str) {
+        match inputs::PeripheralType::from_name(name) {
+            Some(peripheral_type) => self
+                .core
+                .ports
+                .joysticks
+                .set_peripheral(port, peripheral_type),
+            None => println!(
+                "Unknown peripheral '{}', expected joypad, paddle or sportspad.",
+                name
+            ),
+        }
+    }
+
+    // Filter applied to the display: nearest (1x), nearest2x-4x, scale2x,
+    // scale3x or hq2x.
+    pub fn set_filter(&mut self, name: &str) {
+        match graphics::filters::Filter::from_name(name) {
+            Some(filter) => self.display_filter.filter = filter,
+            None => println!(
+                "Unknown filter '{}', expected nearest, nearest2x, nearest3x, nearest4x, scale2x, scale3x or hq2x.",
+                name
+            ),
+        }
+    }
+
+    // Percentage to darken alternate lines by (0 for no scanlines).
+    pub fn set_scanlines(&mut self, percent: u8) {
+        self.display_filter.scanlines = percent.min(100);
+    }
+
+    // Pixel aspect ratio: square, 4:3 (whole display) or 8:7.
+    pub fn set_aspect_ratio(&mut self, name: &str) {
+        match graphics::filters::AspectRatio::from_name(name) {
+            Some(aspect_ratio) => self.display_filter.aspect_ratio = aspect_ratio,
+            None => println!(
+                "Unknown aspect ratio '{}', expected square, 4:3 or 8:7.",
+                name
+            ),
+        }
+    }
+
+    // NTSC video signal: rgb (no filter), svideo, composite or rf.
+    pub fn set_ntsc_mode(&mut self, name: &str) {
+        match graphics::ntsc::NtscMode::from_name(name) {
+            Some(mode) => self.display_filter.ntsc.mode = mode,
+            None => println!(
+                "Unknown NTSC mode '{}', expected rgb, svideo, composite or rf.",
+                name
+            ),
+        }
+    }
+
+    // Show the overscan border (in the VDP's border colour) around the display.
+    pub fn set_overscan(&mut self, overscan: bool) {
+        self.display_filter.overscan = overscan;
+    }
+
+    // Only scale the display by whole numbers (the rest of the window is border).
+    pub fn set_integer_scale(&mut self, integer_scale: bool) {
+        self.display_filter.integer_scale = integer_scale;
+    }
+
+    // Initial window size, as a multiple of the display size.
+    pub fn set_window_scale(&mut self, scale: u16) {
+        self.window_scale = scale.max(1);
+    }
+
+    // Displayed size of the console (after the aspect ratio is applied).
+    fn get_display_size(&self) -> (u32, u32) {
+        self.display_filter.display_size(
+            graphics::vdp::Constants::SMS_WIDTH as u32,
+            graphics::vdp::Constants::SMS_HEIGHT as u32,
+        )
+    }
+
+    // Handle emulator hotkeys, return 'false' to quit.
+    fn handle_action(&mut self, action: inputs::Action, pressed: bool) -> bool {
+        // Only fast forward and rewind are held, everything else happens on the key press.
+        if action == inputs::Action::FastForward {
+            self.fast_forward = pressed;
+            self.update_speed();
+            return true;
+        }
+        if action == inputs::Action::Rewind {
+            self.set_rewinding(pressed);
+            return true;
+        }
+        if !pressed {
+            return true;
+        }
+        match action {
+            inputs::Action::Quit => return false,
+            inputs::Action::MuteChannel(channel) => {
+                self.core.ports.audio.toggle_channel_muted(channel);
+                self.core.ports.audio.print_channel_status();
+            }
+            inputs::Action::SoloChannel(channel) => {
+                self.core.ports.audio.toggle_channel_solo(channel);
+                self.core.ports.audio.print_channel_status();
+            }
+            inputs::Action::RecordAudio => self.toggle_audio_recording(),
+            inputs::Action::Screenshot => self.save_screenshot(),
+            inputs::Action::RecordVideo => self.toggle_video_recording(),
+            inputs::Action::Debug => {
+                // Stops in the debugger (on the terminal) before the next instruction.
+                self.debugger.request_break();
+                self.set_emulation_paused(false);
+            }
+            inputs::Action::SlowMotion => {
+                let next = Sega::SLOW_MOTION_SPEEDS
+                    .iter()
+                    .position(|speed| *speed == self.slow_motion_speed)
+                    .map_or(0, |index| (index + 1) % Sega::SLOW_MOTION_SPEEDS.len());
+                self.slow_motion_speed = Sega::SLOW_MOTION_SPEEDS[next];
+                println!("Speed: {}x", self.slow_motion_speed);
+                self.update_speed();
+            }
+            inputs::Action::PauseEmulation => {
+                self.set_emulation_paused(!self.emulation_paused);
+            }
+            inputs::Action::FrameAdvance => {
+                // The first press pauses, then each press runs one frame.
+                if self.emulation_paused {
+                    return self.advance_frame();
+                }
+                self.set_emulation_paused(true);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    // Log the instructions to 'filename', 'ring_size' keeps only the last
+    // instructions (written out on a panic).
+    pub fn start_trace(&mut self, filename: &str, ring_size: Option<usize>) {
+        match cpu::trace::TraceLogger::create(filename, ring_size) {
+            Ok(trace) => self.core.set_trace(Some(trace)),
+            Err(e) => println!("Error creating trace file {}. {}", filename, e),
+        }
+    }
+
+    pub fn stop_trace(&mut self) {
+        self.core.set_trace(None);
+    }
+
+    pub fn set_trace_fields(&mut self, names: &str) {
+        if let Some(trace) = self.core.get_trace_mut() {
+            match cpu::trace::TraceFields::from_names(names) {
+                Some(fields) => trace.set_fields(fields),
+                None => println!(
+                    "Unknown trace fields: {} (use {})",
+                    names,
+                    cpu::trace::TraceFields::NAMES
+                ),
+            }
+        }
+    }
+
+    pub fn set_trace_start_pc(&mut self, pc: u16) {
+        if let Some(trace) = self.core.get_trace_mut() {
+            trace.set_start_pc(pc);
+        }
+    }
+
+    pub fn set_trace_stop_pc(&mut self, pc: u16) {
+        if let Some(trace) = self.core.get_trace_mut() {
+            trace.set_stop_pc(pc);
+        }
+    }
+
+    // Only trace the frames "first-last".
+    pub fn set_trace_frames(&mut self, frames: &str) {
+        if let Some(trace) = self.core.get_trace_mut() {
+            match cpu::trace::TraceLogger::parse_frames(frames) {
+                Some((first, last)) => trace.set_frames(first, last),
+                None => println!("Invalid trace frames: {} (use first-last)", frames),
+            }
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.debugger.add_breakpoint(address);
+    }
+
+    // Stop in the debugger if a breakpoint (or step) is reached, returns 'false' to quit.
+    fn update_debugger(
+        debugger: &mut debugger::Debugger,
+        core: &mut cpu::core::Core<SegaMemory>,
+        audio_queue: &mut Option<Box<sound::SoundQueueType>>,
+    ) -> bool {
+        if !debugger.should_break(core) {
+            return true;
+        }
+        if let Some(audio_queue) = audio_queue.as_mut() {
+            audio_queue.clear();
+        }
+        let resume = debugger.prompt(core);
+        core.rebase_timing();
+        resume == debugger::Resume::Run
+    }
+
+    // What to do on an unimplemented opcode: "ask", "debug", "nop" or "stop".
+    pub fn set_on_cpu_error(&mut self, name: &str) {
+        match debugger::CpuErrorAction::from_name(name) {
+            Some(action) => self.on_cpu_error = action,
+            None => println!(
+                "Unknown CPU error option '{}', expected ask, debug, nop or stop.",
+                name
+            ),
+        }
+    }
+
+    // Report an unimplemented opcode, returns 'false' to quit.
+    fn handle_cpu_error(
+        on_cpu_error: debugger::CpuErrorAction,
+        debugger: &mut debugger::Debugger,
+        core: &mut cpu::core::Core<SegaMemory>,
+        audio_queue: &mut Option<Box<sound::SoundQueueType>>,
+        error: cpu::error::CpuError,
+    ) -> bool {
+        println!("{}", error);
+        if let Some(trace) = core.get_trace_mut() {
+            match trace.dump() {
+                Ok(()) => println!("Trace written."),
+                Err(e) => println!("Error writing trace. {}", e),
+            }
+        }
+        let action = match on_cpu_error {
+            debugger::CpuErrorAction::Ask => {
+                if let Some(audio_queue) = audio_queue.as_mut() {
+                    audio_queue.clear();
+                }
+                let action = debugger::CpuErrorAction::ask();
+                core.rebase_timing();
+                action
+            }
+            action => action,
+        };
+        match action {
+            debugger::CpuErrorAction::Debug => {
+                // The PC is after the opcode, so the debugger stops on the next one.
+                debugger.request_break();
+                true
+            }
+            debugger::CpuErrorAction::Stop => false,
+            _ => true,
+        }
+    }
+
+    // Take a snapshot every 'interval' frames, keeping up to 'buffer_mb'
+    // megabytes of them (0 disables rewind).
+    pub fn set_rewind(&mut self, interval: u64, buffer_mb: usize) {
+        self.rewind = if buffer_mb > 0 {
+            Some(rewind::RewindBuffer::new(interval, buffer_mb << 20))
+        } else {
+            None
+        };
+    }
+
+    // Current (interval, buffer_mb), the defaults if rewind is disabled.
+    pub fn get_rewind(&self) -> (u64, usize) {
+        self.rewind.as_ref().map_or(
+            (
+                rewind::RewindBuffer::DEFAULT_INTERVAL,
+                rewind::RewindBuffer::DEFAULT_SIZE_MB,
+            ),
+            |rewind| (rewind.get_interval(), rewind.get_max_bytes() >> 20),
+        )
+    }
+
+    fn set_rewinding(&mut self, rewinding: bool) {
+        if rewinding == self.rewinding {
+            return;
+        }
+        if rewinding {
+            if self.rewind.is_none() {
+                println!("Rewind is disabled.");
+                return;
+            }
+            if self.movie.is_some() {
+                println!("Rewind isn't available while recording or playing a movie.");
+                return;
+            }
+        }
+        self.rewinding = rewinding;
+
+        // Sound stops while rewinding.
+        if let Some(audio_queue) = self.audio_queue.as_mut() {
+            audio_queue.clear();
+        }
+        if !rewinding {
+            self.core.rebase_timing();
+        }
+    }
+
+    // Restore the previous snapshot and show it, stays on the oldest once reached.
+    fn rewind_step(&mut self) {
+        let snapshot =
+            self.rewind.as_mut().and_then(
+                |rewind| {
+                    if rewind.len() > 1 {
+                        rewind.pop()
+                    } else {
+                        None
+                    }
+                },
+            );
+        if let Some(snapshot) = snapshot {
+            let mut reader = snapshot::StateReader::new(&snapshot);
+            if let Err(e) = self.core.load_state(&mut reader) {
+                println!("Error while rewinding. {}", e);
+            }
+            self.show_frame();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(Sega::REWIND_FRAME_MS));
+    }
+
+    // Snapshot the machine for rewinding (before each step), once per interval.
+    fn update_rewind(
+        rewind: &mut Option<rewind::RewindBuffer>,
+        core: &cpu::core::Core<SegaMemory>,
+    ) {
+        if let Some(rewind) = rewind.as_mut() {
+            let frame_count = core.frame_count();
+            if rewind.is_snapshot_due(frame_count) {
+                let mut writer = snapshot::StateWriter::new();
+                core.save_state(&mut writer);
+                rewind.push(frame_count, writer.into_bytes());
+            }
+        }
+    }
+
+    // Speed multiplier used while the fast forward key is held.
+    pub fn set_fast_forward_speed(&mut self, speed: f64) {
+        if speed > 0.0 {
+            self.fast_forward_speed = speed;
+        } else {
+            println!("Fast forward speed must be greater than 0.");
+        }
+    }
+
+    fn update_speed(&mut self) {
+        let speed = if self.fast_forward {
+            self.fast_forward_speed
+        } else {
+            self.slow_motion_speed
+        };
+        if speed != self.core.get_speed() {
+            self.core.set_speed(speed);
+            // Sound is muted away from 1x (see 'top_up_audio'), so switch straight over.
+            if let Some(audio_queue) = self.audio_queue.as_mut() {
+                audio_queue.clear();
+            }
+        }
+    }
+
+    // Stop running the emulation (unlike the console's pause button, which
+    // the game handles).  Audio output stops while paused, rather than
+    // repeating the current tones.
+    pub fn set_emulation_paused(&mut self, paused: bool) {
+        if paused == self.emulation_paused {
+            return;
+        }
+        self.emulation_paused = paused;
+        if let Some(audio_queue) = self.audio_queue.as_mut() {
+            audio_queue.clear();
+            if paused {
+                audio_queue.pause();
+            } else {
+                audio_queue.resume();
+            }
+        }
+        if paused {
+            println!("Emulation paused.");
+        } else {
+            println!("Emulation resumed.");
+            self.core.rebase_timing();
+        }
+    }
+
+    // Run a single VDP frame (while paused), returns 'false' if the emulation stopped.
+    fn advance_frame(&mut self) -> bool {
+        let pixel_format = graphics::display::SDLUtility::PIXEL_FORMAT;
+        let console_size = Self::get_console_size();
+        let frame_count = self.core.frame_count();
+        self.core.rebase_timing();
+        while self.core.frame_count() == frame_count {
+            if !self.draw_loop(pixel_format, &console_size, 1) {
+                return false;
+            }
+        }
+        // Audio generated for the frame isn't played.
+        if let Some(audio_queue) = self.audio_queue.as_mut() {
+            audio_queue.clear();
+        }
+        true
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.core.ports.audio.set_master_volume(volume);
+    }
+
+    pub fn set_channel_muted(&mut self, channel: usize, muted: bool) {
+        self.core.ports.audio.set_channel_muted(channel, muted);
+    }
+
+    pub fn set_channel_solo(&mut self, channel: usize, solo: bool) {
+        self.core.ports.audio.set_channel_solo(channel, solo);
+    }
+
+    // Filename used by the audio recording hotkey (otherwise a timestamped name is used).
+    pub fn set_record_audio_filename(&mut self, filename: &str) {
+        self.record_audio_filename = filename.to_string();
+    }
+
+    pub fn start_audio_recording(&mut self, filename: &str) {
+        self.stop_audio_recording();
+        match wav::WavWriter::create(
+            filename,
+            sound::Sound::SAMPLERATE,
+            sound::SDLUtility::MONO_STERO_FLAG as u16,
+        ) {
+            Ok(audio_recorder) => {
+                println!("Recording audio to: {}", filename);
+                self.audio_recorder = Some(audio_recorder);
+            }
+            Err(e) => {
+                println!("Error while creating audio recording {}. {}", filename, e);
+            }
+        }
+    }
+
+    pub fn stop_audio_recording(&mut self) {
+        if let Some(mut audio_recorder) = self.audio_recorder.take() {
+            match audio_recorder.finalise() {
+                Ok(_) => println!("Audio recording stopped."),
+                Err(e) => println!("Error while finalising audio recording. {}", e),
+            }
+        }
+    }
+
+    pub fn toggle_audio_recording(&mut self) {
+        if self.audio_recorder.is_some() {
+            self.stop_audio_recording();
+        } else {
+            let filename = if self.record_audio_filename.is_empty() {
+                format!(
+                    "rustsega_{}.wav",
+                    std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|duration| duration.as_secs())
+                        .unwrap_or(0)
+                )
+            } else {
+                self.record_audio_filename.clone()
+            };
+            self.start_audio_recording(&filename);
+        }
+    }
+
+    // Also save a copy scaled by 'scale' with each screenshot (0 for none).
+    pub fn set_screenshot_scale(&mut self, scale: u32) {
+        self.screenshot_scale = scale;
+    }
+
+    // Save a screenshot once the frame count reaches 'frame'.
+    pub fn set_screenshot_at_frame(&mut self, frame: u64) {
+        self.screenshot_at_frame = Some(frame);
+    }
+
+    // Save the current display as a PNG (at 256x192), with a timestamped name.
+    pub fn save_screenshot(&mut self) {
+        // Make sure any pending frame is in the display (and shown).
+        self.show_frame();
+        let screenshot = graphics::screenshot::Screenshot::from_display(self.core.get_display());
+        let frame_count = self.core.frame_count();
+        let game_name = self.get_game_name();
+
+        let mut images = vec![(
+            graphics::screenshot::Screenshot::filename(&game_name, frame_count, ""),
+            screenshot,
+        )];
+        if self.screenshot_scale > 1 {
+            let scaled = images[0].1.scaled(self.screenshot_scale);
+            let suffix = format!("_x{}", self.screenshot_scale);
+            images.push((
+                graphics::screenshot::Screenshot::filename(&game_name, frame_count, &suffix),
+                scaled,
+            ));
+        }
+
+        for (filename, image) in images {
+            match image.save(&filename) {
+                Ok(_) => println!("Screenshot saved to: {}", filename),
+                Err(e) => println!("Error while saving screenshot {}. {}", filename, e),
+            }
+        }
+    }
+
+    // Filename used by the video recording hotkey (otherwise a timestamped name is used).
+    pub fn set_record_video_filename(&mut self, filename: &str) {
+        self.record_video_filename = filename.to_string();
+    }
+
+    pub fn start_video_recording(&mut self, filename: &str) {
+        self.stop_video_recording();
+        match video_capture::VideoRecorder::create(
+            filename,
+            self.core.frame_count(),
+            self.core.clock.cycles,
+        ) {
+            Ok(video_recorder) => {
+                println!(
+                    "Recording video to: {} (audio to: {})",
+                    filename,
+                    video_capture::VideoRecorder::audio_filename(filename)
+                );
+                self.video_recorder = Some(video_recorder);
+            }
+            Err(e) => {
+                println!("Error while creating video recording {}. {}", filename, e);
+            }
+        }
+    }
+
+    pub fn stop_video_recording(&mut self) {
+        if let Some(mut video_recorder) = self.video_recorder.take() {
+            match video_recorder.finalise() {
+                Ok(_) => println!(
+                    "Video recording stopped ({} frames).",
+                    video_recorder.frames()
+                ),
+                Err(e) => println!("Error while finalising video recording. {}", e),
+            }
+        }
+    }
+
+    pub fn toggle_video_recording(&mut self) {
+        if self.video_recorder.is_some() {
+            self.stop_video_recording();
+        } else {
+            let filename = if self.record_video_filename.is_empty() {
+                format!(
+                    "rustsega_{}.y4m",
+                    std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|duration| duration.as_secs())
+                        .unwrap_or(0)
+                )
+            } else {
+                self.record_video_filename.clone()
+            };
+            self.start_video_recording(&filename);
+        }
+    }
+
+    // Write a video frame on each v-sync.  While recording, the audio is
+    // generated per frame (rather than as the audio queue drains), so the
+    // recorded audio matches the frames.
+    fn update_video(
+        video_recorder: &mut Option<video_capture::VideoRecorder>,
+        core: &mut cpu::core::Core<SegaMemory>,
+        audio_queue: &mut Option<Box<sound::SoundQueueType>>,
+        audio_recorder: &mut Option<wav::WavFileWriter>,
+    ) {
+        if let Some(recorder) = video_recorder.as_mut() {
+            let frame_count = core.frame_count();
+            if recorder.is_frame_due(frame_count) {
+                // Make sure the frame is in the display (if not already drawn).
+                core.export();
+                let samples = recorder.samples_for(core.clock.cycles);
+                let chunk = core.ports.audio.get_next_audio_chunk(samples);
+                match recorder.write_frame(core.get_display(), frame_count, &chunk) {
+                    Ok(_) => {
+                        Sega::record_audio_chunk(audio_recorder, &chunk);
+                        // The recording has the frame's sound at any speed, only 1x is played.
+                        if let Some(audio_queue) =
+                            audio_queue.as_mut().filter(|_| core.get_speed() == 1.0)
+                        {
+                            sound::SDLUtility::queue_audio_samples(audio_queue, &chunk);
+                        }
+                    }
+                    Err(e) => {
+                        println!("Error while recording video, recording stopped. {}", e);
+                        *video_recorder = None;
+                    }
+                }
+            }
+        }
+    }
+
+    // Name of the game, taken from the cartridge filename.
+    pub fn get_game_name(&self) -> String {
+        std::path::Path::new(&self.cartridge_name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn start_vgm_recording(&mut self, filename: &str) {
+        let gd3_tag = vgm::Gd3Tag {
+            game_name: self.get_game_name(),
+            system_name: "Sega Master System".to_string(),
+            ripper: "rustsega".to_string(),
+            ..Default::default()
+        };
+        self.core.ports.audio.start_vgm_recording(
+            filename,
+            gd3_tag,
+            cpu::core::Constants::CLOCK_HZ,
+            &self.core.clock,
+        );
+    }
+
+    pub fn stop_vgm_recording(&mut self) {
+        self.core.ports.audio.stop_vgm_recording(&self.core.clock);
+    }
+
+    // Play a VGM file through the sound chip (no ROM is run), until the end of the file.
+    pub fn play_vgm(&mut self, filename: &str, looping: bool) {
+        let mut player = match vgm::VgmPlayer::load(filename) {
+            Ok(player) => player,
+            Err(e) => {
+                println!("Error while loading VGM file. {}", e);
+                return;
+            }
+        };
+        player.set_loop(looping);
+
+        let mut sdl_context = sdl2::init().unwrap();
+        let mut audio_queue = match sound::SDLUtility::get_audio_queue(&mut sdl_context) {
+            Some(audio_queue) => audio_queue,
+            None => return,
+        };
+
+        println!("Playing VGM file: {}", filename);
+        while !player.is_finished() {
+            let audio = &mut self.core.ports.audio;
+            let audio_recorder = &mut self.audio_recorder;
+            sound::SDLUtility::top_up_audio_queue(&mut audio_queue, |fill_size| {
+                let chunk = player.get_next_audio_chunk(audio, fill_size);
+                Sega::record_audio_chunk(audio_recorder, &chunk);
+                chunk
+            });
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        // Let the queued audio play out.
+        while audio_queue.size() > 0 {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+
+    fn record_audio_chunk(
+        audio_recorder: &mut Option<wav::WavFileWriter>,
+        chunk: &[soundchannel::PlaybackType],
+    ) {
+        if let Some(recorder) = audio_recorder.as_mut() {
+            if let Err(e) = recorder.write_samples(chunk) {
+                println!("Error while recording audio, recording stopped. {}", e);
+                *audio_recorder = None;
+            }
+        }
+    }
+
+    fn get_rom_crc32(&self) -> u32 {
+        match std::fs::read(&self.cartridge_name) {
+            Ok(data) => checksum::crc32(&data),
+            Err(e) => {
+                println!(
+                    "Unable to read {} for the ROM hash. {}",
+                    self.cartridge_name, e
+                );
+                0
+            }
+        }
+    }
+
+    // Movies are anchored at power on, so they need to be started before
+    // running.  Only the joypads are recorded (not the paddle or sports pad).
+    fn check_movie_start(&self) -> bool {
+        if self.core.clock.cycles != 0 {
+            println!("Movies can only be started from power on.");
+            false
+        } else if self.core.ports.joysticks.uses_mouse() {
+            println!("Movies can't be used with the paddle or sports pad.");
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn start_movie_recording(&mut self, filename: &str) {
+        if self.check_movie_start() {
+            println!("Recording movie to: {}", filename);
+            self.movie = Some(movie::MovieSession::record(
+                movie::Movie::new(self.get_rom_crc32(), movie::Anchor::PowerOn),
+                &self.core.ports.joysticks,
+            ));
+            self.movie_filename = filename.to_string();
+        }
+    }
+
+    pub fn start_movie_playback(&mut self, filename: &str) {
+        if !self.check_movie_start() {
+            return;
+        }
+        match movie::Movie::load(filename) {
+            Ok(movie) => {
+                if movie.rom_crc32 != self.get_rom_crc32() {
+                    println!("Warning: the movie was recorded with a different ROM, playback may differ.");
+                }
+                println!(
+                    "Playing movie: {} ({} frames)",
+                    filename,
+                    movie.frames.len()
+                );
+                self.movie = Some(movie::MovieSession::play(movie));
+                self.movie_filename = filename.to_string();
+            }
+            Err(e) => println!("Error while loading movie. {}", e),
+        }
+    }
+
+    // Stop the movie, saving it if recording.
+    pub fn stop_movie(&mut self) {
+        if let Some(session) = self.movie.take() {
+            if session.mode() == movie::MovieMode::Recording {
+                let movie = session.into_movie();
+                match movie.save(&self.movie_filename) {
+                    Ok(_) => println!(
+                        "Movie saved: {} ({} frames)",
+                        self.movie_filename,
+                        movie.frames.len()
+                    ),
+                    Err(e) => println!("Error while saving movie {}. {}", self.movie_filename, e),
+                }
+            }
+        }
+    }
+
+    // Update the turbo buttons and record/play back the movie input (before each step).
+    fn update_input(
+        movie: &mut Option<movie::MovieSession>,
+        core: &mut cpu::core::Core<SegaMemory>,
+    ) {
+        let frame_count = core.frame_count();
+        core.ports.joysticks.update_turbo(frame_count);
+        if let Some(session) = movie.as_mut() {
+            if !session.update(core.frame_count(), &mut core.ports.joysticks) {
+                println!("Movie playback finished.");
+                *movie = None;
+            }
+        }
+    }
+
+    // Top-up the audio queue, passing the same samples to the recorder (if recording).
+    // The sound chip is sampled as the queue drains (in realtime), so away
+    // from 1x speed the samples don't follow the music (skipping through it
+    // when fast, stretching notes when slow), and silence is queued instead.
+    fn top_up_audio(
+        audio_queue: &mut Option<Box<sound::SoundQueueType>>,
+        audio: &mut sound::Sound,
+        audio_recorder: &mut Option<wav::WavFileWriter>,
+        speed: f64,
+    ) {
+        let audio_queue = audio_queue.as_mut().expect("Optional audio not set");
+        sound::SDLUtility::top_up_audio_queue(audio_queue, |fill_size| {
+            let mut chunk = audio.get_next_audio_chunk(fill_size);
+            if speed != 1.0 {
+                chunk.fill(0);
+            }
+            Sega::record_audio_chunk(audio_recorder, &chunk);
+            chunk
+        });
+    }
+
+    // Texture for the display, sized for the filtered image.
+    fn create_display_texture<'a>(
+        texture_creator: &'a render::TextureCreator<video::WindowContext>,
+        pixel_format: pixels::PixelFormatEnum,
+        console_size: &graphics::display::ConsoleSize,
+        display_filter: &graphics::filters::DisplayFilter,
+    ) -> render::Texture<'a> {
+        let scale = display_filter.scale() as u16;
+        graphics::display::SDLUtility::create_texture(
+            texture_creator,
+            pixel_format,
+            console_size.console_width * scale,
+            console_size.console_height * scale,
+        )
+    }
+
+    // Copy the display (after an 'export') to the window.
+    fn present_frame(
+        canvas: &mut render::Canvas<video::Window>,
+        texture: &mut render::Texture,
+        core: &mut cpu::core::Core<SegaMemory>,
+        display_filter: &mut graphics::filters::DisplayFilter,
+        console_size: &graphics::display::ConsoleSize,
+    ) {
+        texture
+            .with_lock(None, |buffer: &mut [u8], pitch: usize| {
+                if display_filter.is_identity() {
+                    core.generate_display(buffer)
+                } else {
+                    display_filter.apply(
+                        core.get_display(),
+                        (
+                            console_size.console_width as u32,
+                            console_size.console_height as u32,
+                        ),
+                        graphics::display::SDLUtility::bytes_per_pixel() as usize,
+                        buffer,
+                        pitch,
+                        core.frame_count(),
+                    )
+                }
+            })
+            .unwrap();
+
+        let (x, y, width, height) = display_filter.display_rect(
+            console_size.console_width as u32,
+            console_size.console_height as u32,
+        );
+        // The overscan border is the VDP's border colour.
+        let border = core
+            .border_colour()
+            .filter(|_| display_filter.overscan)
+            .map(|colour| {
+                let mut rgb = [0; 3];
+                colour.convert_rgb24(&mut rgb);
+                pixels::Color::RGB(rgb[0], rgb[1], rgb[2])
+            });
+        canvas.set_draw_color(border.unwrap_or(pixels::Color::RGB(0, 0, 0)));
+        canvas.clear();
+        canvas
+            .copy(texture, None, Some(rect::Rect::new(x, y, width, height)))
+            .unwrap();
+        canvas.present();
+    }
+
+    // Show the current display without running the emulation (eg, after rewinding).
+    fn show_frame(&mut self) {
+        if !self.core.export() {
+            return;
+        }
+        if let Some(canvas) = self.canvas.as_mut() {
+            let console_size = Self::get_console_size();
+            let texture_creator = graphics::display::SDLUtility::texture_creator(canvas);
+            let mut texture = Sega::create_display_texture(
+                &texture_creator,
+                graphics::display::SDLUtility::PIXEL_FORMAT,
+                &console_size,
+                &self.display_filter,
+            );
+            Sega::present_frame(
+                canvas,
+                &mut texture,
+                &mut self.core,
+                &mut self.display_filter,
+                &console_size,
+            );
         }
     }
 
@@ -144,85 +1230,100 @@ impl Sega {
         // Number of iterations to do before getting a new texture.
         // These loops will update the display, but currently events aren't checked in this time.
 
-        if self.canvas.is_some() {
-            let canvas = self.canvas.as_mut().expect("Optional canvas not set");
-
-            // Creating the texture creator and texture is slow, so perform multiple display updates per creation.
-            let texture_creator = graphics::display::SDLUtility::texture_creator(canvas);
-            let mut texture;
-            texture = graphics::display::SDLUtility::create_texture(
-                &texture_creator,
+        // Creating the texture creator and texture is slow, so perform multiple display updates per creation.
+        let texture_creator = self
+            .canvas
+            .as_ref()
+            .map(graphics::display::SDLUtility::texture_creator);
+        let mut texture = texture_creator.as_ref().map(|texture_creator| {
+            Sega::create_display_texture(
+                texture_creator,
                 pixel_format,
-                console_size.console_width,
-                console_size.console_height,
-            );
+                console_size,
+                &self.display_filter,
+            )
+        });
 
-            let mut audio_steps = 0;
-            let mut display_refreshes = 0;
-            while display_refreshes < iterations {
-                if self.stop_clock > 0 && self.core.clock.cycles > self.stop_clock {
-                    return false;
-                }
-                self.core.step(self.debug, self.realtime);
+        let mut audio_steps = 0;
+        let mut display_refreshes = 0;
+        while display_refreshes < iterations {
+            if !self.run_step(
+                texture.as_mut(),
+                console_size,
+                &mut audio_steps,
+                &mut display_refreshes,
+            ) {
+                return false;
+            }
+        }
+        true
+    }
 
-                if 0 == audio_steps % Sega::CPU_STEPS_PER_AUDIO_UPDATE {
-                    // Top-up the audio queue
-                    let audio_queue = self.audio_queue.as_mut().expect("Optional audio not set");
-                    sound::SDLUtility::top_up_audio_queue(audio_queue, |fill_size| {
-                        self.core.ports.audio.get_next_audio_chunk(fill_size)
-                    });
-                }
-                audio_steps += 1;
+    // Run one CPU step (with the input, rewind, debugger, audio and video
+    // updates around it), returns false to quit.  Without a display each
+    // step counts as a refresh.
+    fn run_step(
+        &mut self,
+        texture: Option<&mut render::Texture>,
+        console_size: &graphics::display::ConsoleSize,
+        audio_steps: &mut u32,
+        display_refreshes: &mut u32,
+    ) -> bool {
+        if self.stop_clock > 0 && self.core.clock.cycles > self.stop_clock {
+            return false;
+        }
+        Sega::update_input(&mut self.movie, &mut self.core);
+        Sega::update_rewind(&mut self.rewind, &self.core);
+        if !Sega::update_debugger(&mut self.debugger, &mut self.core, &mut self.audio_queue) {
+            return false;
+        }
+        if let Err(error) = self.core.step(self.debug, self.realtime) {
+            if !Sega::handle_cpu_error(
+                self.on_cpu_error,
+                &mut self.debugger,
+                &mut self.core,
+                &mut self.audio_queue,
+                error,
+            ) {
+                return false;
+            }
+        }
+
+        // While recording video, the audio is generated per frame (see 'update_video').
+        if self.video_recorder.is_none() && 0 == *audio_steps % Sega::CPU_STEPS_PER_AUDIO_UPDATE {
+            let speed = self.core.get_speed();
+            Sega::top_up_audio(
+                &mut self.audio_queue,
+                &mut self.core.ports.audio,
+                &mut self.audio_recorder,
+                speed,
+            );
+        }
+        *audio_steps += 1;
 
+        match (self.canvas.as_mut(), texture) {
+            (Some(canvas), Some(texture)) => {
                 // If an 'export' occurred (buffer was draw), then update the texture.
                 if self.core.export() {
-                    texture
-                        .with_lock(None, |buffer: &mut [u8], _pitch: usize| {
-                            self.core.generate_display(buffer)
-                        })
-                        .unwrap();
-
-                    canvas.clear();
-                    canvas
-                        .copy(
-                            &texture,
-                            None,
-                            Some(rect::Rect::new(
-                                0,
-                                0,
-                                console_size.console_width as u32,
-                                console_size.console_height as u32,
-                            )),
-                        )
-                        .unwrap();
-                    canvas.present();
-
-                    display_refreshes += 1;
+                    Sega::present_frame(
+                        canvas,
+                        texture,
+                        &mut self.core,
+                        &mut self.display_filter,
+                        console_size,
+                    );
+                    *display_refreshes += 1;
                 }
             }
-            true
-        } else {
-            let mut audio_steps = 0;
-            let mut display_refreshes = 0;
-            while display_refreshes < iterations {
-                if self.stop_clock > 0 && self.core.clock.cycles > self.stop_clock {
-                    return false;
-                }
-                self.core.step(self.debug, self.realtime);
-
-                if 0 == audio_steps % Sega::CPU_STEPS_PER_AUDIO_UPDATE {
-                    // Top-up the audio queue
-                    let audio_queue = self.audio_queue.as_mut().expect("Optional audio not set");
-                    sound::SDLUtility::top_up_audio_queue(audio_queue, |fill_size| {
-                        self.core.ports.audio.get_next_audio_chunk(fill_size)
-                    });
-                }
-                audio_steps += 1;
-
-                display_refreshes += 1;
-            }
-            true
+            _ => *display_refreshes += 1,
         }
+        Sega::update_video(
+            &mut self.video_recorder,
+            &mut self.core,
+            &mut self.audio_queue,
+            &mut self.audio_recorder,
+        );
+        true
     }
 
     pub fn configure_sdl(
@@ -240,15 +1341,20 @@ impl Sega {
             window_size.fullscreen,
         );
 
+        let (display_width, display_height) = self.get_display_size();
         if let Some(ref mut v) = self.canvas {
-            v.set_logical_size(
-                window_size.console_size.console_width as u32,
-                window_size.console_size.console_height as u32,
-            )
-            .unwrap();
+            v.set_logical_size(display_width, display_height).unwrap();
+            v.set_integer_scale(self.display_filter.integer_scale)
+                .unwrap();
         }
 
         self.audio_queue = sound::SDLUtility::get_audio_queue(&mut sdl_context);
+        self.controllers.open(&sdl_context);
+
+        // The paddle and sports pad are driven by (relative) mouse movement.
+        if self.core.ports.joysticks.uses_mouse() {
+            sdl_context.mouse().set_relative_mouse_mode(true);
+        }
         self.sdl_context = Some(sdl_context);
     }
 }
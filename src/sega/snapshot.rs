@@ -0,0 +1,127 @@
+// Machine state snapshots, used by the rewind buffer.
+// Each component writes its state in a fixed order to a byte stream, and
+// reads it back in the same order.  State that can be rebuilt (pattern
+// caches, display buffers) isn't saved.
+pub trait Snapshot {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String>;
+}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position == self.data.len()
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self.position + length;
+        if end > self.data.len() {
+            return Err("Snapshot is truncated.".to_string());
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    // Copy the next 'destination.len()' bytes.
+    pub fn read_into(&mut self, destination: &mut [u8]) -> Result<(), String> {
+        destination.copy_from_slice(self.read_bytes(destination.len())?);
+        Ok(())
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::snapshot;
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut writer = snapshot::StateWriter::new();
+        writer.write_u8(0x12);
+        writer.write_bool(true);
+        writer.write_u16(0x3456);
+        writer.write_u32(0x789A_BCDE);
+        writer.write_u64(0x0102_0304_0506_0708);
+        writer.write_bytes(&[1, 2, 3]);
+        let data = writer.into_bytes();
+        assert_eq!(data.len(), 1 + 1 + 2 + 4 + 8 + 3);
+
+        let mut reader = snapshot::StateReader::new(&data);
+        assert_eq!(reader.read_u8(), Ok(0x12));
+        assert_eq!(reader.read_bool(), Ok(true));
+        assert_eq!(reader.read_u16(), Ok(0x3456));
+        assert_eq!(reader.read_u32(), Ok(0x789A_BCDE));
+        assert_eq!(reader.read_u64(), Ok(0x0102_0304_0506_0708));
+        let mut bytes = [0; 3];
+        assert!(reader.read_into(&mut bytes).is_ok());
+        assert_eq!(bytes, [1, 2, 3]);
+        assert!(reader.is_finished());
+        assert!(reader.read_u8().is_err());
+    }
+}
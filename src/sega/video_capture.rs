@@ -0,0 +1,141 @@
+use super::audio::sound;
+use super::audio::soundchannel;
+use super::audio::wav;
+use super::cpu;
+use super::graphics::vdp;
+use super::graphics::y4m;
+use std::io;
+
+// Records the exported frames to a Y4M file, with the audio for each frame
+// to a WAV file alongside it (same name, '.wav' extension).
+// A frame is written on each v-sync (frame count change), and the audio
+// generated for it covers the emulated time since the previous frame, so the
+// audio stays in sync however fast the emulation runs.
+pub struct VideoRecorder {
+    video: y4m::Y4mFileWriter,
+    audio: wav::WavFileWriter,
+    last_frame: u64,
+    last_cycles: u64,
+    sample_remainder: u64, // Fraction of a sample carried to the next frame (in 'samples * CLOCK_HZ').
+}
+
+impl VideoRecorder {
+    pub fn create(filename: &str, frame_count: u64, cycles: u64) -> io::Result<Self> {
+        let video = y4m::Y4mWriter::create(
+            filename,
+            vdp::Constants::SMS_WIDTH as u32,
+            vdp::Constants::SMS_HEIGHT as u32,
+            (
+                cpu::core::Constants::CLOCK_HZ,
+                vdp::Constants::VSYNCCYCLETIME as u32,
+            ),
+        )?;
+        let audio = wav::WavWriter::create(
+            &VideoRecorder::audio_filename(filename),
+            sound::Sound::SAMPLERATE,
+            sound::SDLUtility::MONO_STERO_FLAG as u16,
+        )?;
+        Ok(Self {
+            video,
+            audio,
+            last_frame: frame_count,
+            last_cycles: cycles,
+            sample_remainder: 0,
+        })
+    }
+
+    pub fn audio_filename(filename: &str) -> String {
+        std::path::Path::new(filename)
+            .with_extension("wav")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    pub fn is_frame_due(&self, frame_count: u64) -> bool {
+        frame_count != self.last_frame
+    }
+
+    // Number of (mono) audio samples for the frame, covering the emulated time
+    // up to 'cycles'.
+    pub fn samples_for(&mut self, cycles: u64) -> u32 {
+        // Time went backwards (rewind/reset), assume a single frame passed.
+        let elapsed = if cycles >= self.last_cycles {
+            cycles - self.last_cycles
+        } else {
+            vdp::Constants::VSYNCCYCLETIME as u64
+        };
+        self.last_cycles = cycles;
+
+        let total = elapsed * sound::Sound::SAMPLERATE as u64 + self.sample_remainder;
+        self.sample_remainder = total % cpu::core::Constants::CLOCK_HZ as u64;
+        (total / cpu::core::Constants::CLOCK_HZ as u64) as u32
+    }
+
+    // Write the frame, along with its audio (see 'samples_for').
+    pub fn write_frame(
+        &mut self,
+        raw_display: &[u8],
+        frame_count: u64,
+        audio: &[soundchannel::PlaybackType],
+    ) -> io::Result<()> {
+        self.last_frame = frame_count;
+        self.video.write_frame(raw_display)?;
+        self.audio.write_samples(audio)
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.video.frames()
+    }
+
+    pub fn finalise(&mut self) -> io::Result<()> {
+        self.video.flush()?;
+        self.audio.finalise()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::audio::sound;
+    use crate::sega::cpu;
+    use crate::sega::graphics::vdp;
+    use crate::sega::video_capture;
+
+    #[test]
+    fn test_audio_filename() {
+        assert_eq!(
+            video_capture::VideoRecorder::audio_filename("capture.y4m"),
+            "capture.wav"
+        );
+        assert_eq!(
+            video_capture::VideoRecorder::audio_filename("capture"),
+            "capture.wav"
+        );
+    }
+
+    #[test]
+    fn test_samples_per_frame() {
+        let filename = std::env::temp_dir().join("rustsega_test_video.y4m");
+        let filename = filename.to_string_lossy().to_string();
+        let mut recorder = video_capture::VideoRecorder::create(&filename, 0, 0).unwrap();
+
+        // Over many frames the audio matches the emulated time exactly.
+        let frames = 1000;
+        let frame_cycles = vdp::Constants::VSYNCCYCLETIME as u64;
+        let total: u64 = (1..=frames)
+            .map(|frame| recorder.samples_for(frame * frame_cycles) as u64)
+            .sum();
+        assert_eq!(
+            total,
+            frames * frame_cycles * sound::Sound::SAMPLERATE as u64
+                / cpu::core::Constants::CLOCK_HZ as u64
+        );
+
+        // Going backwards counts as one frame.
+        assert!((801..=802).contains(&recorder.samples_for(0)));
+
+        recorder.finalise().unwrap();
+        std::fs::remove_file(&filename).unwrap();
+        std::fs::remove_file(video_capture::VideoRecorder::audio_filename(&filename)).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+// Memory and I/O port watches (for the debugger), checked on each access.
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite, // Only used by watches.
+}
+
+impl Access {
+    pub fn from_name(name: &str) -> Option<Access> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "r" | "read" => Some(Access::Read),
+            "w" | "write" => Some(Access::Write),
+            "rw" | "readwrite" => Some(Access::ReadWrite),
+            _ => None,
+        }
+    }
+
+    fn includes(&self, access: Access) -> bool {
+        *self == Access::ReadWrite || *self == access
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Target {
+    Memory,
+    Port,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Watch {
+    pub access: Access,
+    pub start: u16,
+    pub end: u16,          // Inclusive.
+    pub value: Option<u8>, // Only stop for this value.
+}
+
+impl Watch {
+    pub fn new(access: Access, start: u16, end: u16, value: Option<u8>) -> Self {
+        Self {
+            access,
+            start,
+            end,
+            value,
+        }
+    }
+
+    fn matches(&self, access: Access, address: u16, value: u8) -> bool {
+        self.access.includes(access)
+            && (self.start..=self.end).contains(&address)
+            && self.value.is_none_or(|watch_value| watch_value == value)
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::ReadWrite => "read/write",
+        };
+        write!(f, "{} {:04X}", access, self.start)?;
+        if self.end != self.start {
+            write!(f, "-{:04X}", self.end)?;
+        }
+        if let Some(value) = self.value {
+            write!(f, " = {:02X}", value)?;
+        }
+        Ok(())
+    }
+}
+
+// The access that triggered a watch.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WatchHit {
+    pub target: Target,
+    pub access: Access,
+    pub address: u16,
+    pub value: u8,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let target = match self.target {
+            Target::Memory => "memory",
+            Target::Port => "port",
+        };
+        match self.access {
+            Access::Read => write!(
+                f,
+                "{} read of {:02X} from {:04X}",
+                target, self.value, self.address
+            ),
+            _ => write!(
+                f,
+                "{} write of {:02X} to {:04X}",
+                target, self.value, self.address
+            ),
+        }
+    }
+}
+
+pub struct WatchList {
+    target: Target,
+    watches: Vec<Watch>,
+}
+
+impl WatchList {
+    pub fn new(target: Target) -> Self {
+        Self {
+            target,
+            watches: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, watch: Watch) {
+        self.watches.push(watch);
+    }
+
+    pub fn clear(&mut self) {
+        self.watches.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    pub fn get_watches(&self) -> &[Watch] {
+        &self.watches
+    }
+
+    pub fn check(&self, access: Access, address: u16, value: u8) -> Option<WatchHit> {
+        self.watches
+            .iter()
+            .any(|watch| watch.matches(access, address, value))
+            .then_some(WatchHit {
+                target: self.target,
+                access,
+                address,
+                value,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sega::watch;
+
+    #[test]
+    fn test_watch_list() {
+        let mut watches = watch::WatchList::new(watch::Target::Memory);
+        assert!(watches.is_empty());
+        watches.add(watch::Watch::new(
+            watch::Access::Write,
+            0xFFFF,
+            0xFFFF,
+            Some(0x05),
+        ));
+        watches.add(watch::Watch::new(
+            watch::Access::ReadWrite,
+            0xC000,
+            0xC0FF,
+            None,
+        ));
+
+        assert!(watches.check(watch::Access::Write, 0xFFFF, 0x04).is_none());
+        assert!(watches.check(watch::Access::Read, 0xFFFF, 0x05).is_none());
+        let hit = watches.check(watch::Access::Write, 0xFFFF, 0x05).unwrap();
+        assert_eq!(hit.to_string(), "memory write of 05 to FFFF");
+        assert!(watches.check(watch::Access::Read, 0xC080, 0x12).is_some());
+        assert!(watches.check(watch::Access::Read, 0xC100, 0x12).is_none());
+        assert_eq!(watches.get_watches()[0].to_string(), "write FFFF = 05");
+        assert_eq!(watches.get_watches()[1].to_string(), "read/write C000-C0FF");
+    }
+}